@@ -1,16 +1,19 @@
 use clap::{Parser, ValueEnum};
 use teanga::DiskCorpus;
 use std::fs::File;
-use flate2;
-use std::io::{BufReader, BufRead};
+use std::io::BufRead;
+use std::path::Path;
 use teanga::Corpus;
 use teanga::TCFConfig;
+use teanga::CountingWriter;
 use teanga::Document;
 use teanga::TeangaError;
-use teanga::read_yaml_meta;
-use teanga::read_json;
+use teanga::read_yaml_meta_with_mode;
+use teanga::read_json_with_mode;
 use teanga::read_jsonl;
-use teanga::read_yaml;
+use teanga::read_yaml_with_mode;
+use teanga::LoadMode;
+use teanga::open_maybe_compressed;
 
 // for CBOR conversion
 use std::io::BufWriter;
@@ -45,7 +48,14 @@ struct LoadCommand {
 
     /// Read the file as JSONL (one JSON object per line)
     #[arg(long)]
-    jsonl: bool
+    jsonl: bool,
+
+    /// Remove the documents already in the corpus and replace its metadata.
+    /// By default the documents are added after the existing documents,
+    /// skipping any that are already in the corpus, and the metadata is
+    /// merged with the existing metadata
+    #[arg(long)]
+    replace: bool
 }
 
 #[derive(ValueEnum, Debug, Clone, PartialEq, Eq)]
@@ -63,7 +73,11 @@ enum Format {
 enum StringCompression {
     Smaz,
     Shoco,
-    Generate
+    Generate,
+    Auto,
+    Zstd,
+    #[value(name = "zstd-dict")]
+    ZstdDict
 }
 
 impl Format {
@@ -111,12 +125,15 @@ struct ConvertCommand {
     meta_file: Option<String>,
 
     /// The string compression method (for TCF output only). It is best to use
-    /// `smaz` for English corpora and `generate` for other languages.
+    /// `smaz` for English corpora and `generate` for other languages, or
+    /// `auto` to choose based on a sample of the corpus. `zstd-dict` uses
+    /// Zstandard with a dictionary trained on the corpus, which suits large
+    /// corpora in any language.
     #[arg(long)]
     #[clap(default_value="smaz")]
     compression: StringCompression,
 
-    /// The number of bytes to use for generate string compression (for TCF output only, only used if compression is set to generate)
+    /// The number of bytes to use for generate string compression (for TCF output only, only used if compression is set to generate, auto or zstd-dict)
     #[arg(long)]
     #[clap(default_value="1000000")]
     compression_bytes: usize
@@ -126,28 +143,31 @@ impl LoadCommand {
     fn run(&self) -> Result<(), String> {
         let mut corpus = DiskCorpus::new(&self.db)
             .map_err(|e| format!("Failed to open corpus: {}", e))?;
+        let mode = if self.replace { LoadMode::Replace } else { LoadMode::Append };
         if let Some(meta) = &self.meta {
-            read_yaml_meta(File::open(meta)
+            read_yaml_meta_with_mode(File::open(meta)
                 .map_err(|e| format!("Failed to open meta file: {}", e))?,
-                &mut corpus)
+                &mut corpus, mode)
                 .map_err(|e| format!("Failed to read meta file: {}", e))?;
         }
-        let mut file = if self.file.ends_with(".gz") {
-            let reader = flate2::read::GzDecoder::new(File::open(&self.file)
-                .map_err(|e| format!("Failed to open file: {}", e))?);
-            Box::new(reader) as Box<dyn std::io::Read>
-        } else {
-            Box::new(File::open(&self.file)
-                .map_err(|e| format!("Failed to open file: {}", e))?) as Box<dyn std::io::Read>
-        };
+        let mut file = open_maybe_compressed(Path::new(&self.file))
+            .map_err(|e| format!("Failed to open file: {}", e))?;
         if self.jsonl {
-            read_jsonl(&mut BufReader::new(file), &mut corpus)
+            // JSONL is read line by line, so there is no load mode and the
+            // documents must be removed first
+            if self.replace {
+                for id in corpus.get_docs() {
+                    corpus.remove_doc(&id)
+                        .map_err(|e| format!("Failed to remove document: {}", e))?;
+                }
+            }
+            read_jsonl(&mut file, &mut corpus)
                 .map_err(|e| format!("Failed to read file: {}", e))?;
         } else if self.file.ends_with(".json") || self.file.ends_with(".json.gz") {
-            read_json(&mut file, &mut corpus)
+            read_json_with_mode(&mut file, &mut corpus, mode)
                 .map_err(|e| format!("Failed to read file: {}", e))?;
         } else {
-            read_yaml(&mut file, &mut corpus)
+            read_yaml_with_mode(&mut file, &mut corpus, mode)
                 .map_err(|e| format!("Failed to read file: {}", e))?;
         }
         Ok(())
@@ -156,14 +176,8 @@ impl LoadCommand {
 
 impl ConvertCommand {
     fn run(&self) -> Result<(), String> {
-        let mut input = if self.input.ends_with(".gz") {
-            let reader = BufReader::new(flate2::read::GzDecoder::new(File::open(&self.input)
-                .map_err(|e| format!("Failed to open input file: {}", e))?));
-            Box::new(reader) as Box<dyn std::io::BufRead>
-        } else {
-            Box::new(BufReader::new(File::open(&self.input)
-                .map_err(|e| format!("Failed to open input file: {}", e))?)) as Box<dyn std::io::BufRead>
-        };
+        let mut input = open_maybe_compressed(Path::new(&self.input))
+            .map_err(|e| format!("Failed to open input file: {}", e))?;
         let mut output = BufWriter::new(File::create(&self.output)
             .map_err(|e| format!("Failed to create output file: {}", e))?);
         let mut corpus = teanga::SimpleCorpus::new();
@@ -221,9 +235,15 @@ impl ConvertCommand {
                     StringCompression::Smaz => TCFConfig::new().with_string_compression(teanga::StringCompressionMethod::Smaz),
                     StringCompression::Shoco => TCFConfig::new().with_string_compression(teanga::StringCompressionMethod::ShocoDefault),
                     StringCompression::Generate => TCFConfig::new().with_string_compression(teanga::StringCompressionMethod::GenerateShocoModel(self.compression_bytes)),
+                    StringCompression::Auto => TCFConfig::new().with_string_compression(teanga::StringCompressionMethod::Auto(self.compression_bytes)),
+                    StringCompression::Zstd => TCFConfig::new().with_string_compression(teanga::StringCompressionMethod::Zstd),
+                    StringCompression::ZstdDict => TCFConfig::new().with_string_compression(teanga::StringCompressionMethod::GenerateZstdDictionary(self.compression_bytes)),
                 };
                 if progressive {
-                    let (mut cache, _) = teanga::write_tcf_header(&mut output, corpus.get_meta())
+                    // The bytes are counted to record the offset of each
+                    // document for the document index
+                    let mut output = CountingWriter::new(&mut output);
+                    let (mut cache, _) = teanga::write_tcf_header_with_config(&mut output, corpus.get_meta(), &config)
                         .map_err(|e| format!("Failed to write TCF: {}", e))?;
                     let replay = std::cell::RefCell::new(Vec::new());
                     let do_replay = std::cell::RefCell::new(true);
@@ -240,17 +260,30 @@ impl ConvertCommand {
                         }));
                     let compressor = teanga::write_tcf_config(&mut output, &mut iter, &config)
                         .map_err(|e| format!("Failed to write TCF: {}", e))?;
+                    let mut offsets : Vec<(String, u64)> = Vec::new();
+                    let mut order : Vec<String> = Vec::new();
                     let replay = replay.clone();
                     for doc in replay.borrow().iter() {
+                        let id = teanga::teanga_id(&order, doc);
+                        order.push(id.clone());
+                        offsets.push((id, output.count()));
                         teanga::write_tcf_doc(&mut output, doc.clone(), &mut cache, corpus.get_meta(), &compressor)
                             .map_err(|e| format!("Failed to write TCF: {}", e))?;
                     }
                     *do_replay.borrow_mut() = false;
                     for doc in iter {
                         let doc = doc.map_err(|e| format!("Failed to read document: {}", e))?;
+                        let id = teanga::teanga_id(&order, &doc);
+                        order.push(id.clone());
+                        offsets.push((id, output.count()));
                         teanga::write_tcf_doc(&mut output, doc, &mut cache, corpus.get_meta(), &compressor)
                             .map_err(|e| format!("Failed to write TCF: {}", e))?;
                     }
+                    if config.doc_index {
+                        let position = output.count();
+                        teanga::write_tcf_doc_index(&mut output, &offsets, &cache, position)
+                            .map_err(|e| format!("Failed to write TCF: {}", e))?;
+                    }
                 } else {
                     teanga::write_tcf_with_config(&mut output, &corpus, &config)
                         .map_err(|e| format!("Failed to write TCF: {}", e))?;
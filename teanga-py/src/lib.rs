@@ -183,7 +183,7 @@ impl PyLayerDesc {
 /// Any valid JSON/YAML value
 pub enum PyValue {
     Bool(bool),
-    Int(i32),
+    Int(i64),
     Float(f64),
     String(String),
     Array(Vec<PyValue>),
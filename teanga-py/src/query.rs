@@ -66,6 +66,8 @@ fn convert_query(query : HashMap<String, QueryValue>) -> PyResult<Query> {
     for (layer, value) in query {
         if layer == "$exists" {
             queries.push(Query::Exists(value.to_string()?));
+        } else if layer == "$not_exists" {
+            queries.push(Query::NotExists(value.to_string()?));
         } else {
             match value {
                 QueryValue::Vec(v) => {
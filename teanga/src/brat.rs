@@ -0,0 +1,438 @@
+//! Support for the brat standoff format
+//!
+//! [brat](https://brat.nlplab.org/) stores each document as a pair of files:
+//! a `.txt` file with the text and an `.ann` file with the annotations. The
+//! offsets in the `.ann` file are character offsets, whereas Teanga indexes
+//! the bytes of the characters layer, so offsets are converted when reading
+//! and writing.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use itertools::Itertools;
+use thiserror::Error;
+use crate::{Corpus, DataType, Document, Layer, LayerDesc, LayerType, TeangaData, TeangaError};
+
+/// Selects which layers of a corpus are mapped to brat
+#[derive(Debug, Clone, PartialEq)]
+pub struct BratMapping {
+    /// The characters layer that is written to the `.txt` file
+    pub text_layer: String,
+    /// The span layers that are written as entities. If the layer has
+    /// string data this is used as the entity type, otherwise the name of
+    /// the layer is used. When reading, an entity type that is the name of
+    /// a listed layer without string data is read into that layer, and the
+    /// other entity types are read into the first listed layer that has
+    /// string data in the corpus, with the type as the value. If this is
+    /// empty, each entity type is read into a layer of the same name
+    pub entity_layers: Vec<String>,
+    /// The link layers that are written as relations. Each annotation of
+    /// the layer is a relation from an entity of its base layer to an
+    /// entity of its target layer, which must both be entity layers. The
+    /// link type is used as the relation type, or the name of the layer if
+    /// the link has no type. Relations are not read
+    pub relation_layers: Vec<String>
+}
+
+impl BratMapping {
+    /// Create a mapping with no entity layers
+    ///
+    /// # Arguments
+    ///
+    /// * `text_layer` - The characters layer
+    pub fn new(text_layer : &str) -> BratMapping {
+        BratMapping {
+            text_layer: text_layer.to_string(),
+            entity_layers: Vec::new(),
+            relation_layers: Vec::new()
+        }
+    }
+
+    /// Add a span layer to be written as entities
+    ///
+    /// # Arguments
+    ///
+    /// * `layer` - The name of the layer
+    pub fn entity_layer(mut self, layer : &str) -> BratMapping {
+        self.entity_layers.push(layer.to_string());
+        self
+    }
+
+    /// Add a link layer to be written as relations
+    ///
+    /// # Arguments
+    ///
+    /// * `layer` - The name of the layer
+    pub fn relation_layer(mut self, layer : &str) -> BratMapping {
+        self.relation_layers.push(layer.to_string());
+        self
+    }
+}
+
+/// An error reading or writing brat files
+#[derive(Error, Debug)]
+pub enum BratError {
+    #[error("IO error: {0}")]
+    IOError(#[from] std::io::Error),
+    #[error("Teanga error: {0}")]
+    TeangaError(#[from] TeangaError),
+    #[error("Invalid brat annotation: {0}")]
+    FormatError(String)
+}
+
+/// Write a corpus as brat standoff files
+///
+/// For each document a `<id>.txt` and `<id>.ann` file is written to the
+/// directory. As document IDs may contain `/` and `+`, these are replaced
+/// by `_` and `-` in the file names.
+///
+/// # Arguments
+///
+/// * `dir` - The directory to write the files to
+/// * `corpus` - The corpus to write
+/// * `mapping` - The layers to write
+pub fn write_brat<P : AsRef<Path>, C : Corpus>(dir : P, corpus : &C,
+    mapping : &BratMapping) -> Result<(), BratError> {
+    let dir = dir.as_ref();
+    std::fs::create_dir_all(dir)?;
+    let meta = corpus.get_meta();
+    for doc in corpus.iter_doc_ids() {
+        let (doc_id, doc) = doc?;
+        let text = doc.get(&mapping.text_layer)
+            .and_then(|l| l.characters())
+            .ok_or_else(|| TeangaError::LayerNotFoundError(mapping.text_layer.clone()))?;
+        let name = brat_file_name(&doc_id);
+        File::create(dir.join(format!("{}.txt", name)))?.write_all(text.as_bytes())?;
+        write_brat_ann(File::create(dir.join(format!("{}.ann", name)))?,
+            &doc, meta, mapping)?;
+    }
+    Ok(())
+}
+
+/// Write the annotations of a document as a brat `.ann` file. Each span of
+/// the entity layers is written as a text-bound annotation (`T`) and each
+/// annotation of the relation layers as a relation (`R`). Spans that contain
+/// a newline are written as several fragments, as brat does not allow an
+/// annotation to cross a line
+///
+/// # Arguments
+///
+/// * `writer` - The writer to write to
+/// * `doc` - The document
+/// * `meta` - The metadata of the corpus
+/// * `mapping` - The layers to write
+pub fn write_brat_ann<W : Write>(mut writer : W, doc : &Document,
+    meta : &HashMap<String, LayerDesc>, mapping : &BratMapping) -> Result<(), BratError> {
+    let text = doc.get(&mapping.text_layer)
+        .and_then(|l| l.characters())
+        .ok_or_else(|| TeangaError::LayerNotFoundError(mapping.text_layer.clone()))?;
+    // The number of the text-bound annotation of each span
+    let mut entities : HashMap<(&str, usize), usize> = HashMap::new();
+    let mut n = 1;
+    for layer in mapping.entity_layers.iter() {
+        if doc.get(layer).is_none() {
+            continue;
+        }
+        for (i, (start, end, data)) in doc.indexes_data(layer, &mapping.text_layer, meta)?
+            .into_iter().enumerate() {
+            let entity_type = match data {
+                TeangaData::String(s) => s,
+                _ => layer.clone()
+            };
+            let fragments = line_fragments(text, start, end);
+            writeln!(writer, "T{}\t{} {}\t{}", n, entity_type,
+                fragments.iter().map(|(s, e)| format!("{} {}",
+                    byte_to_char(text, *s), byte_to_char(text, *e))).join(";"),
+                fragments.iter().map(|(s, e)| &text[*s..*e]).join(" "))?;
+            entities.insert((layer.as_str(), i), n);
+            n += 1;
+        }
+    }
+    let mut r = 1;
+    for layer in mapping.relation_layers.iter() {
+        let layer_desc = meta.get(layer)
+            .ok_or_else(|| TeangaError::LayerNotFoundError(layer.clone()))?;
+        let (Some(base), Some(target)) = (layer_desc.base.as_deref(), layer_desc.link_target()) else {
+            return Err(BratError::FormatError(
+                format!("Relation layer {} does not have links", layer)));
+        };
+        if !mapping.entity_layers.iter().any(|l| l == base) ||
+            !mapping.entity_layers.iter().any(|l| l == target) {
+            return Err(BratError::FormatError(
+                format!("Relation layer {} must link two entity layers", layer)));
+        }
+        let Some(l) = doc.get(layer) else {
+            continue;
+        };
+        for (start, _, data) in l.indexes_data(layer, base, doc, meta)? {
+            let (link, relation_type) = match data {
+                TeangaData::Link(j) => (j, layer.clone()),
+                TeangaData::TypedLink(j, t) => (j, t),
+                _ => continue
+            };
+            let arg = |entity_layer : &str, i : usize| entities.get(&(entity_layer, i)).copied().ok_or_else(||
+                BratError::FormatError(format!("Relation layer {} refers to a missing entity", layer)));
+            writeln!(writer, "R{}\t{} Arg1:T{} Arg2:T{}", r, relation_type,
+                arg(base, start)?, arg(target, link as usize)?)?;
+            r += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Read a corpus from brat standoff files
+///
+/// Each `.txt` file in the directory (in order of file name) is read as a
+/// document, together with the `.ann` file of the same name if it exists.
+/// The entity types are read into span layers based on the text layer as
+/// described in `BratMapping::entity_layers`. A layer that should keep the
+/// entity types as labels must be declared with string data before
+/// reading. Only text-bound annotations are read, other annotations
+/// (relations, events, attributes and notes) are ignored. An annotation
+/// with several fragments is read as one span if the fragments are only
+/// separated by line breaks, as written by `write_brat`, and is an error
+/// otherwise.
+///
+/// # Arguments
+///
+/// * `dir` - The directory to read the files from
+/// * `corpus` - The corpus to read into
+/// * `mapping` - The layers to read
+///
+/// # Returns
+///
+/// The IDs of the documents that were added
+pub fn read_brat<P : AsRef<Path>, C : Corpus>(dir : P, corpus : &mut C,
+    mapping : &BratMapping) -> Result<Vec<String>, BratError> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "txt") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    if !corpus.get_meta().contains_key(&mapping.text_layer) {
+        corpus.build_layer(&mapping.text_layer).add()?;
+    }
+    // The layer that the entity types are read into as labels
+    let labelled = mapping.entity_layers.iter()
+        .find(|l| corpus.get_meta().get(*l).is_some_and(|d| d.data == Some(DataType::String)))
+        .cloned();
+    let mut ids = Vec::new();
+    for path in files {
+        let text = std::fs::read_to_string(&path)?;
+        let mut spans : HashMap<String, Vec<(u32, u32)>> = HashMap::new();
+        let mut labels : Vec<(u32, u32, String)> = Vec::new();
+        let ann_path = path.with_extension("ann");
+        if ann_path.exists() {
+            for line in std::fs::read_to_string(&ann_path)?.lines() {
+                if !line.starts_with('T') {
+                    continue;
+                }
+                let (entity_type, start, end) = parse_text_bound(line, &text)?;
+                if mapping.entity_layers.is_empty() ||
+                    (mapping.entity_layers.contains(&entity_type) && labelled.as_ref() != Some(&entity_type)) {
+                    spans.entry(entity_type).or_default().push((start, end));
+                } else if labelled.is_some() {
+                    labels.push((start, end, entity_type));
+                }
+            }
+        }
+        let mut content = HashMap::new();
+        content.insert(mapping.text_layer.clone(), Layer::Characters(text));
+        for (layer, mut s) in spans {
+            if !corpus.get_meta().contains_key(&layer) {
+                corpus.build_layer(&layer)
+                    .base(&mapping.text_layer)
+                    .layer_type(LayerType::span)
+                    .add()?;
+            }
+            s.sort();
+            content.insert(layer, Layer::L2(s));
+        }
+        if let Some(ref layer) = labelled {
+            if !labels.is_empty() {
+                labels.sort();
+                content.insert(layer.clone(), Layer::L2S(labels));
+            }
+        }
+        ids.push(corpus.add_doc(content)?);
+    }
+    Ok(ids)
+}
+
+// Parse a line such as `T1\tLOC 0 4;5 9\tÉire is` into the entity type and
+// the byte offsets. Fragments must be in order and only separated by line
+// breaks, so that they can be read as a single span
+fn parse_text_bound(line : &str, text : &str) -> Result<(String, u32, u32), BratError> {
+    let err = || BratError::FormatError(line.to_string());
+    let mut fields = line.split('\t');
+    fields.next();
+    let mut annotation = fields.next().ok_or_else(err)?.splitn(2, ' ');
+    let entity_type = annotation.next().ok_or_else(err)?.to_string();
+    let offsets = annotation.next().ok_or_else(err)?;
+    let mut span : Option<(usize, usize)> = None;
+    for fragment in offsets.split(';') {
+        let (s, e) = fragment.split_once(' ').ok_or_else(err)?;
+        let s = char_to_byte(text, s.trim().parse().map_err(|_| err())?).ok_or_else(err)?;
+        let e = char_to_byte(text, e.trim().parse().map_err(|_| err())?).ok_or_else(err)?;
+        if s > e {
+            return Err(err());
+        }
+        span = match span {
+            None => Some((s, e)),
+            Some((start, end)) if end <= s && text[end..s].chars().all(|c| c == '\n' || c == '\r') =>
+                Some((start, e)),
+            Some(_) => return Err(BratError::FormatError(
+                format!("Discontinuous annotations are not supported: {}", line)))
+        };
+    }
+    let (start, end) = span.ok_or_else(err)?;
+    Ok((entity_type, start as u32, end as u32))
+}
+
+// Split a span at newlines into the fragments that contain text
+fn line_fragments(text : &str, start : usize, end : usize) -> Vec<(usize, usize)> {
+    let mut fragments = Vec::new();
+    let mut fragment_start = start;
+    for (i, _) in text[start..end].match_indices('\n') {
+        if start + i > fragment_start {
+            fragments.push((fragment_start, start + i));
+        }
+        fragment_start = start + i + 1;
+    }
+    if end > fragment_start || fragments.is_empty() {
+        fragments.push((fragment_start.min(end), end));
+    }
+    fragments
+}
+
+fn brat_file_name(doc_id : &str) -> String {
+    doc_id.replace('/', "_").replace('+', "-")
+}
+
+// Convert a byte offset in a string to a character offset
+fn byte_to_char(text : &str, offset : usize) -> usize {
+    text[..offset].chars().count()
+}
+
+// Convert a character offset in a string to a byte offset
+fn char_to_byte(text : &str, offset : usize) -> Option<usize> {
+    text.char_indices().map(|(i, _)| i)
+        .chain(std::iter::once(text.len()))
+        .nth(offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SimpleCorpus, LayerType};
+
+    #[test]
+    fn test_write_brat() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("ner").base("text").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        let id = corpus.build_doc()
+            .layer("text", "Éire is in Europe").unwrap()
+            .layer("ner", vec![(0, 5, "LOC"), (12, 18, "LOC")]).unwrap()
+            .add().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        write_brat(dir.path(), &corpus, &BratMapping::new("text").entity_layer("ner")).unwrap();
+        let name = brat_file_name(&id);
+        let txt = std::fs::read_to_string(dir.path().join(format!("{}.txt", name))).unwrap();
+        let ann = std::fs::read_to_string(dir.path().join(format!("{}.ann", name))).unwrap();
+        assert_eq!(txt, "Éire is in Europe");
+        assert_eq!(ann, "T1\tLOC 0 4\tÉire\nT2\tLOC 11 17\tEurope\n");
+    }
+
+    #[test]
+    fn test_write_brat_ann() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("ner").base("text").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        corpus.build_layer("located").base("ner").layer_type(LayerType::element)
+            .data(DataType::Link).link_types(vec!["in".to_string()]).add().unwrap();
+        let id = corpus.build_doc()
+            .layer("text", "Tá Seán\nÓ Sé i nGaillimh").unwrap()
+            .layer("ner", vec![(4, 16, "PER"), (19, 28, "LOC")]).unwrap()
+            .layer("located", vec![(0, 1, "in")]).unwrap()
+            .add().unwrap();
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        let mapping = BratMapping::new("text").entity_layer("ner").relation_layer("located");
+        let mut ann = Vec::new();
+        write_brat_ann(&mut ann, &doc, corpus.get_meta(), &mapping).unwrap();
+        assert_eq!(String::from_utf8(ann).unwrap(),
+            "T1\tPER 3 7;8 12\tSeán Ó Sé\nT2\tLOC 15 24\tnGaillimh\nR1\tin Arg1:T1 Arg2:T2\n");
+        let mapping = BratMapping::new("text").relation_layer("located");
+        assert!(write_brat_ann(Vec::new(), &doc, corpus.get_meta(), &mapping).is_err());
+    }
+
+    #[test]
+    fn test_read_brat() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("doc1.txt"), "Éire is in Europe").unwrap();
+        std::fs::write(dir.path().join("doc1.ann"),
+            "T1\tLOC 11 17\tEurope\nT2\tGPE 0 4\tÉire\nR1\tIn Arg1:T2 Arg2:T1\n").unwrap();
+        let mut corpus = SimpleCorpus::new();
+        let ids = read_brat(dir.path(), &mut corpus, &BratMapping::new("text")).unwrap();
+        assert_eq!(ids.len(), 1);
+        let doc = corpus.get_doc_by_id(&ids[0]).unwrap();
+        assert_eq!(doc.get("LOC"), Some(&Layer::L2(vec![(12, 18)])));
+        assert_eq!(doc.text("GPE", corpus.get_meta()).unwrap(), vec!["Éire"]);
+        assert_eq!(corpus.get_meta()["LOC"].base, Some("text".to_string()));
+    }
+
+    #[test]
+    fn test_brat_round_trip() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("ner").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_doc()
+            .layer("text", "Baile Átha Cliath").unwrap()
+            .layer("ner", vec![(0, 5), (6, 18)]).unwrap()
+            .add().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let mapping = BratMapping::new("text").entity_layer("ner");
+        write_brat(dir.path(), &corpus, &mapping).unwrap();
+        let mut corpus2 = SimpleCorpus::new();
+        read_brat(dir.path(), &mut corpus2, &mapping).unwrap();
+        assert_eq!(corpus.get_docs(), corpus2.get_docs());
+        assert_eq!(corpus.get_doc_by_id(&corpus.get_docs()[0]).unwrap(),
+            corpus2.get_doc_by_id(&corpus2.get_docs()[0]).unwrap());
+    }
+
+    #[test]
+    fn test_brat_round_trip_labelled() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("ner").base("text").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        corpus.build_doc()
+            .layer("text", "Tá Seán\nÓ Sé i nGaillimh").unwrap()
+            .layer("ner", vec![(4, 16, "PER"), (19, 28, "LOC")]).unwrap()
+            .add().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let mapping = BratMapping::new("text").entity_layer("ner");
+        write_brat(dir.path(), &corpus, &mapping).unwrap();
+        let mut corpus2 = SimpleCorpus::new();
+        corpus2.build_layer("text").add().unwrap();
+        corpus2.build_layer("ner").base("text").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        read_brat(dir.path(), &mut corpus2, &mapping).unwrap();
+        assert_eq!(corpus, corpus2);
+    }
+
+    #[test]
+    fn test_read_brat_discontinuous() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("doc1.txt"), "Baile Átha Cliath").unwrap();
+        std::fs::write(dir.path().join("doc1.ann"), "T1\tLOC 0 5;11 17\tBaile Cliath\n").unwrap();
+        let mut corpus = SimpleCorpus::new();
+        assert!(matches!(read_brat(dir.path(), &mut corpus, &BratMapping::new("text")),
+            Err(BratError::FormatError(_))));
+    }
+}
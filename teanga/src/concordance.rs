@@ -0,0 +1,243 @@
+//! Key-word in context (KWIC) extraction
+//!
+//! This module provides concordance lines for a word in a layer of a corpus,
+//! or for the matches of a query, showing the match together with its
+//! surrounding context. Concordance lines can be written as a key-word in
+//! context table with `write_kwic`.
+use std::collections::HashMap;
+use std::io::Write;
+use unicode_width::UnicodeWidthChar;
+use crate::{Document, LayerDesc, LayerGraph, Query, TeangaError, TeangaResult};
+
+/// A single concordance line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Concordance {
+    /// The ID of the document the keyword occurs in
+    pub doc_id: String,
+    /// The text before the keyword
+    pub left: String,
+    /// The keyword as it occurs in the text
+    pub keyword: String,
+    /// The text after the keyword
+    pub right: String,
+    /// The start of the keyword as a byte offset in the characters layer
+    pub start: usize,
+    /// The end (exclusive) of the keyword as a byte offset in the characters
+    /// layer
+    pub end: usize
+}
+
+/// Find the concordance lines for a keyword in a single document
+///
+/// # Arguments
+///
+/// * `doc_id` - The ID of the document
+/// * `doc` - The document
+/// * `layer` - The layer whose annotations are matched against the keyword
+/// * `needle` - The keyword to find
+/// * `window` - The number of annotations of context on each side
+/// * `meta` - The metadata of the corpus
+///
+/// # Returns
+///
+/// The concordance lines for each occurrence of the keyword
+pub fn doc_concordance(doc_id : &str, doc : &Document, layer : &str, needle : &str,
+    window : usize, meta : &HashMap<String, LayerDesc>) -> TeangaResult<Vec<Concordance>> {
+    let graph = LayerGraph::new(meta);
+    let char_layer = graph.root_of(layer)?;
+    let text = doc.get(char_layer).and_then(|l| l.characters())
+        .ok_or_else(|| TeangaError::LayerNotFoundError(char_layer.to_string()))?;
+    let indexes = doc.indexes(layer, char_layer, meta)?;
+    let mut lines = Vec::new();
+    for (i, (start, end)) in indexes.iter().enumerate() {
+        if &text[*start..*end] == needle {
+            let left_start = indexes[i.saturating_sub(window)].0;
+            let right_end = indexes[(i + window).min(indexes.len() - 1)].1;
+            lines.push(Concordance {
+                doc_id: doc_id.to_string(),
+                left: text[left_start..*start].trim().to_string(),
+                keyword: text[*start..*end].to_string(),
+                right: text[*end..right_end].trim().to_string(),
+                start: *start,
+                end: *end
+            });
+        }
+    }
+    Ok(lines)
+}
+
+/// Find the concordance lines for the matches of a query in a single
+/// document, see `Query::match_offsets`
+///
+/// # Arguments
+///
+/// * `doc_id` - The ID of the document
+/// * `doc` - The document
+/// * `query` - The query
+/// * `context` - The number of characters of context on each side
+/// * `meta` - The metadata of the corpus
+///
+/// # Returns
+///
+/// The concordance lines for each match of the query
+pub fn query_concordance(doc_id : &str, doc : &Document, query : &Query,
+    context : usize, meta : &HashMap<String, LayerDesc>) -> TeangaResult<Vec<Concordance>> {
+    let mut lines = Vec::new();
+    for (char_layer, start, end) in query.match_offsets(doc, meta) {
+        let text = doc.get(&char_layer).and_then(|l| l.characters())
+            .ok_or_else(|| TeangaError::LayerNotFoundError(char_layer.clone()))?;
+        let left_start = text[..start].char_indices().rev().take(context).last()
+            .map_or(start, |(i, _)| i);
+        let right_end = text[end..].char_indices().nth(context)
+            .map_or(text.len(), |(i, _)| end + i);
+        lines.push(Concordance {
+            doc_id: doc_id.to_string(),
+            left: text[left_start..start].to_string(),
+            keyword: text[start..end].to_string(),
+            right: text[end..right_end].to_string(),
+            start,
+            end
+        });
+    }
+    Ok(lines)
+}
+
+/// Write concordance lines as a key-word in context table, with the left
+/// context aligned to the right, the keyword in brackets and the right
+/// context aligned to the left. Widths are measured in terminal columns so
+/// that wide characters are aligned correctly
+///
+/// # Arguments
+///
+/// * `writer` - The writer to write to
+/// * `concordances` - The concordance lines
+/// * `width` - The width of the left and right context columns. Context
+///   that is too long is truncated on the side away from the keyword
+pub fn write_kwic<W : Write>(mut writer : W, concordances : &[Concordance],
+    width : usize) -> std::io::Result<()> {
+    let keyword_width = concordances.iter()
+        .map(|c| display_width(&c.keyword))
+        .max().unwrap_or(0);
+    for c in concordances {
+        let left = fit(c.left.chars().rev(), width).chars().rev().collect::<String>();
+        let right = fit(c.right.chars(), width);
+        let keyword = c.keyword.chars().map(display_char).collect::<String>();
+        writeln!(writer, "{}{} [{}]{} {}{}",
+            " ".repeat(width - display_width(&left)), left,
+            keyword, " ".repeat(keyword_width - display_width(&keyword)),
+            right, " ".repeat(width - display_width(&right)))?;
+    }
+    Ok(())
+}
+
+// Take characters until they fill the width
+fn fit<I : Iterator<Item=char>>(chars : I, width : usize) -> String {
+    let mut result = String::new();
+    let mut used = 0;
+    for c in chars.map(display_char) {
+        let w = c.width().unwrap_or(0);
+        if used + w > width {
+            break;
+        }
+        used += w;
+        result.push(c);
+    }
+    result
+}
+
+// Line breaks and tabs would break the table so are shown as spaces
+fn display_char(c : char) -> char {
+    if c.is_whitespace() { ' ' } else { c }
+}
+
+fn display_width(text : &str) -> usize {
+    text.chars().map(|c| display_char(c).width().unwrap_or(0)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+    use crate::{Corpus, SimpleCorpus, LayerType, DataType};
+
+    #[test]
+    fn test_concordance() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        let id = corpus.build_doc()
+            .layer("text", "the quick brown fox jumps over the lazy dog").unwrap()
+            .layer("words", vec![(0, 3), (4, 9), (10, 15), (16, 19), (20, 25), (26, 30), (31, 34), (35, 39), (40, 43)]).unwrap()
+            .add().unwrap();
+        let lines = corpus.concordance("words", "the", 2).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].doc_id, id);
+        assert_eq!(lines[0].left, "");
+        assert_eq!(lines[0].right, "quick brown");
+        assert_eq!(lines[1].left, "jumps over");
+        assert_eq!(lines[1].keyword, "the");
+        assert_eq!(lines[1].right, "lazy dog");
+        assert_eq!((lines[1].start, lines[1].end), (31, 34));
+    }
+
+    #[test]
+    fn test_search_concordance() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("pos").base("words").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        let id = corpus.build_doc()
+            .layer("text", "bhí an cú ag rith agus an cat ina luí").unwrap()
+            .layer("words", vec![(0, 4), (5, 7), (8, 11), (12, 14), (15, 19), (20, 24), (25, 27), (28, 31), (32, 35), (36, 40)]).unwrap()
+            .layer("pos", vec!["VERB", "DET", "NOUN", "ADP", "NOUN", "CONJ", "DET", "NOUN", "ADP", "NOUN"]).unwrap()
+            .add().unwrap();
+        corpus.build_doc()
+            .layer("text", "tá sé fuar").unwrap()
+            .layer("words", vec![(0, 3), (4, 7), (8, 12)]).unwrap()
+            .layer("pos", vec!["VERB", "PRON", "ADJ"]).unwrap()
+            .add().unwrap();
+        let lines : Vec<Concordance> = corpus.search_concordance(Query::text("words", "an"), 5)
+            .collect::<TeangaResult<_>>().unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].doc_id, id);
+        assert_eq!((lines[0].start, lines[0].end), (5, 7));
+        assert_eq!(lines[0].left, "bhí ");
+        assert_eq!(lines[0].right, " cú a");
+        assert_eq!(lines[1].left, "agus ");
+        assert_eq!(lines[1].keyword, "an");
+        assert_eq!(lines[1].right, " cat ");
+        let lines : Vec<Concordance> = corpus.search_concordance(
+            Query::value("pos", "DET").and(Query::text("words", "cat")), 3)
+            .collect::<TeangaResult<_>>().unwrap();
+        assert_eq!(lines.iter().map(|l| l.keyword.as_str()).collect::<Vec<_>>(),
+            vec!["an", "an", "cat"]);
+        let lines : Vec<Concordance> = corpus.search_concordance(
+            Query::TextRegex("text".to_string(), Regex::new("u.r").unwrap()), 2)
+            .collect::<TeangaResult<_>>().unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!((lines[0].left.as_str(), lines[0].keyword.as_str(), lines[0].right.as_str()),
+            (" f", "uar", ""));
+        assert_eq!(corpus.search_concordance(Query::exists("pos"), 3).count(), 0);
+    }
+
+    #[test]
+    fn test_write_kwic() {
+        let line = |left : &str, keyword : &str, right : &str| Concordance {
+            doc_id: "doc".to_string(),
+            left: left.to_string(),
+            keyword: keyword.to_string(),
+            right: right.to_string(),
+            start: 0,
+            end: 0
+        };
+        let lines = vec![
+            line("bhí an", "cú", " ag rith\nagus"),
+            line("a", "東京", "は"),
+            line("", "cat", "")];
+        let mut out = Vec::new();
+        write_kwic(&mut out, &lines, 6).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+            "bhí an [cú]    ag ri\n     a [東京] は    \n       [cat]        \n");
+    }
+}
@@ -0,0 +1,177 @@
+//! Operations that combine several corpora
+//!
+//! # Examples
+//! ```rust
+//! use teanga::{SimpleCorpus, Corpus, LayerType};
+//! use teanga::corpus_ops::{merge, MergeStrategy};
+//! let mut words = SimpleCorpus::new();
+//! words.build_layer("text").add().unwrap();
+//! words.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+//! words.build_doc().layer("text", "Hello world").unwrap()
+//!     .layer("words", vec![(0, 5), (6, 11)]).unwrap().add().unwrap();
+//! let mut corpus = SimpleCorpus::new();
+//! corpus.build_layer("text").add().unwrap();
+//! corpus.build_doc().layer("text", "Hello world").unwrap().add().unwrap();
+//! let summary = merge(&words, &mut corpus, MergeStrategy::MergeLayers).unwrap();
+//! assert_eq!(summary.merged, 1);
+//! ```
+use std::collections::{HashMap, HashSet};
+use crate::{Corpus, Layer, WriteableCorpus, TeangaError, TeangaResult};
+
+/// What to do with a document that occurs in both corpora when merging
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Add the layers of the new document to the existing document. It is
+    /// an error if a layer occurs in both with different content
+    MergeLayers,
+    /// Keep the existing document
+    Skip,
+    /// Replace the existing document with the new document
+    Replace
+}
+
+/// The number of documents affected by a merge
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MergeSummary {
+    /// The documents that were not in the target corpus
+    pub added: usize,
+    /// The documents that were in the target corpus and were merged with or
+    /// replaced by the new document
+    pub merged: usize,
+    /// The documents that were in the target corpus and were not changed
+    pub skipped: usize
+}
+
+/// Merge the documents of one corpus into another. Layers that are only
+/// defined in the source corpus are added to the target corpus
+///
+/// # Arguments
+///
+/// * `src` - The corpus to merge from
+/// * `dst` - The corpus to merge into
+/// * `on_conflict` - What to do with a document that is in both corpora
+///
+/// # Returns
+///
+/// The number of documents that were added, merged and skipped. This fails
+/// without changing the target corpus if a layer is defined in both corpora
+/// with a different type or data type, or, when merging layers, if a
+/// document has a layer with different content in the two corpora
+pub fn merge<C1 : Corpus, C2 : WriteableCorpus>(src : &C1, dst : &mut C2,
+    on_conflict : MergeStrategy) -> TeangaResult<MergeSummary> {
+    let mut meta = dst.get_meta().clone();
+    for (name, layer_desc) in src.get_meta() {
+        match meta.get(name) {
+            Some(existing) => {
+                if existing.layer_type != layer_desc.layer_type || existing.data != layer_desc.data {
+                    return Err(TeangaError::ModelError(
+                        format!("Layer {} has a different type in the two corpora", name)));
+                }
+            },
+            None => {
+                meta.insert(name.clone(), layer_desc.clone());
+            }
+        }
+    }
+    let existing : HashSet<String> = dst.get_docs().into_iter().collect();
+    // Check every document before anything is written
+    if on_conflict == MergeStrategy::MergeLayers {
+        for id in src.get_order().iter().filter(|id| existing.contains(*id)) {
+            merged_content(dst.get_doc_by_id(id)?.content, src.get_doc_by_id(id)?.content, id)?;
+        }
+    }
+    dst.set_meta(meta)?;
+    let mut summary = MergeSummary::default();
+    for id in src.get_order() {
+        let doc = src.get_doc_by_id(id)?;
+        if !existing.contains(id) {
+            dst.add_doc(doc)?;
+            summary.added += 1;
+            continue;
+        }
+        match on_conflict {
+            MergeStrategy::MergeLayers => {
+                let content = merged_content(dst.get_doc_by_id(id)?.content, doc.content, id)?;
+                dst.update_doc(id, content)?;
+                summary.merged += 1;
+            },
+            MergeStrategy::Skip => summary.skipped += 1,
+            MergeStrategy::Replace => {
+                dst.update_doc(id, doc)?;
+                summary.merged += 1;
+            }
+        }
+    }
+    Ok(summary)
+}
+
+// Add the layers of a new document to the layers of an existing document
+fn merged_content(mut content : HashMap<String, Layer>, new : HashMap<String, Layer>,
+    id : &str) -> TeangaResult<HashMap<String, Layer>> {
+    for (name, layer) in new {
+        match content.get(&name) {
+            Some(l) if *l != layer => return Err(TeangaError::ModelError(
+                format!("Layer {} of document {} differs in the two corpora", name, id))),
+            Some(_) => {},
+            None => {
+                content.insert(name, layer);
+            }
+        }
+    }
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SimpleCorpus, LayerType, DataType};
+
+    fn corpus_with(layer : &str, data : Vec<&'static str>) -> SimpleCorpus {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer(layer).base("words").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        corpus.build_doc().layer("text", "Tá sé fuar").unwrap()
+            .layer("words", vec![(0, 3), (4, 7), (8, 12)]).unwrap()
+            .layer(layer, data).unwrap().add().unwrap();
+        corpus
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut dst = corpus_with("pos", vec!["VERB", "PRON", "ADJ"]);
+        let mut src = corpus_with("lemma", vec!["bí", "sé", "fuar"]);
+        src.build_doc().layer("text", "Dia duit").unwrap().add().unwrap();
+        let summary = merge(&src, &mut dst, MergeStrategy::MergeLayers).unwrap();
+        assert_eq!(summary, MergeSummary { added: 1, merged: 1, skipped: 0 });
+        assert_eq!(dst.get_docs().len(), 2);
+        let doc = dst.get_doc_by_id(&dst.get_docs()[0]).unwrap();
+        assert_eq!(doc.text("lemma", dst.get_meta()).unwrap(), vec!["Tá", "sé", "fuar"]);
+        assert_eq!(doc["pos"], Layer::LS(vec!["VERB".to_string(), "PRON".to_string(), "ADJ".to_string()]));
+        let summary = merge(&src, &mut dst, MergeStrategy::Skip).unwrap();
+        assert_eq!(summary, MergeSummary { added: 0, merged: 0, skipped: 2 });
+    }
+
+    #[test]
+    fn test_merge_conflict() {
+        let mut dst = corpus_with("pos", vec!["VERB", "PRON", "ADJ"]);
+        let mut src = corpus_with("pos", vec!["VERB", "PRON", "NOUN"]);
+        src.build_layer("lemma").base("words").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        src.build_doc().layer("text", "Dia duit").unwrap().add().unwrap();
+        let order = src.get_docs().into_iter().rev().collect();
+        src.set_order(order).unwrap();
+        // Nothing is added when a later document conflicts
+        let before = dst.clone();
+        assert!(merge(&src, &mut dst, MergeStrategy::MergeLayers).is_err());
+        assert_eq!(dst, before);
+        merge(&src, &mut dst, MergeStrategy::Replace).unwrap();
+        let doc = dst.get_doc_by_id(&dst.get_docs()[0]).unwrap();
+        assert_eq!(doc["pos"], Layer::LS(vec!["VERB".to_string(), "PRON".to_string(), "NOUN".to_string()]));
+        let mut src = SimpleCorpus::new();
+        src.build_layer("text").add().unwrap();
+        src.build_layer("pos").base("text").layer_type(LayerType::span).add().unwrap();
+        assert!(merge(&src, &mut dst, MergeStrategy::Replace).is_err());
+    }
+}
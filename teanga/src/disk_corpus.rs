@@ -16,7 +16,9 @@ use fjall::{Config, PartitionCreateOptions, PartitionHandle};
 #[cfg(feature = "redb")]
 use redb::{Database, TableDefinition, TableError};
 use ciborium::{from_reader, into_writer};
+use std::ops::{Deref, DerefMut};
 use std::path::Path;
+use thiserror::Error;
 
 const DOCUMENT_PREFIX : u8 = 0x00;
 const META_BYTES : [u8;1] = [0x01];
@@ -31,7 +33,9 @@ pub struct DiskCorpus {
     order: Vec<String>,
     compression_model: SupportedStringCompression,
     index: Index,
-    db: Box<dyn DBImpl>
+    db: Box<dyn DBImpl>,
+    pending: Option<HashMap<Vec<u8>, Option<Vec<u8>>>>,
+    batch_error: Option<TeangaError>
 }
 
 impl DiskCorpus {
@@ -78,35 +82,153 @@ impl DiskCorpus {
             order,
             compression_model,
             index,
-            db
+            db,
+            pending: None,
+            batch_error: None
         })
     }
 
+    /// Start a batch of writes. All calls to `add_doc`, `update_doc` and
+    /// `remove_doc` made through the returned guard are held in memory and
+    /// written to the database in a single transaction when the guard is
+    /// committed or dropped.
+    ///
+    /// # Returns
+    /// A guard that can be used as the corpus
+    ///
+    /// # Example
+    /// ```ignore
+    /// let mut batch = corpus.batch();
+    /// batch.add_doc(vec![("text".to_string(), "A document")])?;
+    /// batch.commit()?;
+    /// ```
+    pub fn batch(&mut self) -> BatchGuard<'_> {
+        let nested = self.pending.is_some();
+        let snapshot = (self.meta.clone(), self.order.clone(), self.pending.clone(),
+            self.index.vec().len());
+        if !nested {
+            self.pending = Some(HashMap::new());
+        }
+        BatchGuard {
+            corpus: self,
+            snapshot: Some(snapshot),
+            nested
+        }
+    }
+
+    /// Run a function in a batch of writes. The batch is committed if the
+    /// function succeeds and rolled back if it returns an error
+    ///
+    /// # Arguments
+    /// * `f` - The function, which can use the batch as the corpus
+    ///
+    /// # Returns
+    /// The result of the function, or the error if the commit fails
+    pub fn with_batch<T, F : FnOnce(&mut BatchGuard) -> TeangaResult<T>>(&mut self, f : F) -> TeangaResult<T> {
+        let mut batch = self.batch();
+        match f(&mut batch) {
+            Ok(result) => {
+                batch.commit()?;
+                Ok(result)
+            },
+            Err(e) => {
+                batch.rollback();
+                Err(e)
+            }
+        }
+    }
+
+    /// Take the error of the last batch that failed to commit when its guard
+    /// was dropped. The changes of that batch were rolled back. Call
+    /// `BatchGuard::commit` to get the error of a batch directly
+    ///
+    /// # Returns
+    /// The error, or `None` if no batch has failed since the last call
+    pub fn take_batch_error(&mut self) -> Option<TeangaError> {
+        self.batch_error.take()
+    }
+
+    /// Check that the corpus is consistent, e.g., after a crash. This reads
+    /// every document in the corpus and checks that:
+    ///
+    /// * every ID in the order is unique and has a stored document
+    /// * every layer referred to as a base or target is declared in the
+    ///   metadata
+    /// * every document can be read with the metadata, including that every
+    ///   string index refers to a string in the index
+    ///
+    /// # Returns
+    /// Nothing if the corpus is consistent, otherwise all inconsistencies
+    /// that were found
+    pub fn verify_integrity(&self) -> Result<(), Vec<IntegrityError>> {
+        let mut errors = Vec::new();
+        for name in self.meta.keys().sorted() {
+            let layer_desc = &self.meta[name];
+            for referenced in layer_desc.base.iter().chain(layer_desc.target.iter()) {
+                if !referenced.is_empty() && !self.meta.contains_key(referenced) {
+                    errors.push(IntegrityError::UndeclaredLayer {
+                        layer: referenced.clone(),
+                        referenced_by: name.clone()
+                    });
+                }
+            }
+        }
+        let mut seen = HashSet::new();
+        for id in self.order.iter() {
+            if !seen.insert(id) {
+                errors.push(IntegrityError::DuplicateDocument(id.clone()));
+                continue;
+            }
+            match self.get(id) {
+                Ok(Some(_)) => {},
+                Ok(None) => errors.push(IntegrityError::MissingDocument(id.clone())),
+                Err(e) => errors.push(IntegrityError::CorruptDocument {
+                    id: id.clone(),
+                    message: e.to_string()
+                })
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
     fn insert(&mut self, id : String, doc : Document) -> TeangaResult<()> {
         let mut data = Vec::new();
         write_tcf_doc(&mut data, doc.clone(), &mut self.index, &self.meta, &self.compression_model)
             .map_err(|e| TeangaError::ModelError(e.to_string()))?;
-        let mut id_bytes = Vec::new();
-        id_bytes.push(DOCUMENT_PREFIX);
-        id_bytes.extend(id.as_bytes());
-        self.db.insert(id_bytes, data)?;
+        let id_bytes = doc_key(&id);
+        if let Some(pending) = self.pending.as_mut() {
+            pending.insert(id_bytes, Some(data));
+        } else {
+            self.db.insert(id_bytes, data)?;
+        }
         Ok(())
 
     }
 
     fn remove(&mut self, id : &str) -> TeangaResult<()> {
-        let mut id_bytes = Vec::new();
-        id_bytes.push(DOCUMENT_PREFIX);
-        id_bytes.extend(id.as_bytes());
-        self.db.remove(id_bytes)?;
+        let id_bytes = doc_key(id);
+        if let Some(pending) = self.pending.as_mut() {
+            pending.insert(id_bytes, None);
+        } else {
+            self.db.remove(id_bytes)?;
+        }
         Ok(())
     }
 
+    fn get_bytes(&self, id : &str) -> TeangaResult<Option<Vec<u8>>> {
+        let id_bytes = doc_key(id);
+        match self.pending.as_ref().and_then(|p| p.get(&id_bytes)) {
+            Some(pending) => Ok(pending.clone()),
+            None => self.db.get(id_bytes)
+        }
+    }
+
     fn get(&self, id : &str) -> TeangaResult<Option<Document>> {
-        let mut id_bytes = Vec::new();
-        id_bytes.push(DOCUMENT_PREFIX);
-        id_bytes.extend(id.as_bytes());
-        match self.db.get(id_bytes)? {
+        match self.get_bytes(id)? {
             Some(bytes) => {
                 let doc = read_tcf_doc(&mut bytes.as_ref(), &self.meta, 
                         &self.index.freeze(), &self.compression_model)
@@ -118,14 +240,147 @@ impl DiskCorpus {
     }
 
     fn commit(&mut self) -> TeangaResult<()> {
+        for (key, value) in self.header_entries()? {
+            self.db.insert(key, value)?;
+        }
+        Ok(())
+    }
+
+    fn header_entries(&self) -> TeangaResult<Vec<(Vec<u8>, Vec<u8>)>> {
         let mut meta_bytes = Vec::new();
         write_tcf_header_compression(&mut meta_bytes, &self.meta, &self.compression_model)
             .map_err(|e| TeangaError::ModelError(e.to_string()))?;
-        self.db.insert(META_BYTES.to_vec(), meta_bytes)?;
-        self.db.insert(ORDER_BYTES.to_vec(), to_stdvec(&self.order)?)?;
-        let index_bytes = self.index.to_bytes();
-        self.db.insert(INDEX_BYTES.to_vec(), index_bytes)?;
-        Ok(())
+        Ok(vec![
+            (META_BYTES.to_vec(), meta_bytes),
+            (ORDER_BYTES.to_vec(), to_stdvec(&self.order)?),
+            (INDEX_BYTES.to_vec(), self.index.to_bytes())
+        ])
+    }
+}
+
+/// Open a disk corpus and run a function in a batch of writes, see
+/// `DiskCorpus::with_batch`
+///
+/// # Arguments
+/// * `path` - The path to the database
+/// * `f` - The function, which can use the batch as the corpus
+///
+/// # Returns
+/// The result of the function
+pub fn with_transaction<P : AsRef<Path>, T, F : FnOnce(&mut BatchGuard) -> TeangaResult<T>>(path : P, f : F) -> TeangaResult<T> {
+    DiskCorpus::new(path)?.with_batch(f)
+}
+
+fn doc_key(id : &str) -> Vec<u8> {
+    let mut id_bytes = Vec::new();
+    id_bytes.push(DOCUMENT_PREFIX);
+    id_bytes.extend(id.as_bytes());
+    id_bytes
+}
+
+/// An inconsistency found by `DiskCorpus::verify_integrity`
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum IntegrityError {
+    /// A document in the order is not stored in the database
+    #[error("Document {0} is in the order but is not stored")]
+    MissingDocument(String),
+    /// A document occurs more than once in the order
+    #[error("Document {0} occurs more than once in the order")]
+    DuplicateDocument(String),
+    /// A layer is used as a base or target but is not declared
+    #[error("Layer {layer} is used by {referenced_by} but is not declared")]
+    UndeclaredLayer {
+        layer: String,
+        referenced_by: String
+    },
+    /// A document could not be read
+    #[error("Document {id} could not be read: {message}")]
+    CorruptDocument {
+        id: String,
+        message: String
+    }
+}
+
+/// A batch of writes to a disk corpus. This is created by
+/// `DiskCorpus::batch` and can be used in place of the corpus. The
+/// writes are committed by calling `commit`, or when the guard is dropped.
+/// If the commit fails the corpus is rolled back to the state at the start
+/// of the batch. `commit` returns the error, whereas the error of a commit
+/// on drop is kept and can be taken with `DiskCorpus::take_batch_error`. The writes are applied atomically with sled and redb, but
+/// fjall uses the default `DBImpl::apply_batch`, which is not atomic.
+pub struct BatchGuard<'a> {
+    corpus: &'a mut DiskCorpus,
+    snapshot: Option<BatchSnapshot>,
+    nested: bool
+}
+
+/// The metadata, order, pending writes and number of indexed strings of a
+/// corpus at the start of a batch
+type BatchSnapshot = (HashMap<String, LayerDesc>, Vec<String>, Option<HashMap<Vec<u8>, Option<Vec<u8>>>>, usize);
+
+impl<'a> BatchGuard<'a> {
+    /// Write all changes in this batch to the database
+    pub fn commit(mut self) -> TeangaResult<()> {
+        self.finish()
+    }
+
+    /// Discard all changes made in this batch
+    pub fn rollback(mut self) {
+        self.restore();
+    }
+
+    fn finish(&mut self) -> TeangaResult<()> {
+        if self.snapshot.is_none() || self.nested {
+            self.snapshot = None;
+            return Ok(());
+        }
+        let mut batch : Vec<(Vec<u8>, Option<Vec<u8>>)> = self.corpus.pending.take()
+            .unwrap_or_default().into_iter().collect();
+        let result = self.corpus.header_entries().and_then(|header| {
+            batch.extend(header.into_iter().map(|(k, v)| (k, Some(v))));
+            self.corpus.db.apply_batch(batch)
+        });
+        match result {
+            Ok(()) => {
+                self.snapshot = None;
+                Ok(())
+            },
+            Err(e) => {
+                self.restore();
+                Err(e)
+            }
+        }
+    }
+
+    fn restore(&mut self) {
+        if let Some((meta, order, pending, index_len)) = self.snapshot.take() {
+            self.corpus.meta = meta;
+            self.corpus.order = order;
+            self.corpus.pending = pending;
+            self.corpus.index.truncate(index_len);
+        }
+    }
+}
+
+impl<'a> Deref for BatchGuard<'a> {
+    type Target = DiskCorpus;
+
+    fn deref(&self) -> &DiskCorpus {
+        self.corpus
+    }
+}
+
+impl<'a> DerefMut for BatchGuard<'a> {
+    fn deref_mut(&mut self) -> &mut DiskCorpus {
+        self.corpus
+    }
+}
+
+impl<'a> Drop for BatchGuard<'a> {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            self.corpus.batch_error = Some(e);
+        }
     }
 }
 
@@ -138,7 +393,9 @@ impl Corpus for DiskCorpus {
         base: Option<String>, data: Option<DataType>, link_types: Option<Vec<String>>, 
         target: Option<String>, default: Option<Layer>,
         meta : HashMap<String, Value>) -> TeangaResult<()> {
-        self.meta.insert(name.clone(), LayerDesc {
+        crate::layer::check_meta_values([&meta])?;
+        let mut new_meta = self.meta.clone();
+        new_meta.insert(name.clone(), LayerDesc {
             layer_type,
             base,
             data,
@@ -147,6 +404,8 @@ impl Corpus for DiskCorpus {
             default,
             meta
         });
+        check_meta(&new_meta)?;
+        self.meta = new_meta;
         Ok(())
     }
 
@@ -163,8 +422,8 @@ impl Corpus for DiskCorpus {
         let doc = match self.get_doc_by_id(id) {
             Ok(mut doc) => {
                 for (key, layer) in content {
-                    let layer_desc = self.meta.get(&key).ok_or_else(|| TeangaError::ModelError(
-                        format!("Layer {} does not exist", key)))?;
+                    let layer_desc = self.meta.get(&key).ok_or_else(||
+                        TeangaError::LayerNotFoundError(key.clone()))?;
                     doc.set(&key, layer.into_layer(layer_desc)?);
                 }
                 doc
@@ -196,6 +455,18 @@ impl Corpus for DiskCorpus {
         Ok(())
     }
 
+    /// Remove duplicate documents in a single transaction
+    fn dedup_ids(&mut self) -> TeangaResult<Vec<String>> {
+        let removed = duplicate_ids(self)?;
+        self.with_batch(|batch| {
+            for id in removed.iter() {
+                batch.remove_doc(id)?;
+            }
+            Ok(())
+        })?;
+        Ok(removed)
+    }
+
     fn get_doc_by_id(&self, id : &str) -> TeangaResult<Document> {
         match self.get(id)? {
             Some(doc) => {
@@ -226,6 +497,7 @@ impl Corpus for DiskCorpus {
 
 impl WriteableCorpus for DiskCorpus {
     fn set_meta(&mut self, meta : HashMap<String, LayerDesc>) -> TeangaResult<()> {
+        check_meta(&meta)?;
         self.meta = meta;
         Ok(())
         
@@ -234,6 +506,28 @@ impl WriteableCorpus for DiskCorpus {
         self.order = order;
         Ok(())
     }
+
+    /// Rename documents. The stored documents are moved to their new keys
+    /// without decoding them, in a single transaction
+    fn apply_id_mapping(&mut self, mapping : &HashMap<String, String>) -> TeangaResult<()> {
+        let order = mapped_order(&self.order, mapping)?;
+        let mut entries = Vec::new();
+        for (old, new) in mapping.iter() {
+            let bytes = self.get_bytes(old)?.ok_or(TeangaError::DocumentNotFoundError)?;
+            entries.push((old, new, bytes));
+        }
+        let mut batch = self.batch();
+        if let Some(pending) = batch.pending.as_mut() {
+            for (old, _, _) in entries.iter() {
+                pending.insert(doc_key(old), None);
+            }
+            for (_, new, bytes) in entries {
+                pending.insert(doc_key(new), Some(bytes));
+            }
+        }
+        batch.order = order;
+        batch.commit()
+    }
 }
 
 impl Drop for DiskCorpus {
@@ -247,6 +541,18 @@ pub trait DBImpl {
     fn get(&self, key : Vec<u8>) -> TeangaResult<Option<Vec<u8>>>;
     fn remove(&self, key : Vec<u8>) -> TeangaResult<()>;
     fn flush(&self) -> TeangaResult<()>;
+    /// Apply a set of inserts (`Some`) and removals (`None`) together. The
+    /// default implementation applies them one at a time, so it is not
+    /// atomic. This is used by fjall.
+    fn apply_batch(&self, batch : Vec<(Vec<u8>, Option<Vec<u8>>)>) -> TeangaResult<()> {
+        for (key, value) in batch {
+            match value {
+                Some(value) => self.insert(key, value)?,
+                None => self.remove(key)?
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(feature = "sled")]
@@ -273,6 +579,18 @@ impl DBImpl for SledDb {
         self.0.flush()?;
         Ok(())
     }
+
+    fn apply_batch(&self, batch : Vec<(Vec<u8>, Option<Vec<u8>>)>) -> TeangaResult<()> {
+        let mut b = sled::Batch::default();
+        for (key, value) in batch {
+            match value {
+                Some(value) => b.insert(key, value),
+                None => b.remove(key)
+            }
+        }
+        self.0.apply_batch(b)?;
+        Ok(())
+    }
 }
 
 #[cfg(feature = "fjall")]
@@ -339,6 +657,21 @@ impl DBImpl for RedbDb {
     fn flush(&self) -> TeangaResult<()> {
         Ok(())
     }
+
+    fn apply_batch(&self, batch : Vec<(Vec<u8>, Option<Vec<u8>>)>) -> TeangaResult<()> {
+        let write_txn = self.0.begin_write()?;
+        {
+            let mut table = write_txn.open_table(TABLE)?;
+            for (key, value) in batch {
+                match value {
+                    Some(value) => { table.insert(key.as_slice(), value.as_slice())?; },
+                    None => { table.remove(key.as_slice())?; }
+                }
+            }
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
 }
 
 #[cfg(feature = "sled")]
@@ -448,4 +781,150 @@ mod tests {
         let corpus2 = DiskCorpus::new(&tmpfile).unwrap();
         assert!(!corpus2.get_meta().is_empty());
     }
+
+    #[test]
+    fn test_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut corpus = DiskCorpus::new(dir.path().join("db")).unwrap();
+        corpus.build_layer("text").add().unwrap();
+        let id = {
+            let mut batch = corpus.batch();
+            let id = batch.add_doc(vec![("text".to_string(), "first")]).unwrap();
+            batch.add_doc(vec![("text".to_string(), "second")]).unwrap();
+            assert!(batch.get_doc_by_id(&id).is_ok());
+            batch.commit().unwrap();
+            id
+        };
+        assert_eq!(corpus.get_docs().len(), 2);
+        assert!(corpus.get_doc_by_id(&id).is_ok());
+        {
+            let mut batch = corpus.batch();
+            batch.add_doc(vec![("text".to_string(), "dropped")]).unwrap();
+        }
+        assert_eq!(corpus.get_docs().len(), 3);
+        assert!(corpus.take_batch_error().is_none());
+        let dropped = corpus.get_docs()[2].clone();
+        corpus.remove_doc(&dropped).unwrap();
+        {
+            let mut batch = corpus.batch();
+            batch.add_doc(vec![("text".to_string(), "third")]).unwrap();
+            batch.remove_doc(&id).unwrap();
+            batch.rollback();
+        }
+        assert_eq!(corpus.get_docs().len(), 2);
+        assert!(corpus.get_doc_by_id(&id).is_ok());
+        // Strings indexed in a rolled back batch are removed from the index
+        corpus.build_layer("words").layer_type(LayerType::span).base("text")
+            .data(DataType::String).add().unwrap();
+        let index_len = corpus.index.vec().len();
+        {
+            let mut batch = corpus.batch();
+            batch.add_doc(vec![("text".to_string(), Layer::Characters("fourth".to_string())),
+                ("words".to_string(), Layer::L2S(vec![(0, 3, "X".to_string()), (3, 6, "X".to_string())]))]).unwrap();
+            assert!(batch.index.vec().len() > index_len);
+            batch.rollback();
+        }
+        assert_eq!(corpus.index.vec().len(), index_len);
+    }
+
+    #[test]
+    fn test_add_layer_meta_checked() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut corpus = DiskCorpus::new(dir.path().join("db")).unwrap();
+        corpus.build_layer("text").add().unwrap();
+        assert!(corpus.build_layer("a").base("missing").add().is_err());
+        assert!(!corpus.get_meta().contains_key("a"));
+    }
+
+    #[test]
+    fn test_dedup() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut corpus = DiskCorpus::new(dir.path().join("db")).unwrap();
+        corpus.build_layer("text").add().unwrap();
+        let id = corpus.add_doc(vec![("text".to_string(), "first")]).unwrap();
+        let duplicate = corpus.add_doc(vec![("text".to_string(), "first")]).unwrap();
+        corpus.add_doc(vec![("text".to_string(), "second")]).unwrap();
+        assert_eq!(corpus.dedup_ids().unwrap(), vec![duplicate.clone()]);
+        assert_eq!(corpus.get_docs().len(), 2);
+        assert!(corpus.get_doc_by_id(&id).is_ok());
+        assert!(corpus.get_doc_by_id(&duplicate).is_err());
+    }
+
+    #[test]
+    fn test_with_transaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db");
+        let id = with_transaction(&path, |corpus| {
+            corpus.build_layer("text").add()?;
+            corpus.add_doc(vec![("text".to_string(), "first")])
+        }).unwrap();
+        let result : TeangaResult<()> = with_transaction(&path, |corpus| {
+            corpus.add_doc(vec![("text".to_string(), "second")])?;
+            corpus.remove_doc(&id)?;
+            Err(TeangaError::ModelError("failed".to_string()))
+        });
+        assert!(result.is_err());
+        let corpus = DiskCorpus::new(&path).unwrap();
+        assert_eq!(corpus.get_docs(), vec![id]);
+    }
+
+    #[test]
+    fn test_apply_id_mapping() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut corpus = DiskCorpus::new(dir.path().join("db")).unwrap();
+        corpus.build_layer("text").add().unwrap();
+        let id1 = corpus.add_doc(vec![("text".to_string(), "first")]).unwrap();
+        let id2 = corpus.add_doc(vec![("text".to_string(), "second")]).unwrap();
+        let doc1 = corpus.get_doc_by_id(&id1).unwrap();
+        let doc2 = corpus.get_doc_by_id(&id2).unwrap();
+        corpus.apply_id_mapping(&HashMap::from([
+            (id1.clone(), id2.clone()), (id2.clone(), "two".to_string())])).unwrap();
+        assert_eq!(corpus.get_docs(), vec![id2.clone(), "two".to_string()]);
+        assert_eq!(corpus.get_doc_by_id(&id2).unwrap(), doc1);
+        assert_eq!(corpus.get_doc_by_id("two").unwrap(), doc2);
+        assert!(corpus.get_doc_by_id(&id1).is_err());
+        assert!(corpus.apply_id_mapping(&HashMap::from([
+            ("two".to_string(), id2.clone())])).is_err());
+        assert_eq!(corpus.get_doc_by_id("two").unwrap(), doc2);
+    }
+
+    #[test]
+    fn test_verify_integrity() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut corpus = DiskCorpus::new(dir.path().join("db")).unwrap();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        let mut ids = Vec::new();
+        for text in ["a b", "b a", "a a"] {
+            ids.push(corpus.build_doc().layer("text", text).unwrap()
+                .layer("words", vec![(0, 1, &text[0..1]), (2, 3, &text[2..3])]).unwrap()
+                .add().unwrap());
+        }
+        assert_eq!(corpus.verify_integrity(), Ok(()));
+        corpus.remove(&ids[0]).unwrap();
+        corpus.index = Index::new();
+        corpus.order.push(ids[1].clone());
+        corpus.meta.get_mut("words").unwrap().target = Some("sentences".to_string());
+        let errors = corpus.verify_integrity().unwrap_err();
+        assert_eq!(errors[0], IntegrityError::UndeclaredLayer {
+            layer: "sentences".to_string(), referenced_by: "words".to_string() });
+        assert_eq!(errors[1], IntegrityError::MissingDocument(ids[0].clone()));
+        assert!(matches!(errors[2], IntegrityError::CorruptDocument { .. }));
+        assert_eq!(errors.last(), Some(&IntegrityError::DuplicateDocument(ids[1].clone())));
+    }
+
+    #[test]
+    fn test_to_simple_corpus() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut corpus = DiskCorpus::new(dir.path().join("db")).unwrap();
+        corpus.build_layer("text").add().unwrap();
+        let id1 = corpus.add_doc(vec![("text".to_string(), "first")]).unwrap();
+        let id2 = corpus.add_doc(vec![("text".to_string(), "second")]).unwrap();
+        corpus.set_order(vec![id2.clone(), id1.clone()]).unwrap();
+        let simple = corpus.to_simple_corpus().unwrap();
+        assert_eq!(simple.get_meta(), corpus.get_meta());
+        assert_eq!(simple.get_docs(), vec![id2.clone(), id1]);
+        assert_eq!(simple.get_doc_by_id(&id2).unwrap(), corpus.get_doc_by_id(&id2).unwrap());
+    }
 }
@@ -1,8 +1,10 @@
 //! Documents in the corpus.
 use std::collections::HashMap;
-use crate::layer::{Layer, IntoLayer, LayerDesc, TeangaData};
+use crate::layer::{Layer, IntoLayer, LayerDesc, LayerType, DataType, TeangaData};
+use itertools::Itertools;
+use thiserror::Error;
 use serde::{Deserialize, Serialize};
-use crate::{Corpus, TeangaResult, TeangaError};
+use crate::{Corpus, LayerGraph, SplitPolicy, TeangaResult, TeangaError};
 use std::ops::Index;
 
 /// Anything that can be understood as a document content
@@ -36,6 +38,18 @@ impl<D: IntoLayer> DocumentContent<D> for Vec<(String, D)> {
     }
 }
 
+/// A link that refers to an annotation that does not exist
+#[derive(Error,Debug,Clone,PartialEq)]
+#[error("Link {index} in layer {layer} refers to annotation {target} which does not exist")]
+pub struct LinkError {
+    /// The layer containing the link
+    pub layer: String,
+    /// The index of the annotation containing the link
+    pub index: usize,
+    /// The index that the link refers to
+    pub target: u32
+}
+
 #[derive(Debug,Clone,PartialEq,Serialize,Deserialize)]
 /// A document object
 pub struct Document {
@@ -57,8 +71,7 @@ impl Document {
     pub fn new<D : IntoLayer, DC : DocumentContent<D>>(content : DC, meta: &HashMap<String, LayerDesc>) -> TeangaResult<Document> {
        for key in content.keys() {
             if !meta.contains_key(&key) {
-                return Err(TeangaError::ModelError(
-                    format!("Layer {} does not exist", key)))
+                return Err(TeangaError::LayerNotFoundError(key))
             }
         }
         let mut doc_content = HashMap::new();
@@ -73,6 +86,236 @@ impl Document {
         })
     }
 
+    /// Create a new document from its content, sorting the annotations of
+    /// each layer with `Layer::sorted`. Layers that another layer is based
+    /// on or targets are not sorted, as this would break the references
+    /// from the other layer
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content of the document
+    /// * `meta` - The metadata for the document
+    ///
+    /// # Returns
+    ///
+    /// A new document object
+    pub fn new_sorted<D : IntoLayer, DC : DocumentContent<D>>(content : DC, meta: &HashMap<String, LayerDesc>) -> TeangaResult<Document> {
+        let mut doc = Document::new(content, meta)?;
+        for (name, layer) in doc.content.iter_mut() {
+            let referenced = meta.values().any(|m|
+                m.base.as_ref() == Some(name) || m.target.as_ref() == Some(name));
+            if !referenced {
+                *layer = layer.sorted(&meta[name]);
+            }
+        }
+        Ok(doc)
+    }
+
+    /// Join several documents into one. The characters layers are joined
+    /// with a separator and the indexes and links of the other layers are
+    /// offset by the length of the layers they refer to in the preceding
+    /// documents
+    ///
+    /// # Arguments
+    ///
+    /// * `docs` - The documents to join, which must all have the same layers
+    /// * `sep` - The separator between the characters layers of the documents
+    /// * `meta` - The metadata for the documents
+    ///
+    /// # Returns
+    ///
+    /// The joined document
+    pub fn concat(docs : Vec<Document>, sep : &str, meta : &HashMap<String, LayerDesc>) -> TeangaResult<Document> {
+        let names : Vec<String> = match docs.first() {
+            Some(doc) => doc.keys().into_iter().sorted().collect(),
+            None => return Err(TeangaError::ModelError(
+                "Cannot concatenate an empty list of documents".to_string()))
+        };
+        if let Some(doc) = docs.iter().find(|doc| doc.keys().into_iter().sorted().collect::<Vec<_>>() != names) {
+            return Err(TeangaError::ModelError(
+                format!("Cannot concatenate documents with layers {} and {}",
+                    names.join(", "), doc.keys().into_iter().sorted().join(", "))));
+        }
+        // The offset of each layer in each document
+        let mut offsets : HashMap<&str, Vec<u32>> = HashMap::new();
+        for name in names.iter() {
+            let mut offset = 0;
+            let sep_len = if docs[0][name.as_str()].characters().is_some() { sep.len() } else { 0 };
+            offsets.insert(name, docs.iter().map(|doc| {
+                let o = offset;
+                offset += (doc[name.as_str()].len() + sep_len) as u32;
+                o
+            }).collect());
+        }
+        let mut content = HashMap::new();
+        for name in names.iter() {
+            let layer_desc = meta.get(name).ok_or_else(|| TeangaError::LayerNotFoundError(name.clone()))?;
+            let base_offsets = layer_desc.base.as_ref().and_then(|b| offsets.get(b.as_str()));
+            let link_offsets = layer_desc.target.as_ref().or(layer_desc.base.as_ref())
+                .and_then(|t| offsets.get(t.as_str()))
+                .filter(|_| layer_desc.data == Some(DataType::Link));
+            let mut parts = Vec::new();
+            for (i, doc) in docs.iter().enumerate() {
+                let mut layer = doc[name.as_str()].clone();
+                if let Some(offsets) = base_offsets {
+                    if layer_desc.layer_type != LayerType::seq {
+                        layer = shift_indexes(layer, &layer_desc.layer_type, offsets[i]);
+                    }
+                }
+                if let Some(offsets) = link_offsets {
+                    layer = crate::remap_links(&layer, &layer_desc.layer_type, |l| Some(l + offsets[i]));
+                }
+                parts.push(layer);
+            }
+            content.insert(name.clone(), concat_layers(parts, sep)?);
+        }
+        Document::new(content, meta)
+    }
+
+    /// Split the layers of this document at positions in its text, see
+    /// `WriteableCorpus::split_document`
+    ///
+    /// # Arguments
+    ///
+    /// * `char_positions` - The byte offsets in the text to split at
+    /// * `meta` - The metadata for the document
+    ///
+    /// # Returns
+    ///
+    /// The content of each new document
+    pub(crate) fn split_layers(&self, char_positions : &[usize],
+        meta : &HashMap<String, LayerDesc>) -> TeangaResult<Vec<Vec<(String, Layer)>>> {
+        let layers = self.keys();
+        let text_layers : Vec<&String> = layers.iter()
+            .filter(|l| self[l.as_str()].characters().is_some())
+            .collect();
+        let [text_layer] = text_layers.as_slice() else {
+            return Err(TeangaError::ModelError(
+                "The document must have exactly one characters layer to be split".to_string()));
+        };
+        let text_len = self[text_layer.as_str()].len();
+        let mut positions = char_positions.to_vec();
+        positions.sort();
+        positions.dedup();
+        if let Some(pos) = positions.iter().find(|p| **p == 0 || **p >= text_len) {
+            return Err(TeangaError::ModelError(
+                format!("Cannot split the document at {} as its text has length {}", pos, text_len)));
+        }
+        let is_dependency = |name : &str| layers.iter().any(|l| meta.get(l).is_some_and(|d|
+            d.base.as_deref() == Some(name) || d.link_target() == Some(name)));
+        let graph = LayerGraph::new(meta);
+        // The split positions in each layer, with the start and end of the layer,
+        // or None if the layer cannot be split consistently
+        let mut bounds : HashMap<&str, Option<Vec<usize>>> = HashMap::new();
+        let mut pieces : HashMap<&str, Vec<Layer>> = HashMap::new();
+        for name in graph.topo_order()? {
+            let Some(layer) = self.get(name) else {
+                continue;
+            };
+            let layer_desc = &meta[name];
+            let base_bounds = match layer_desc.base {
+                Some(ref base) => match bounds.get(base.as_str()) {
+                    Some(Some(b)) => b.clone(),
+                    Some(None) => return Err(TeangaError::ModelError(
+                        format!("Cannot split layer {} as its base layer {} has annotations across a split position", name, base))),
+                    None => return Err(TeangaError::LayerNotFoundError(base.clone()))
+                },
+                None => [0].into_iter().chain(positions.iter().copied()).chain([text_len]).collect()
+            };
+            let base_len = base_bounds[base_bounds.len() - 1];
+            let mut rest = layer.clone();
+            let mut layer_pieces = Vec::new();
+            for k in 1..base_bounds.len() - 1 {
+                let (left, right) = rest.split_at(base_bounds[k] - base_bounds[k - 1],
+                    base_len - base_bounds[k - 1], layer_desc, SplitPolicy::Drop)?;
+                layer_pieces.push(left);
+                rest = right;
+            }
+            layer_pieces.push(rest);
+            let layer_bounds : Vec<usize> = [0].into_iter()
+                .chain(layer_pieces.iter().scan(0, |n, p| { *n += p.len(); Some(*n) }))
+                .collect();
+            if layer_bounds[layer_bounds.len() - 1] == layer.len() {
+                bounds.insert(name, Some(layer_bounds));
+            } else {
+                bounds.insert(name, None);
+            }
+            pieces.insert(name, layer_pieces);
+        }
+        for (name, layer_pieces) in pieces.iter_mut() {
+            let layer_desc = &meta[*name];
+            let Some(target) = layer_desc.link_target() else {
+                continue;
+            };
+            let Some(Some(target_bounds)) = bounds.get(target) else {
+                return Err(TeangaError::ModelError(
+                    format!("Cannot split layer {} as the layer {} it links to cannot be split", name, target)));
+            };
+            for (k, piece) in layer_pieces.iter_mut().enumerate() {
+                let (start, end) = (target_bounds[k], target_bounds[k + 1]);
+                let remapped = crate::remap_links(piece, &layer_desc.layer_type,
+                    |l| if (l as usize) >= start && (l as usize) < end {
+                        Some(l - start as u32)
+                    } else {
+                        None
+                    });
+                if remapped.len() != piece.len() &&
+                    (layer_desc.layer_type == LayerType::seq || is_dependency(name)) {
+                    return Err(TeangaError::ModelError(
+                        format!("Cannot split layer {} as it has links across a split position", name)));
+                }
+                *piece = remapped;
+            }
+        }
+        Ok((0..=positions.len()).map(|k| pieces.iter()
+            .map(|(name, p)| (name.to_string(), p[k].clone()))
+            .collect()).collect())
+    }
+
+    /// Split this document into a document for each segment of a div layer,
+    /// e.g., to get a document for each sentence. Every layer is split and
+    /// its indexes are rebased to the new documents. The document must have
+    /// a single characters layer
+    ///
+    /// # Arguments
+    ///
+    /// * `boundary_layer` - The div layer whose segments become documents
+    /// * `meta` - The metadata for the document
+    ///
+    /// # Returns
+    ///
+    /// A document for each segment, or an `IndexingError` if an annotation
+    /// crosses a boundary between segments. Other div layers are cut at the
+    /// boundaries
+    pub fn split_on(&self, boundary_layer : &str, meta : &HashMap<String, LayerDesc>) -> TeangaResult<Vec<Document>> {
+        let layer_desc = meta.get(boundary_layer)
+            .ok_or_else(|| TeangaError::LayerNotFoundError(boundary_layer.to_string()))?;
+        if layer_desc.layer_type != LayerType::div {
+            return Err(TeangaError::ModelError(
+                format!("Cannot split on layer {} as it is not a div layer", boundary_layer)));
+        }
+        let graph = LayerGraph::new(meta);
+        let char_layer = graph.root_of(boundary_layer)?;
+        let boundaries : Vec<usize> = self.indexes(boundary_layer, char_layer, meta)?
+            .into_iter().map(|(start, _)| start).filter(|start| *start > 0).collect();
+        for name in self.keys().into_iter().sorted() {
+            let layer_desc = meta.get(&name)
+                .ok_or_else(|| TeangaError::LayerNotFoundError(name.clone()))?;
+            if matches!(layer_desc.layer_type, LayerType::characters | LayerType::div) ||
+                graph.root_of(&name)? != char_layer {
+                continue;
+            }
+            for (start, end) in self.indexes(&name, char_layer, meta)? {
+                if boundaries.iter().any(|b| start < *b && *b < end) {
+                    return Err(TeangaError::IndexingError(name, boundary_layer.to_string()));
+                }
+            }
+        }
+        self.split_layers(&boundaries, meta)?.into_iter()
+            .map(|content| Document::new(content, meta))
+            .collect()
+    }
+
     /// Get the text that is indexed by a particular layer
     /// divided by the annotations in this layer
     ///
@@ -178,6 +421,211 @@ impl Document {
         }
     }
 
+    /// Check that every link in the document refers to an annotation that
+    /// exists in the target layer (or the base layer if the layer has no
+    /// target)
+    ///
+    /// # Arguments
+    ///
+    /// * `meta` - The metadata for the document
+    ///
+    /// # Returns
+    ///
+    /// Nothing if all links are valid, otherwise an error for each link that
+    /// is out of range. Links into a layer that the document does not have
+    /// and that has no default are not checked
+    pub fn validate_links(&self, meta : &HashMap<String, LayerDesc>) -> Result<(), Vec<LinkError>> {
+        let mut errors = Vec::new();
+        for name in self.content.keys().sorted() {
+            let layer_desc = match meta.get(name) {
+                Some(layer_desc) => layer_desc,
+                None => continue
+            };
+            let target_name = match layer_desc.link_target() {
+                Some(target_name) => target_name,
+                None => continue
+            };
+            // A target layer that is only given by the default in the
+            // metadata is resolved, and links into a missing layer are not
+            // checked
+            let target_len = match self.content.get(target_name)
+                .or_else(|| meta.get(target_name).and_then(|d| d.default.as_ref())) {
+                Some(target) => target.len(),
+                None => continue
+            };
+            for (index, data) in self.content[name].data(layer_desc).into_iter().enumerate() {
+                let target = match data {
+                    TeangaData::Link(target) => target,
+                    TeangaData::TypedLink(target, _) => target,
+                    _ => continue
+                };
+                if target as usize >= target_len {
+                    errors.push(LinkError {
+                        layer: name.clone(),
+                        index,
+                        target
+                    });
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Find the annotation that a link refers to
+    ///
+    /// # Arguments
+    ///
+    /// * `layer` - The layer with link data
+    /// * `ann_idx` - The index of the annotation with the link
+    /// * `meta` - The metadata for the document
+    ///
+    /// # Returns
+    ///
+    /// The name of the layer the link refers to and the index of the
+    /// annotation in that layer
+    pub fn resolve_link(&self, layer : &str, ann_idx : usize,
+        meta : &HashMap<String, LayerDesc>) -> TeangaResult<(String, usize)> {
+        let layer_desc = meta.get(layer)
+            .ok_or_else(|| TeangaError::LayerNotFoundError(layer.to_string()))?;
+        let target_name = layer_desc.link_target()
+            .ok_or_else(|| TeangaError::ModelError(
+                format!("Layer {} does not have link data", layer)))?;
+        let data = self.content.get(layer)
+            .ok_or_else(|| TeangaError::LayerNotFoundError(layer.to_string()))?
+            .data(layer_desc);
+        match data.into_iter().nth(ann_idx) {
+            Some(TeangaData::Link(target)) | Some(TeangaData::TypedLink(target, _)) =>
+                Ok((target_name.to_string(), target as usize)),
+            Some(_) => Err(TeangaError::ModelError(
+                format!("Annotation {} of layer {} is not a link", ann_idx, layer))),
+            None => Err(TeangaError::ModelError(
+                format!("Layer {} has no annotation {}", layer, ann_idx)))
+        }
+    }
+
+    /// Tag each token with the entity it belongs to in the BIO scheme
+    /// (`B-LABEL` for the first token of an entity, `I-LABEL` for the
+    /// following tokens and `O` for tokens outside any entity). The label
+    /// is the string data of the entity or the name of the span layer if
+    /// it has no string data.
+    ///
+    /// A token belongs to an entity if their text overlaps, even partially.
+    /// If a token overlaps several (e.g., nested) entities, the longest entity
+    /// wins, and of entities of the same length the first.
+    ///
+    /// # Arguments
+    ///
+    /// * `token_layer` - The layer with the tokens
+    /// * `span_layer` - The layer with the entities
+    /// * `meta` - The metadata for the document
+    ///
+    /// # Returns
+    ///
+    /// One tag for each token
+    pub fn to_bio(&self, token_layer : &str, span_layer : &str,
+        meta : &HashMap<String, LayerDesc>) -> TeangaResult<Vec<String>> {
+        let graph = LayerGraph::new(meta);
+        let char_layer = graph.root_of(token_layer)?;
+        if graph.root_of(span_layer)? != char_layer {
+            return Err(TeangaError::ModelError(
+                format!("Layers {} and {} are not based on the same text", token_layer, span_layer)));
+        }
+        let tokens = self.indexes(token_layer, char_layer, meta)?;
+        let mut entities = self.indexes_data(span_layer, char_layer, meta)?;
+        entities.sort_by_key(|(start, end, _)| (std::cmp::Reverse(end - start), *start));
+        let mut tags = Vec::new();
+        let mut prev = None;
+        for (start, end) in tokens {
+            let entity = entities.iter().position(|(s, e, _)| *s < end && start < *e);
+            tags.push(match entity {
+                Some(i) => {
+                    let label = match entities[i].2 {
+                        TeangaData::String(ref label) => label.as_str(),
+                        _ => span_layer
+                    };
+                    if prev == Some(i) {
+                        format!("I-{}", label)
+                    } else {
+                        format!("B-{}", label)
+                    }
+                },
+                None => "O".to_string()
+            });
+            prev = entity;
+        }
+        Ok(tags)
+    }
+
+    /// Add a span layer from BIO tags for each token, the reverse of
+    /// `to_bio`. The new layer must be declared in the metadata and be based
+    /// either on the token layer, in which case the spans are token indexes,
+    /// or on the characters layer of the tokens, in which case the spans are
+    /// resolved to the text. If the new layer has data, the labels of the
+    /// tags are stored as its values.
+    ///
+    /// An `I-LABEL` tag that does not continue an entity with the same label
+    /// (e.g., after `O` or at the start) starts a new entity as if it were
+    /// `B-LABEL`.
+    ///
+    /// # Arguments
+    ///
+    /// * `token_layer` - The layer with the tokens
+    /// * `tags` - One tag for each token
+    /// * `new_layer` - The name of the layer to add
+    /// * `meta` - The metadata for the document
+    pub fn add_spans_from_bio(&mut self, token_layer : &str, tags : &[String],
+        new_layer : &str, meta : &HashMap<String, LayerDesc>) -> TeangaResult<()> {
+        let graph = LayerGraph::new(meta);
+        let char_layer = graph.root_of(token_layer)?;
+        let tokens = self.indexes(token_layer, char_layer, meta)?;
+        if tags.len() != tokens.len() {
+            return Err(TeangaError::ModelError(
+                format!("Expected {} tags but got {}", tokens.len(), tags.len())));
+        }
+        let layer_desc = meta.get(new_layer)
+            .ok_or_else(|| TeangaError::LayerNotFoundError(new_layer.to_string()))?;
+        let base = layer_desc.base.as_deref();
+        if base != Some(token_layer) && base != Some(char_layer) {
+            return Err(TeangaError::ModelError(
+                format!("Layer {} must be based on {} or {}", new_layer, token_layer, char_layer)));
+        }
+        let mut entities : Vec<(usize, usize, String)> = Vec::new();
+        let mut open : Option<(usize, &str)> = None;
+        for (i, tag) in tags.iter().enumerate() {
+            let (prefix, label) = match tag.split_once('-') {
+                Some((prefix, label)) if prefix == "B" || prefix == "I" => (prefix, label),
+                _ if tag == "O" => ("O", ""),
+                _ => return Err(TeangaError::ModelError(format!("Invalid BIO tag {}", tag)))
+            };
+            match open {
+                Some((_, open_label)) if prefix == "I" && open_label == label => continue,
+                Some((start, open_label)) => entities.push((start, i, open_label.to_string())),
+                None => {}
+            }
+            open = if prefix == "O" { None } else { Some((i, label)) };
+        }
+        if let Some((start, label)) = open {
+            entities.push((start, tags.len(), label.to_string()));
+        }
+        let spans : Vec<(u32, u32, String)> = entities.into_iter()
+            .map(|(start, end, label)| if base == Some(token_layer) {
+                (start as u32, end as u32, label)
+            } else {
+                (tokens[start].0 as u32, tokens[end - 1].1 as u32, label)
+            }).collect();
+        let layer = if layer_desc.data.is_some() {
+            Layer::L2S(spans)
+        } else {
+            Layer::L2(spans.into_iter().map(|(start, end, _)| (start, end)).collect())
+        };
+        self.content.insert(new_layer.to_string(), layer);
+        Ok(())
+    }
+
     /// Get the names of layers in this document
     pub fn keys(&self) -> Vec<String> {
         self.content.keys().cloned().collect()
@@ -200,6 +648,48 @@ impl Document {
     pub fn set(&mut self, key: &str, value: Layer) {
         self.content.insert(key.to_string(), value);
     }
+
+    /// Create a copy of this document with only some of its layers. The
+    /// layers that the named layers are based on or target are also included
+    /// (recursively), so that the indexes of the copy remain valid
+    ///
+    /// # Arguments
+    ///
+    /// * `layers` - The names of the layers to keep
+    /// * `meta` - The metadata for the document
+    ///
+    /// # Returns
+    ///
+    /// A new document with the named layers and their dependencies
+    pub fn project(&self, layers : &[&str], meta : &HashMap<String, LayerDesc>) -> Document {
+        let mut content = HashMap::new();
+        let mut todo : Vec<&str> = layers.to_vec();
+        while let Some(name) = todo.pop() {
+            if content.contains_key(name) {
+                continue;
+            }
+            if let Some(layer) = self.content.get(name) {
+                content.insert(name.to_string(), layer.clone());
+            }
+            if let Some(layer_desc) = meta.get(name) {
+                todo.extend(layer_desc.base.iter().chain(layer_desc.target.iter())
+                    .map(|s| s.as_str()).filter(|s| !s.is_empty()));
+            }
+        }
+        Document { content }
+    }
+
+    /// Remove a layer from the document
+    ///
+    /// **Note**: If you remove a character layer this may change the identifier
+    /// of the document
+    ///
+    /// # Returns
+    ///
+    /// The removed layer or `None` if the document does not have this layer
+    pub fn remove(&mut self, key: &str) -> Option<Layer> {
+        self.content.remove(key)
+    }
 }
 
 impl IntoIterator for Document {
@@ -248,8 +738,7 @@ impl<'a, C : Corpus> DocumentBuilder<'a, C> {
     /// or the data provided is not valid for that layer's metadata
     pub fn layer<I : IntoLayer>(mut self, name: &str, layer: I) -> TeangaResult<DocumentBuilder<'a, C>> {
         let layer_desc = self.0.get_meta().get(name)
-                .ok_or_else(|| TeangaError::ModelError(
-                    format!("Layer {} does not exist", name)))?;
+                .ok_or_else(|| TeangaError::LayerNotFoundError(name.to_string()))?;
         self.1.insert(name.to_string(), layer.into_layer(layer_desc)?);
         Ok(self)
     }
@@ -264,12 +753,190 @@ impl<'a, C : Corpus> DocumentBuilder<'a, C> {
     }
 }
 
+/// Add an offset to the indexes of a layer in its base layer
+fn shift_indexes(layer : Layer, layer_type : &LayerType, o : u32) -> Layer {
+    match (layer, layer_type) {
+        (Layer::L1(v), _) => Layer::L1(v.into_iter().map(|i| i + o).collect()),
+        (Layer::L2(v), LayerType::span) => Layer::L2(v.into_iter().map(|(s, e)| (s + o, e + o)).collect()),
+        (Layer::L2(v), _) => Layer::L2(v.into_iter().map(|(i, l)| (i + o, l)).collect()),
+        (Layer::L3(v), _) => Layer::L3(v.into_iter().map(|(s, e, l)| (s + o, e + o, l)).collect()),
+        (Layer::L1S(v), _) => Layer::L1S(v.into_iter().map(|(i, d)| (i + o, d)).collect()),
+        (Layer::L2S(v), LayerType::span) => Layer::L2S(v.into_iter().map(|(s, e, d)| (s + o, e + o, d)).collect()),
+        (Layer::L2S(v), _) => Layer::L2S(v.into_iter().map(|(i, l, d)| (i + o, l, d)).collect()),
+        (Layer::L3S(v), _) => Layer::L3S(v.into_iter().map(|(s, e, l, d)| (s + o, e + o, l, d)).collect()),
+        (layer, _) => layer
+    }
+}
+
+/// Join layers of the same kind
+fn concat_layers(parts : Vec<Layer>, sep : &str) -> TeangaResult<Layer> {
+    let mut iter = parts.into_iter();
+    let mut result = iter.next().unwrap_or(Layer::LS(Vec::new()));
+    for part in iter {
+        match (&mut result, part) {
+            (Layer::Characters(a), Layer::Characters(b)) => {
+                a.push_str(sep);
+                a.push_str(&b);
+            },
+            (Layer::L1(a), Layer::L1(b)) => a.extend(b),
+            (Layer::L2(a), Layer::L2(b)) => a.extend(b),
+            (Layer::L3(a), Layer::L3(b)) => a.extend(b),
+            (Layer::LS(a), Layer::LS(b)) => a.extend(b),
+            (Layer::L1S(a), Layer::L1S(b)) => a.extend(b),
+            (Layer::L2S(a), Layer::L2S(b)) => a.extend(b),
+            (Layer::L3S(a), Layer::L3S(b)) => a.extend(b),
+            (Layer::MetaLayer(a), Layer::MetaLayer(b)) => a.extend(b),
+            _ => return Err(TeangaError::ModelError(
+                "Cannot concatenate layers of different kinds".to_string()))
+        }
+    }
+    Ok(result)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::SimpleCorpus;
+    use crate::{SimpleCorpus, Value};
     use crate::layer::{LayerType, DataType};
 
+    #[test]
+    fn test_concat() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("pos").base("words").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        corpus.build_layer("dep").base("words").layer_type(LayerType::seq)
+            .data(DataType::Link).add().unwrap();
+        corpus.build_layer("sentences").base("text").layer_type(LayerType::div).add().unwrap();
+        let meta = corpus.get_meta();
+        let doc1 = Document::new(vec![
+            ("text".to_string(), Layer::Characters("Dia duit.".to_string())),
+            ("words".to_string(), Layer::L2(vec![(0, 3), (4, 8), (8, 9)])),
+            ("pos".to_string(), Layer::LS(vec!["NOUN".to_string(), "NOUN".to_string(), "PUNCT".to_string()])),
+            ("dep".to_string(), Layer::L1(vec![1, 1, 1])),
+            ("sentences".to_string(), Layer::L1(vec![0]))], meta).unwrap();
+        let doc2 = Document::new(vec![
+            ("text".to_string(), Layer::Characters("Slán.".to_string())),
+            ("words".to_string(), Layer::L2(vec![(0, 5), (5, 6)])),
+            ("pos".to_string(), Layer::LS(vec!["INTJ".to_string(), "PUNCT".to_string()])),
+            ("dep".to_string(), Layer::L1(vec![0, 0])),
+            ("sentences".to_string(), Layer::L1(vec![0]))], meta).unwrap();
+        let doc = Document::concat(vec![doc1.clone(), doc2], " ", meta).unwrap();
+        assert_eq!(doc["text"], Layer::Characters("Dia duit. Slán.".to_string()));
+        assert_eq!(doc["words"], Layer::L2(vec![(0, 3), (4, 8), (8, 9), (10, 15), (15, 16)]));
+        assert_eq!(doc.text("words", meta).unwrap(), vec!["Dia", "duit", ".", "Slán", "."]);
+        assert_eq!(doc["dep"], Layer::L1(vec![1, 1, 1, 3, 3]));
+        assert_eq!(doc["sentences"], Layer::L1(vec![0, 10]));
+        assert_eq!(doc.validate_links(meta), Ok(()));
+        let doc3 = Document::new(vec![
+            ("text".to_string(), Layer::Characters("Slán.".to_string()))], meta).unwrap();
+        assert!(Document::concat(vec![doc1, doc3], " ", meta).is_err());
+    }
+
+    #[test]
+    fn test_split_on() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("pos").base("words").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        corpus.build_layer("dep").base("words").layer_type(LayerType::seq)
+            .data(DataType::Link).add().unwrap();
+        corpus.build_layer("sentences").base("text").layer_type(LayerType::div).add().unwrap();
+        corpus.build_layer("entities").base("text").layer_type(LayerType::span).add().unwrap();
+        let meta = corpus.get_meta();
+        let doc = Document::new(vec![
+            ("text".to_string(), Layer::Characters("Dia duit. Slán.".to_string())),
+            ("words".to_string(), Layer::L2(vec![(0, 3), (4, 8), (8, 9), (10, 15), (15, 16)])),
+            ("pos".to_string(), Layer::LS(vec!["NOUN", "NOUN", "PUNCT", "INTJ", "PUNCT"]
+                .into_iter().map(|s| s.to_string()).collect())),
+            ("dep".to_string(), Layer::L1(vec![1, 1, 1, 3, 3])),
+            ("sentences".to_string(), Layer::L1(vec![0, 10]))], meta).unwrap();
+        let docs = doc.split_on("sentences", meta).unwrap();
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs[0]["text"], Layer::Characters("Dia duit. ".to_string()));
+        assert_eq!(docs[1]["text"], Layer::Characters("Slán.".to_string()));
+        assert_eq!(docs[1]["words"], Layer::L2(vec![(0, 5), (5, 6)]));
+        assert_eq!(docs[1]["pos"], Layer::LS(vec!["INTJ".to_string(), "PUNCT".to_string()]));
+        assert_eq!(docs[1]["dep"], Layer::L1(vec![0, 0]));
+        assert_eq!(docs[1]["sentences"], Layer::L1(vec![0]));
+        let mut doc = doc;
+        doc.content.insert("entities".to_string(), Layer::L2(vec![(4, 15)]));
+        assert!(matches!(doc.split_on("sentences", meta),
+            Err(TeangaError::IndexingError(l, _)) if l == "entities"));
+        assert!(doc.split_on("words", meta).is_err());
+    }
+
+    #[test]
+    fn test_resolve_link() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("dep").base("words").layer_type(LayerType::seq)
+            .data(DataType::Link).add().unwrap();
+        corpus.build_layer("coref").base("words").layer_type(LayerType::element)
+            .data(DataType::Link).target("words").add().unwrap();
+        let id = corpus.build_doc()
+            .layer("text", "Seán said he left").unwrap()
+            .layer("words", vec![(0, 5), (6, 10), (11, 13), (14, 18)]).unwrap()
+            .layer("dep", vec![1u32, 1, 3, 1]).unwrap()
+            .layer("coref", vec![(2u32, 0u32)]).unwrap()
+            .add().unwrap();
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        let meta = corpus.get_meta();
+        assert_eq!(meta["dep"].link_target(), Some("words"));
+        assert_eq!(meta["words"].link_target(), None);
+        assert_eq!(doc.resolve_link("dep", 2, meta).unwrap(), ("words".to_string(), 3));
+        assert_eq!(doc.resolve_link("coref", 0, meta).unwrap(), ("words".to_string(), 0));
+        assert!(doc.resolve_link("dep", 4, meta).is_err());
+        assert!(doc.resolve_link("words", 0, meta).is_err());
+    }
+
+    #[test]
+    fn test_to_bio() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("tokens").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("entities").base("text").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        let id = corpus.build_doc()
+            .layer("text", "The White House is in Washington D.C.").unwrap()
+            .layer("tokens", vec![(0, 3), (4, 9), (10, 15), (16, 18), (19, 21), (22, 32), (33, 37)]).unwrap()
+            .layer("entities", vec![(4, 15, "ORG"), (22, 32, "LOC"), (22, 37, "GPE")]).unwrap()
+            .add().unwrap();
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        assert_eq!(doc.to_bio("tokens", "entities", corpus.get_meta()).unwrap(),
+            vec!["O", "B-ORG", "I-ORG", "O", "O", "B-GPE", "I-GPE"]);
+    }
+
+    #[test]
+    fn test_add_spans_from_bio() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("tokens").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("entities").base("text").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        corpus.build_layer("token_entities").base("tokens").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        let id = corpus.build_doc()
+            .layer("text", "The White House is in Washington D.C.").unwrap()
+            .layer("tokens", vec![(0, 3), (4, 9), (10, 15), (16, 18), (19, 21), (22, 32), (33, 37)]).unwrap()
+            .add().unwrap();
+        let mut doc = corpus.get_doc_by_id(&id).unwrap();
+        let tags : Vec<String> = vec!["O", "B-ORG", "I-ORG", "O", "I-LOC", "B-GPE", "I-GPE"]
+            .into_iter().map(|t| t.to_string()).collect();
+        doc.add_spans_from_bio("tokens", &tags, "entities", corpus.get_meta()).unwrap();
+        doc.add_spans_from_bio("tokens", &tags, "token_entities", corpus.get_meta()).unwrap();
+        assert_eq!(doc.get("entities"), Some(&Layer::L2S(vec![
+            (4, 15, "ORG".to_string()), (19, 21, "LOC".to_string()), (22, 37, "GPE".to_string())])));
+        assert_eq!(doc.get("token_entities"), Some(&Layer::L2S(vec![
+            (1, 3, "ORG".to_string()), (4, 5, "LOC".to_string()), (5, 7, "GPE".to_string())])));
+        assert_eq!(doc.to_bio("tokens", "entities", corpus.get_meta()).unwrap()[4], "B-LOC");
+        assert!(doc.add_spans_from_bio("tokens", &tags[1..], "entities", corpus.get_meta()).is_err());
+        assert!(doc.add_spans_from_bio("tokens", &vec!["X".to_string(); 7], "entities", corpus.get_meta()).is_err());
+    }
+
     #[test]
     fn test_corpus_layers() {
         let mut corpus = SimpleCorpus::new();
@@ -296,5 +963,134 @@ mod tests {
         eprintln!("{:?}", doc.indexes("entities", "text", corpus.get_meta()));
         assert_eq!(doc.text("entities", corpus.get_meta()).unwrap(), vec!["White House", "Washington"]);
     }
-}
 
+    #[test]
+    fn test_new_sorted() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("tokens").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("pos").base("tokens").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        corpus.build_layer("entities").base("tokens").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        let doc = Document::new_sorted(vec![
+            ("text".to_string(), Layer::Characters("Dublin is in Ireland".to_string())),
+            ("tokens".to_string(), Layer::L2(vec![(7, 9), (0, 6), (10, 12), (13, 20)])),
+            ("pos".to_string(), Layer::LS(vec!["VERB".to_string(), "PROPN".to_string(), "ADP".to_string(), "PROPN".to_string()])),
+            ("entities".to_string(), Layer::L2S(vec![(3, 4, "LOC".to_string()), (1, 2, "LOC".to_string())]))
+        ], corpus.get_meta()).unwrap();
+        // tokens are the base of other layers so are left unchanged
+        assert_eq!(doc.get("tokens"), Some(&Layer::L2(vec![(7, 9), (0, 6), (10, 12), (13, 20)])));
+        assert_eq!(doc.get("entities"), Some(&Layer::L2S(vec![(1, 2, "LOC".to_string()), (3, 4, "LOC".to_string())])));
+        assert_eq!(Layer::L2(vec![(7, 9), (0, 6)]).sorted(&corpus.get_meta()["tokens"]),
+            Layer::L2(vec![(0, 6), (7, 9)]));
+    }
+
+    #[test]
+    fn test_validate_links() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("tokens").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("dep").base("tokens").layer_type(LayerType::seq)
+            .data(DataType::Link).add().unwrap();
+        let meta = corpus.get_meta();
+        let doc = Document::new(vec![
+            ("text".to_string(), Layer::Characters("I saw it".to_string())),
+            ("tokens".to_string(), Layer::L2(vec![(0, 1), (2, 5), (6, 8)])),
+            ("dep".to_string(), Layer::L1(vec![1, 1, 1]))], meta).unwrap();
+        assert!(doc.validate_links(meta).is_ok());
+        let doc = Document::new(vec![
+            ("text".to_string(), Layer::Characters("I saw it".to_string())),
+            ("tokens".to_string(), Layer::L2(vec![(0, 1), (2, 5), (6, 8)])),
+            ("dep".to_string(), Layer::L1(vec![2, 3, 2]))], meta).unwrap();
+        assert_eq!(doc.validate_links(meta), Err(vec![LinkError {
+            layer: "dep".to_string(), index: 1, target: 3 }]));
+        corpus.build_layer("sentences").base("text").layer_type(LayerType::div)
+            .default(Layer::L1(vec![0])).add().unwrap();
+        corpus.build_layer("sentence").base("tokens").layer_type(LayerType::seq)
+            .data(DataType::Link).target("sentences").add().unwrap();
+        let meta = corpus.get_meta();
+        let doc = Document::new(vec![
+            ("text".to_string(), Layer::Characters("I saw it".to_string())),
+            ("tokens".to_string(), Layer::L2(vec![(0, 1), (2, 5), (6, 8)])),
+            ("sentence".to_string(), Layer::L1(vec![0, 0, 0]))], meta).unwrap();
+        assert!(doc.validate_links(meta).is_ok());
+        let doc = Document::new(vec![
+            ("text".to_string(), Layer::Characters("I saw it".to_string())),
+            ("tokens".to_string(), Layer::L2(vec![(0, 1), (2, 5), (6, 8)])),
+            ("sentence".to_string(), Layer::L1(vec![0, 1, 0]))], meta).unwrap();
+        assert_eq!(doc.validate_links(meta), Err(vec![LinkError {
+            layer: "sentence".to_string(), index: 1, target: 1 }]));
+    }
+
+    #[test]
+    fn test_meta_layer() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("_meta_info").add().unwrap();
+        let mut info = HashMap::new();
+        info.insert("source".to_string(), Value::String("wikipedia".to_string()));
+        info.insert("year".to_string(), Value::Int(2024));
+        let id = corpus.build_doc()
+            .layer("text", "This is a document.").unwrap()
+            .layer("_meta_info", info.clone()).unwrap()
+            .add().unwrap();
+        assert_eq!(id, "Kjco");
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        assert_eq!(doc.get("_meta_info"), Some(&Layer::MetaLayer(vec![info])));
+    }
+
+    #[test]
+    fn test_remove_layer() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("tokens").base("text").layer_type(LayerType::span).add().unwrap();
+        let id = corpus.build_doc()
+            .layer("text", "Hello world").unwrap()
+            .layer("tokens", vec![(0, 5), (6, 11)]).unwrap()
+            .add().unwrap();
+        let mut doc = corpus.get_doc_by_id(&id).unwrap();
+        assert_eq!(doc.remove("tokens"), Some(Layer::L2(vec![(0, 5), (6, 11)])));
+        assert_eq!(doc.remove("tokens"), None);
+        assert_eq!(doc.keys(), vec!["text".to_string()]);
+    }
+
+    #[test]
+    fn test_project() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("tokens").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("pos").base("tokens").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        corpus.build_layer("entities").base("tokens").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        let id = corpus.build_doc()
+            .layer("text", "Dublin is big").unwrap()
+            .layer("tokens", vec![(0, 6), (7, 9), (10, 13)]).unwrap()
+            .layer("pos", vec!["PROPN", "AUX", "ADJ"]).unwrap()
+            .layer("entities", vec![(0, 1, "LOC")]).unwrap()
+            .add().unwrap();
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        let projected = doc.project(&["entities"], corpus.get_meta());
+        assert_eq!(projected.keys().into_iter().sorted().collect::<Vec<_>>(),
+            vec!["entities", "text", "tokens"]);
+        assert_eq!(projected.text("entities", corpus.get_meta()).unwrap(), vec!["Dublin"]);
+        assert_eq!(doc.keys().len(), 4);
+    }
+
+    #[test]
+    fn test_typed_links() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("tokens").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("dep").base("tokens").layer_type(LayerType::seq)
+            .data(DataType::Link).link_types(vec!["nsubj".to_string(), "root".to_string()])
+            .add().unwrap();
+        let meta = corpus.get_meta();
+        let dep = Layer::L1S(vec![(1, "nsubj".to_string()), (1, "root".to_string())]);
+        assert_eq!(dep.typed_links(&meta["dep"]).unwrap(),
+            vec![(0, 1, "nsubj".to_string()), (1, 1, "root".to_string())]);
+        assert!(Layer::L2(vec![(0, 1)]).typed_links(&meta["tokens"]).is_err());
+        assert!(Layer::L1(vec![1, 1]).typed_links(&meta["dep"]).is_err());
+    }
+}
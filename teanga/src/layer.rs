@@ -27,6 +27,9 @@ pub trait IntoLayer {
 
 impl IntoLayer for Layer {
     fn into_layer(self, _meta : &LayerDesc) -> TeangaResult<Layer> {
+        if let Layer::MetaLayer(ref values) = self {
+            check_meta_values(values)?;
+        }
         Ok(self)
     }
 }
@@ -145,6 +148,32 @@ impl IntoLayer for Vec<(u32, u32, u32, &'static str)> {
     }
 }
 
+impl IntoLayer for HashMap<String, Value> {
+    fn into_layer(self, meta : &LayerDesc) -> TeangaResult<Layer> {
+        Layer::MetaLayer(vec![self]).into_layer(meta)
+    }
+}
+
+impl IntoLayer for Vec<HashMap<String, Value>> {
+    fn into_layer(self, meta : &LayerDesc) -> TeangaResult<Layer> {
+        Layer::MetaLayer(self).into_layer(meta)
+    }
+}
+
+/// Check that the values can be serialized, that is they contain no NaN or
+/// infinite floats, as these cannot be represented in JSON
+pub(crate) fn check_meta_values<'a, I : IntoIterator<Item=&'a HashMap<String, Value>>>(values : I) -> TeangaResult<()> {
+    for map in values {
+        for (key, value) in map.iter() {
+            if !value.is_finite() {
+                return Err(TeangaError::ModelError(
+                    format!("Value for {} contains a NaN or infinite float", key)));
+            }
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug,Clone,Serialize,Deserialize,Default,PartialEq)]
 /// A layer description
 pub struct LayerDesc {
@@ -169,6 +198,7 @@ pub struct LayerDesc {
     /// The metadata for this layer
     #[serde(default)]
     #[serde(skip_serializing_if = "HashMap::is_empty")]
+    #[serde(serialize_with = "crate::serialization::serialize_sorted")]
     pub meta: HashMap<String, Value>, 
 }
 
@@ -187,6 +217,8 @@ impl LayerDesc {
                 format!("Layer {} of type {} must be based on another layer", name, layer_type)))
         }
 
+        check_meta_values([&meta])?;
+
         Ok(LayerDesc {
             layer_type,
             base,
@@ -197,6 +229,83 @@ impl LayerDesc {
             meta
          })
     }
+
+    /// Get the layer that the links of this layer refer to. This is the
+    /// target layer if one is given, otherwise the links refer to the
+    /// annotations of the base layer
+    ///
+    /// # Returns
+    ///
+    /// The name of the layer, or `None` if this layer does not have link data
+    pub fn link_target(&self) -> Option<&str> {
+        if self.data != Some(DataType::Link) {
+            return None;
+        }
+        self.target.as_deref().or(self.base.as_deref())
+            .filter(|t| !t.is_empty())
+    }
+
+    /// Record where the annotations of this layer came from. This is stored
+    /// in the metadata of the layer under the key `_provenance`
+    ///
+    /// # Arguments
+    ///
+    /// * `tool` - The name of the tool that created the annotations
+    /// * `version` - The version of the tool
+    /// * `uri` - A URI identifying the tool or the source of the annotations
+    pub fn set_provenance(&mut self, tool : &str, version : Option<&str>, uri : Option<&str>) {
+        self.meta.insert(PROVENANCE_KEY.to_string(), Provenance {
+            tool: tool.to_string(),
+            version: version.map(|v| v.to_string()),
+            uri: uri.map(|u| u.to_string())
+        }.into());
+    }
+
+    /// Get where the annotations of this layer came from, if this has been
+    /// recorded with `set_provenance`
+    pub fn provenance(&self) -> Option<Provenance> {
+        let vals = match self.meta.get(PROVENANCE_KEY) {
+            Some(Value::Object(vals)) => vals,
+            _ => return None
+        };
+        let string = |key : &str| match vals.get(key) {
+            Some(Value::String(s)) => Some(s.clone()),
+            _ => None
+        };
+        Some(Provenance {
+            tool: string("tool")?,
+            version: string("version"),
+            uri: string("uri")
+        })
+    }
+}
+
+/// The key in the layer metadata where the provenance is stored
+pub const PROVENANCE_KEY : &str = "_provenance";
+
+/// Where the annotations of a layer came from
+#[derive(Debug,Clone,PartialEq)]
+pub struct Provenance {
+    /// The name of the tool that created the annotations
+    pub tool: String,
+    /// The version of the tool
+    pub version: Option<String>,
+    /// A URI identifying the tool or the source of the annotations
+    pub uri: Option<String>
+}
+
+impl From<Provenance> for Value {
+    fn from(p : Provenance) -> Value {
+        let mut vals = HashMap::new();
+        vals.insert("tool".to_string(), Value::String(p.tool));
+        if let Some(version) = p.version {
+            vals.insert("version".to_string(), Value::String(version));
+        }
+        if let Some(uri) = p.uri {
+            vals.insert("uri".to_string(), Value::String(uri));
+        }
+        Value::Object(vals)
+    }
 }
 
 /// A layer in a document
@@ -429,6 +538,360 @@ impl Layer {
             .collect())
     }
 
+    /// Get the typed links of this layer
+    ///
+    /// # Arguments
+    ///
+    /// * `layer_desc` - The description of this layer
+    ///
+    /// # Returns
+    ///
+    /// For each annotation, its index in this layer, the index of the
+    /// annotation it links to and the type of the link, or an error if this
+    /// is not a typed link layer
+    pub fn typed_links(&self, layer_desc : &LayerDesc) -> TeangaResult<Vec<(usize, u32, String)>> {
+        if layer_desc.data != Some(DataType::Link) {
+            return Err(TeangaError::ModelError(
+                "Layer does not have link data".to_string()));
+        }
+        self.data(layer_desc).into_iter().enumerate().map(|(i, d)| match d {
+            TeangaData::TypedLink(target, link_type) => Ok((i, target, link_type)),
+            _ => Err(TeangaError::ModelError(
+                "Layer does not contain typed links".to_string()))
+        }).collect()
+    }
+
+    /// Get a copy of this layer with the annotations sorted by their start
+    /// index (then by their end index). Layers of type characters and seq
+    /// are returned unchanged as their order is given by the base layer.
+    ///
+    /// **Note**: Sorting changes the index of the annotations, so any seq
+    /// layer based on this layer or link layer targeting this layer will
+    /// refer to the wrong annotations unless it is remapped
+    ///
+    /// # Arguments
+    ///
+    /// * `layer_desc` - The description of this layer
+    pub fn sorted(&self, layer_desc : &LayerDesc) -> Layer {
+        if layer_desc.layer_type == LayerType::characters ||
+            layer_desc.layer_type == LayerType::seq {
+            return self.clone();
+        }
+        match self {
+            Layer::L1(indexes) => Layer::L1(indexes.iter().cloned().sorted().collect()),
+            Layer::L2(indexes) => Layer::L2(indexes.iter().cloned().sorted().collect()),
+            Layer::L3(indexes) => Layer::L3(indexes.iter().cloned().sorted().collect()),
+            Layer::L1S(indexes) => Layer::L1S(indexes.iter().cloned().sorted().collect()),
+            Layer::L2S(indexes) => Layer::L2S(indexes.iter().cloned().sorted().collect()),
+            Layer::L3S(indexes) => Layer::L3S(indexes.iter().cloned().sorted().collect()),
+            _ => self.clone()
+        }
+    }
+
+    /// Split this layer into two at a position in its base layer. Annotations
+    /// before the position are kept in the left layer and annotations after
+    /// it are moved to the right layer, with their indexes offset so that
+    /// they start from the position. For characters layers the text is
+    /// split at the byte offset `pos`, and seq layers are split after `pos`
+    /// values. Metadata layers are copied to both sides.
+    ///
+    /// Annotations that straddle the position are dropped or clipped to each
+    /// side depending on the policy. Div layers always cover the whole
+    /// base layer, so a straddling segment is always clipped.
+    ///
+    /// **Note**: Link data is not changed, so links to a layer that is also
+    /// split need to be remapped by the caller
+    ///
+    /// # Arguments
+    ///
+    /// * `pos` - The position in the base layer to split at
+    /// * `base_len` - The number of elements in the base layer
+    /// * `layer_desc` - The description of this layer
+    /// * `policy` - What to do with annotations that straddle the position
+    ///
+    /// # Returns
+    ///
+    /// The left and right layers
+    pub fn split_at(&self, pos : usize, base_len : usize, layer_desc : &LayerDesc,
+        policy : SplitPolicy) -> TeangaResult<(Layer, Layer)> {
+        if let Layer::Characters(c) = self {
+            if !c.is_char_boundary(pos) {
+                return Err(TeangaError::ModelError(
+                    format!("Cannot split text at {} as it is not a character boundary", pos)));
+            }
+            return Ok((Layer::Characters(c[..pos].to_string()),
+                Layer::Characters(c[pos..].to_string())));
+        }
+        if pos > base_len {
+            return Err(TeangaError::ModelError(
+                format!("Cannot split at {} as the base layer has length {}", pos, base_len)));
+        }
+        if let Layer::MetaLayer(_) = self {
+            return Ok((self.clone(), self.clone()));
+        }
+        let n = self.len();
+        if layer_desc.layer_type == LayerType::seq {
+            let split = |range : std::ops::Range<usize>| match self {
+                Layer::L1(v) => Layer::L1(v[range].to_vec()),
+                Layer::LS(v) => Layer::LS(v[range].to_vec()),
+                Layer::L1S(v) => Layer::L1S(v[range].to_vec()),
+                _ => self.clone()
+            };
+            return Ok((split(0..pos.min(n)), split(pos.min(n)..n)));
+        }
+        // The span of each annotation in the base layer
+        let spans : Vec<(usize, usize)> = match (self, &layer_desc.layer_type) {
+            (Layer::L2(v), LayerType::span) => v.iter().map(|a| (a.0 as usize, a.1 as usize)).collect(),
+            (Layer::L3(v), _) => v.iter().map(|a| (a.0 as usize, a.1 as usize)).collect(),
+            (Layer::L2S(v), LayerType::span) => v.iter().map(|a| (a.0 as usize, a.1 as usize)).collect(),
+            (Layer::L3S(v), _) => v.iter().map(|a| (a.0 as usize, a.1 as usize)).collect(),
+            _ => {
+                let starts : Vec<usize> = match self {
+                    Layer::L1(v) => v.iter().map(|a| *a as usize).collect(),
+                    Layer::L2(v) => v.iter().map(|a| a.0 as usize).collect(),
+                    Layer::L1S(v) => v.iter().map(|a| a.0 as usize).collect(),
+                    Layer::L2S(v) => v.iter().map(|a| a.0 as usize).collect(),
+                    _ => Vec::new()
+                };
+                if layer_desc.layer_type == LayerType::div {
+                    starts.iter().enumerate().map(|(i, s)|
+                        (*s, starts.get(i + 1).copied().unwrap_or(base_len))).collect()
+                } else {
+                    starts.iter().map(|s| (*s, s + 1)).collect()
+                }
+            }
+        };
+        let clip = policy == SplitPolicy::Clip || layer_desc.layer_type == LayerType::div;
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+        for (i, (start, end)) in spans.into_iter().enumerate() {
+            if end <= pos {
+                left.push((i, start as u32, end as u32));
+            } else if start >= pos {
+                right.push((i, (start - pos) as u32, (end - pos) as u32));
+            } else if clip {
+                left.push((i, start as u32, pos as u32));
+                right.push((i, 0, (end - pos) as u32));
+            }
+        }
+        let is_span = layer_desc.layer_type == LayerType::span;
+        let rebuild = |anns : Vec<(usize, u32, u32)>| match self {
+            Layer::L1(_) => Layer::L1(anns.into_iter().map(|(_, s, _)| s).collect()),
+            Layer::L2(_) if is_span => Layer::L2(anns.into_iter().map(|(_, s, e)| (s, e)).collect()),
+            Layer::L2(v) => Layer::L2(anns.into_iter().map(|(i, s, _)| (s, v[i].1)).collect()),
+            Layer::L3(v) => Layer::L3(anns.into_iter().map(|(i, s, e)| (s, e, v[i].2)).collect()),
+            Layer::L1S(v) => Layer::L1S(anns.into_iter().map(|(i, s, _)| (s, v[i].1.clone())).collect()),
+            Layer::L2S(v) if is_span => Layer::L2S(anns.into_iter().map(|(i, s, e)| (s, e, v[i].2.clone())).collect()),
+            Layer::L2S(v) => Layer::L2S(anns.into_iter().map(|(i, s, _)| (s, v[i].1, v[i].2.clone())).collect()),
+            Layer::L3S(v) => Layer::L3S(anns.into_iter().map(|(i, s, e)| (s, e, v[i].2, v[i].3.clone())).collect()),
+            _ => self.clone()
+        };
+        Ok((rebuild(left), rebuild(right)))
+    }
+
+    /// Convert this layer to another layer type. The supported conversions
+    /// are seq to element, span to div, and back if the annotations cover
+    /// every element of the base layer exactly once. The annotations keep
+    /// their order, so layers based on this layer remain valid
+    ///
+    /// # Arguments
+    ///
+    /// * `layer_desc` - The current description of this layer
+    /// * `new_type` - The type to convert to
+    /// * `base_len` - The number of elements in the base layer
+    ///
+    /// # Returns
+    ///
+    /// The layer with the new type
+    pub fn convert_type(&self, layer_desc : &LayerDesc, new_type : &LayerType,
+        base_len : usize) -> TeangaResult<Layer> {
+        let no_conversion = || TeangaError::ModelError(
+            format!("Cannot convert layer of type {} to type {}", layer_desc.layer_type, new_type));
+        match (&layer_desc.layer_type, new_type) {
+            (from, to) if from == to => Ok(self.clone()),
+            (LayerType::seq, LayerType::element) => match self {
+                Layer::L1(v) => Ok(Layer::L2(v.iter().enumerate().map(|(i, l)| (i as u32, *l)).collect())),
+                Layer::LS(v) => Ok(Layer::L1S(v.iter().enumerate().map(|(i, s)| (i as u32, s.clone())).collect())),
+                Layer::L1S(v) => Ok(Layer::L2S(v.iter().enumerate().map(|(i, (l, t))| (i as u32, *l, t.clone())).collect())),
+                _ => Err(no_conversion())
+            },
+            (LayerType::element, LayerType::seq) => {
+                let indexes : Vec<u32> = match self {
+                    Layer::L2(v) => v.iter().map(|a| a.0).collect(),
+                    Layer::L1S(v) => v.iter().map(|a| a.0).collect(),
+                    Layer::L2S(v) => v.iter().map(|a| a.0).collect(),
+                    _ => return Err(no_conversion())
+                };
+                if indexes.len() != base_len || indexes.iter().enumerate().any(|(i, j)| i as u32 != *j) {
+                    return Err(TeangaError::ModelError(
+                        "Cannot convert element layer to seq as not every base element has exactly one annotation".to_string()));
+                }
+                match self {
+                    Layer::L2(v) => Ok(Layer::L1(v.iter().map(|a| a.1).collect())),
+                    Layer::L1S(v) => Ok(Layer::LS(v.iter().map(|a| a.1.clone()).collect())),
+                    Layer::L2S(v) => Ok(Layer::L1S(v.iter().map(|a| (a.1, a.2.clone())).collect())),
+                    _ => Err(no_conversion())
+                }
+            },
+            (LayerType::div, LayerType::span) => {
+                let starts : Vec<u32> = match self {
+                    Layer::L1(v) => v.clone(),
+                    Layer::L2(v) => v.iter().map(|a| a.0).collect(),
+                    Layer::L1S(v) => v.iter().map(|a| a.0).collect(),
+                    Layer::L2S(v) => v.iter().map(|a| a.0).collect(),
+                    _ => return Err(no_conversion())
+                };
+                let end = |i : usize| starts.get(i + 1).copied().unwrap_or(base_len as u32);
+                match self {
+                    Layer::L1(v) => Ok(Layer::L2(v.iter().enumerate().map(|(i, s)| (*s, end(i))).collect())),
+                    Layer::L2(v) => Ok(Layer::L3(v.iter().enumerate().map(|(i, (s, l))| (*s, end(i), *l)).collect())),
+                    Layer::L1S(v) => Ok(Layer::L2S(v.iter().enumerate().map(|(i, (s, d))| (*s, end(i), d.clone())).collect())),
+                    Layer::L2S(v) => Ok(Layer::L3S(v.iter().enumerate().map(|(i, (s, l, t))| (*s, end(i), *l, t.clone())).collect())),
+                    _ => Err(no_conversion())
+                }
+            },
+            (LayerType::span, LayerType::div) => {
+                let spans : Vec<(u32, u32)> = match self {
+                    Layer::L2(v) => v.clone(),
+                    Layer::L3(v) => v.iter().map(|a| (a.0, a.1)).collect(),
+                    Layer::L2S(v) => v.iter().map(|a| (a.0, a.1)).collect(),
+                    Layer::L3S(v) => v.iter().map(|a| (a.0, a.1)).collect(),
+                    _ => return Err(no_conversion())
+                };
+                if spans.windows(2).any(|w| w[0].1 != w[1].0) ||
+                    spans.last().is_some_and(|s| s.1 as usize != base_len) {
+                    return Err(TeangaError::ModelError(
+                        "Cannot convert span layer to div as the spans do not divide the base layer".to_string()));
+                }
+                match self {
+                    Layer::L2(v) => Ok(Layer::L1(v.iter().map(|a| a.0).collect())),
+                    Layer::L3(v) => Ok(Layer::L2(v.iter().map(|a| (a.0, a.2)).collect())),
+                    Layer::L2S(v) => Ok(Layer::L1S(v.iter().map(|a| (a.0, a.2.clone())).collect())),
+                    Layer::L3S(v) => Ok(Layer::L2S(v.iter().map(|a| (a.0, a.2, a.3.clone())).collect())),
+                    _ => Err(no_conversion())
+                }
+            },
+            _ => Err(no_conversion())
+        }
+    }
+
+    /// Update this layer after the elements of its base layer are deleted or
+    /// reordered. Element annotations move with their element and are
+    /// dropped if it is deleted. Span and div annotations cover the range of
+    /// their elements that remain, so a span whose first element is deleted
+    /// shrinks to start at the next remaining element, and annotations
+    /// without remaining elements are dropped. Seq layers are reordered in
+    /// the same way as the base layer.
+    ///
+    /// If the layer has link data without a target (or with the base layer
+    /// as target), the links are also updated and annotations that link to
+    /// a deleted element are dropped. For seq layers this is an error, as
+    /// the layer would no longer match the base layer
+    ///
+    /// # Arguments
+    ///
+    /// * `layer_desc` - The description of this layer
+    /// * `mapping` - The new index of each element of the base layer, or
+    ///   `None` if it is deleted
+    pub fn remap_indices(&mut self, layer_desc : &LayerDesc, mapping : &[Option<u32>]) -> TeangaResult<()> {
+        if let Layer::Characters(_) = self {
+            return Err(TeangaError::ModelError(
+                "Cannot remap the indices of a characters layer".to_string()));
+        }
+        if let Layer::MetaLayer(_) = self {
+            return Ok(());
+        }
+        let n = self.len();
+        let remapped = if layer_desc.layer_type == LayerType::seq {
+            if n != mapping.len() {
+                return Err(TeangaError::ModelError(
+                    format!("Cannot remap a seq layer of length {} with a mapping of length {}", n, mapping.len())));
+            }
+            let kept = mapping.iter().flatten().count();
+            let mut order : Vec<Option<usize>> = vec![None; kept];
+            for (i, new) in mapping.iter().enumerate() {
+                if let Some(new) = new {
+                    match order.get_mut(*new as usize) {
+                        Some(slot @ None) => *slot = Some(i),
+                        _ => return Err(TeangaError::ModelError(
+                            format!("Cannot remap a seq layer as index {} is not a valid new position", new)))
+                    }
+                }
+            }
+            let order : Vec<usize> = order.into_iter().flatten().collect();
+            match self {
+                Layer::L1(v) => Layer::L1(order.iter().map(|i| v[*i]).collect()),
+                Layer::L2(v) => Layer::L2(order.iter().map(|i| v[*i]).collect()),
+                Layer::L3(v) => Layer::L3(order.iter().map(|i| v[*i]).collect()),
+                Layer::LS(v) => Layer::LS(order.iter().map(|i| v[*i].clone()).collect()),
+                Layer::L1S(v) => Layer::L1S(order.iter().map(|i| v[*i].clone()).collect()),
+                Layer::L2S(v) => Layer::L2S(order.iter().map(|i| v[*i].clone()).collect()),
+                Layer::L3S(v) => Layer::L3S(order.iter().map(|i| v[*i].clone()).collect()),
+                _ => self.clone()
+            }
+        } else {
+            // The range of each annotation in the base layer
+            let ranges : Vec<(usize, usize)> = match (&*self, &layer_desc.layer_type) {
+                (Layer::L2(v), LayerType::span) => v.iter().map(|a| (a.0 as usize, a.1 as usize)).collect(),
+                (Layer::L3(v), _) => v.iter().map(|a| (a.0 as usize, a.1 as usize)).collect(),
+                (Layer::L2S(v), LayerType::span) => v.iter().map(|a| (a.0 as usize, a.1 as usize)).collect(),
+                (Layer::L3S(v), _) => v.iter().map(|a| (a.0 as usize, a.1 as usize)).collect(),
+                _ => {
+                    let starts : Vec<usize> = match &*self {
+                        Layer::L1(v) => v.iter().map(|a| *a as usize).collect(),
+                        Layer::L2(v) => v.iter().map(|a| a.0 as usize).collect(),
+                        Layer::L1S(v) => v.iter().map(|a| a.0 as usize).collect(),
+                        Layer::L2S(v) => v.iter().map(|a| a.0 as usize).collect(),
+                        _ => Vec::new()
+                    };
+                    if layer_desc.layer_type == LayerType::div {
+                        starts.iter().enumerate().map(|(i, s)|
+                            (*s, starts.get(i + 1).copied().unwrap_or(mapping.len()))).collect()
+                    } else {
+                        starts.iter().map(|s| (*s, s + 1)).collect()
+                    }
+                }
+            };
+            let mut anns = Vec::new();
+            for (i, (start, end)) in ranges.into_iter().enumerate() {
+                if end > mapping.len() || start > end {
+                    return Err(TeangaError::ModelError(
+                        format!("Annotation ({}, {}) is outside the base layer of length {}", start, end, mapping.len())));
+                }
+                let kept = mapping[start..end].iter().flatten();
+                if let (Some(s), Some(e)) = (kept.clone().min(), kept.max()) {
+                    anns.push((i, *s, e + 1));
+                }
+            }
+            let is_span = layer_desc.layer_type == LayerType::span;
+            match self {
+                Layer::L1(_) => Layer::L1(anns.into_iter().map(|(_, s, _)| s).collect()),
+                Layer::L2(_) if is_span => Layer::L2(anns.into_iter().map(|(_, s, e)| (s, e)).collect()),
+                Layer::L2(v) => Layer::L2(anns.into_iter().map(|(i, s, _)| (s, v[i].1)).collect()),
+                Layer::L3(v) => Layer::L3(anns.into_iter().map(|(i, s, e)| (s, e, v[i].2)).collect()),
+                Layer::L1S(v) => Layer::L1S(anns.into_iter().map(|(i, s, _)| (s, v[i].1.clone())).collect()),
+                Layer::L2S(v) if is_span => Layer::L2S(anns.into_iter().map(|(i, s, e)| (s, e, v[i].2.clone())).collect()),
+                Layer::L2S(v) => Layer::L2S(anns.into_iter().map(|(i, s, _)| (s, v[i].1, v[i].2.clone())).collect()),
+                Layer::L3S(v) => Layer::L3S(anns.into_iter().map(|(i, s, e)| (s, e, v[i].2, v[i].3.clone())).collect()),
+                _ => self.clone()
+            }
+        };
+        let links_to_base = layer_desc.data == Some(DataType::Link) &&
+            (layer_desc.target.is_none() || layer_desc.target == layer_desc.base);
+        *self = if links_to_base {
+            let map = |l : u32| mapping.get(l as usize).copied().flatten();
+            let relinked = crate::remap_links(&remapped, &layer_desc.layer_type, map);
+            if layer_desc.layer_type == LayerType::seq && relinked.len() != remapped.len() {
+                return Err(TeangaError::ModelError(
+                    "Cannot remap a seq layer as it links to a deleted element".to_string()));
+            }
+            relinked
+        } else {
+            remapped
+        };
+        Ok(())
+    }
+
     /// Get the number of annotatable elements in this layer
     pub fn len(&self) -> usize {
         match self {
@@ -455,9 +918,18 @@ impl Layer {
     }
 }
 
+/// What to do with annotations that straddle the position a layer is split at
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum SplitPolicy {
+    /// Remove the annotation from both sides
+    Drop,
+    /// Keep the part of the annotation on each side
+    Clip
+}
+
 /// The types of layers supported by Teanga
 #[allow(non_camel_case_types)]
-#[derive(Debug,Clone,PartialEq,Serialize,Deserialize)]
+#[derive(Debug,Clone,PartialEq,Eq,Hash,Serialize,Deserialize)]
 pub enum LayerType {
     /// A plain text layer consisting of a single Unicode String
     characters,
@@ -0,0 +1,169 @@
+//! The dependency graph of the layers in a corpus
+//!
+//! Each layer (except characters layers) is based on another layer. This
+//! module provides a view of these dependencies as a directed acyclic graph.
+//!
+//! # Examples
+//! ```rust
+//! use teanga::{SimpleCorpus, Corpus, LayerType};
+//! let mut corpus = SimpleCorpus::new();
+//! corpus.build_layer("text").add().unwrap();
+//! corpus.build_layer("words")
+//!   .base("text")
+//!   .layer_type(LayerType::span)
+//!   .add().unwrap();
+//! let graph = corpus.layer_graph();
+//! assert_eq!(graph.roots(), vec!["text"]);
+//! assert_eq!(graph.children("text"), vec!["words"]);
+//! ```
+use std::collections::HashMap;
+use crate::{LayerDesc, TeangaError, TeangaResult};
+
+/// The dependency graph of layers
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerGraph {
+    bases: HashMap<String, Option<String>>,
+    children: HashMap<String, Vec<String>>
+}
+
+impl LayerGraph {
+    /// Create the graph from the metadata of a corpus
+    ///
+    /// # Arguments
+    ///
+    /// * `meta` - The metadata of the corpus
+    pub fn new(meta : &HashMap<String, LayerDesc>) -> LayerGraph {
+        let mut bases = HashMap::new();
+        let mut children : HashMap<String, Vec<String>> = HashMap::new();
+        for (name, desc) in meta.iter() {
+            let base = desc.base.clone().filter(|b| !b.is_empty());
+            if let Some(ref base) = base {
+                children.entry(base.clone()).or_default().push(name.clone());
+            }
+            bases.insert(name.clone(), base);
+        }
+        for c in children.values_mut() {
+            c.sort();
+        }
+        LayerGraph { bases, children }
+    }
+
+    /// Get the layers that are not based on any other layer (i.e., the
+    /// characters layers)
+    pub fn roots(&self) -> Vec<&str> {
+        let mut roots : Vec<&str> = self.bases.iter()
+            .filter(|(_, base)| base.is_none())
+            .map(|(name, _)| name.as_str())
+            .collect();
+        roots.sort();
+        roots
+    }
+
+    /// Get the base layer of a layer
+    pub fn base(&self, name : &str) -> Option<&str> {
+        self.bases.get(name).and_then(|b| b.as_deref())
+    }
+
+    /// Get the layers that are directly based on a layer
+    pub fn children(&self, name : &str) -> Vec<&str> {
+        self.children.get(name)
+            .map(|c| c.iter().map(|s| s.as_str()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the characters layer that a layer is ultimately based on
+    ///
+    /// # Returns
+    ///
+    /// The name of the root layer or an error if a base layer is missing
+    /// or the dependencies are cyclic
+    pub fn root_of<'a>(&'a self, name : &'a str) -> TeangaResult<&'a str> {
+        let mut layer = name;
+        for _ in 0..=self.bases.len() {
+            match self.bases.get(layer) {
+                Some(Some(base)) => layer = base,
+                Some(None) => return Ok(layer),
+                None => return Err(TeangaError::LayerNotFoundError(layer.to_string()))
+            }
+        }
+        Err(TeangaError::ModelError(format!("Layer {} has cyclic dependencies", name)))
+    }
+
+    /// Get all layers such that every layer occurs after its base layer.
+    /// Ties are broken alphabetically so the order is deterministic
+    ///
+    /// # Returns
+    ///
+    /// The layers in topological order or an error if the dependencies are cyclic
+    pub fn topo_order(&self) -> TeangaResult<Vec<&str>> {
+        let mut order = Vec::new();
+        let mut frontier = self.roots();
+        frontier.extend(self.bases.iter()
+            .filter(|(_, base)| base.as_ref().is_some_and(|b| !self.bases.contains_key(b)))
+            .map(|(name, _)| name.as_str()));
+        frontier.sort();
+        frontier.reverse();
+        while let Some(layer) = frontier.pop() {
+            order.push(layer);
+            let mut children = self.children(layer);
+            children.reverse();
+            frontier.extend(children);
+        }
+        if order.len() != self.bases.len() {
+            let mut cyclic : Vec<&str> = self.bases.keys()
+                .map(|s| s.as_str())
+                .filter(|s| !order.contains(s))
+                .collect();
+            cyclic.sort();
+            return Err(TeangaError::ModelError(
+                format!("Layers have cyclic dependencies: {}", cyclic.join(", "))));
+        }
+        Ok(order)
+    }
+
+    /// Write the graph in the Graphviz DOT format
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph layers {\n");
+        let mut names : Vec<&String> = self.bases.keys().collect();
+        names.sort();
+        for name in names {
+            dot.push_str(&format!("    \"{}\";\n", name));
+            if let Some(Some(base)) = self.bases.get(name) {
+                dot.push_str(&format!("    \"{}\" -> \"{}\";\n", base, name));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Corpus, SimpleCorpus, LayerType, DataType};
+
+    #[test]
+    fn test_layer_graph() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("sentences").base("text").layer_type(LayerType::div).add().unwrap();
+        corpus.build_layer("pos").base("words").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        let graph = corpus.layer_graph();
+        assert_eq!(graph.roots(), vec!["text"]);
+        assert_eq!(graph.children("text"), vec!["sentences", "words"]);
+        assert_eq!(graph.root_of("pos").unwrap(), "text");
+        assert_eq!(graph.topo_order().unwrap(), vec!["text", "sentences", "words", "pos"]);
+    }
+
+    #[test]
+    fn test_cyclic_graph() {
+        let mut meta = HashMap::new();
+        meta.insert("a".to_string(), LayerDesc { base: Some("b".to_string()), ..LayerDesc::default() });
+        meta.insert("b".to_string(), LayerDesc { base: Some("a".to_string()), ..LayerDesc::default() });
+        let graph = LayerGraph::new(&meta);
+        assert!(graph.topo_order().is_err());
+        assert!(graph.root_of("a").is_err());
+    }
+}
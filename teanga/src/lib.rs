@@ -15,7 +15,7 @@
 // Purpose: Rust implementation of the TeangaDB Python module.
 // Author: John P. McCrae
 // License: Apache 2.0
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 #[cfg(feature = "sled")]
 use sled;
 #[cfg(feature = "fjall")]
@@ -29,25 +29,49 @@ use itertools::Itertools;
 use serde::{Serialize,Deserialize};
 use thiserror::Error;
 
+pub mod brat;
+pub mod concordance;
+pub mod corpus_ops;
 #[cfg(any(feature = "sled", feature = "fjall", feature = "redb"))]
 pub mod disk_corpus;
 pub mod document;
 pub mod layer;
 pub mod layer_builder;
+pub mod layer_graph;
 pub mod query;
+pub mod schema_diff;
 pub mod serialization;
 pub mod match_condition;
+#[cfg(feature = "minhash")]
+pub mod minhash;
+pub mod validation;
+#[cfg(feature = "unicode-normalization")]
+pub mod normalize;
+#[cfg(feature = "tcf")]
 mod tcf;
 
-pub use document::{Document, DocumentContent, DocumentBuilder};
+pub use brat::{read_brat, write_brat, write_brat_ann, BratMapping, BratError};
+pub use concordance::{Concordance, write_kwic};
+pub use corpus_ops::{merge, MergeStrategy, MergeSummary};
+pub use document::{Document, DocumentContent, DocumentBuilder, LinkError};
 #[cfg(any(feature = "sled", feature = "fjall", feature = "redb"))]
-pub use disk_corpus::DiskCorpus;
-pub use layer::{IntoLayer, Layer, LayerDesc, DataType, LayerType, TeangaData};
+pub use disk_corpus::{DiskCorpus, BatchGuard, IntegrityError, with_transaction};
+pub use layer::{IntoLayer, Layer, LayerDesc, DataType, LayerType, TeangaData, Provenance, SplitPolicy};
 pub use layer_builder::build_layer;
-pub use query::Query;
-pub use serialization::{read_json, read_yaml, write_json, write_yaml, read_yaml_meta, read_jsonl};
-pub use tcf::{write_tcf, write_tcf_with_config, read_tcf, write_tcf_header, write_tcf_config, write_tcf_doc, doc_content_to_bytes, bytes_to_doc, Index, IndexResult, TCFReadError, TCFWriteError, TCFConfig, StringCompression, StringCompressionError, StringCompressionMethod, NoCompression, SmazCompression, ShocoCompression};
-pub use match_condition::{TextMatchCondition, DataMatchCondition};
+pub use layer_graph::LayerGraph;
+pub use query::{Query, TokenPattern, SpanMatches};
+pub use schema_diff::{schema_diff, SchemaDiff};
+pub use serialization::{read_json, read_yaml, write_json, write_yaml, read_yaml_meta, read_jsonl, read_jsonl_grouped, write_jsonl_indexed, read_jsonl_index, read_jsonl_at, write_spacy_json, SpacyMapping, write_vrt, VrtConfig, read_conllu, ConlluConfig, write_conllu, ConlluWriteConfig, open_maybe_compressed, write_json_streaming, write_layer_tsv, LoadMode, read_json_with_mode, read_yaml_with_mode, read_yaml_meta_with_mode};
+#[cfg(feature = "tar")]
+pub use serialization::read_jsonl_tar;
+#[cfg(feature = "tcf")]
+pub use tcf::{write_tcf, write_tcf_with_config, read_tcf, read_tcf_iter, TCFDocIter, TCFReader, TCFCorpus, tcf_stats, TCFStats, write_tcf_header, write_tcf_header_with_config, write_tcf_config, write_tcf_doc, write_tcf_doc_index, doc_content_to_bytes, CountingWriter, bytes_to_doc, Index, IndexResult, TCFReadError, TCFWriteError, TCFConfig, StringCompression, StringCompressionError, StringCompressionMethod, NoCompression, SmazCompression, ShocoCompression, train_shoco_model};
+#[cfg(all(feature = "tcf", feature = "zstd"))]
+pub use tcf::ZstdCompression;
+pub use match_condition::{TextMatchCondition, DataMatchCondition, RegexTextMatch};
+pub use validation::ValidationError;
+#[cfg(feature = "unicode-normalization")]
+pub use normalize::NormalizationForm;
 
 /// Trait that defines a corpus according to the Teanga Data Model
 pub trait Corpus {
@@ -56,7 +80,9 @@ type LayerStorage : IntoLayer;
 /// The type of the content
 type Content : DocumentContent<Self::LayerStorage>;
 
-/// Add a meta layer to the corpus
+/// Add a meta layer to the corpus. It is an error if the base or target
+/// layer is not declared or if the layers would be based on each other in
+/// a cycle
 ///
 /// # Arguments
 ///
@@ -72,6 +98,29 @@ fn add_layer_meta(&mut self, name: String, layer_type: LayerType,
     base: Option<String>, data: Option<DataType>, link_types: Option<Vec<String>>, 
     target: Option<String>, default: Option<Layer>,
     meta: HashMap<String, Value>) -> TeangaResult<()>;
+
+/// Add a meta layer to the corpus, unless a layer of the same name exists.
+/// If the existing layer has the same definition this does nothing,
+/// otherwise an error is returned and the existing layer is not changed
+///
+/// # Arguments
+///
+/// * `name` - The name of the layer
+/// * `layer_desc` - The description of the layer
+fn add_layer_meta_checked(&mut self, name: String, layer_desc: LayerDesc) -> TeangaResult<()> {
+    if let Some(existing) = self.get_meta().get(&name) {
+        if *existing == layer_desc {
+            Ok(())
+        } else {
+            Err(TeangaError::ModelError(
+                format!("Layer {} already exists with a different definition", name)))
+        }
+    } else {
+        let LayerDesc { layer_type, base, data, link_types, target, default, meta } = layer_desc;
+        self.add_layer_meta(name, layer_type, base, data, link_types, target, default, meta)
+    }
+}
+
 /// Build a layer using a builder
 ///
 ///
@@ -132,6 +181,80 @@ fn remove_doc(&mut self, id : &str) -> TeangaResult<()>;
 /// * `id` - The ID of the document
 fn get_doc_by_id(&self, id : &str) -> TeangaResult<Document>;
 
+/// Get a document by a prefix of its ID, as with short commit hashes in
+/// git. The IDs of the documents are kept in memory by all corpora, so this
+/// does not need to read any other documents
+///
+/// # Arguments
+///
+/// * `prefix` - The start of the ID of the document
+///
+/// # Returns
+///
+/// The full ID and the document, or an error if no document or more than
+/// one document has an ID starting with the prefix. If the prefix is the
+/// full ID of a document, that document is returned
+fn get_doc_by_prefix(&self, prefix : &str) -> TeangaResult<(String, Document)> {
+    let candidates : Vec<&String> = self.get_order().iter()
+        .filter(|id| id.starts_with(prefix))
+        .collect();
+    let id = match candidates.as_slice() {
+        [] => return Err(TeangaError::DocumentNotFoundError),
+        [id] => *id,
+        _ => match candidates.iter().find(|id| id.as_str() == prefix) {
+            Some(id) => *id,
+            None => return Err(TeangaError::ModelError(
+                format!("Prefix {} is ambiguous, candidates: {}", prefix,
+                    candidates.iter().join(", "))))
+        }
+    };
+    Ok((id.clone(), self.get_doc_by_id(id)?))
+}
+
+/// Get a document by its position in the order of the corpus
+///
+/// # Arguments
+///
+/// * `n` - The index of the document, starting from zero
+///
+/// # Returns
+///
+/// The ID and the document, or an error if the corpus has `n` or fewer
+/// documents
+fn get_doc_by_index(&self, n : usize) -> TeangaResult<(String, Document)> {
+    let id = self.get_order().get(n).ok_or_else(|| TeangaError::ModelError(
+        format!("Index {} is out of bounds for a corpus of {} documents", n, self.len())))?;
+    Ok((id.clone(), self.get_doc_by_id(id)?))
+}
+
+/// The number of documents in the corpus
+fn len(&self) -> usize {
+    self.get_order().len()
+}
+
+/// Whether the corpus has no documents
+fn is_empty(&self) -> bool {
+    self.get_order().is_empty()
+}
+
+/// Get a document object by its ID, including any layers that are not
+/// stored in the document but have a default value in the metadata
+///
+/// # Arguments
+///
+/// * `id` - The ID of the document
+fn get_doc_resolved(&self, id : &str) -> TeangaResult<Document> {
+    let mut doc = self.get_doc_by_id(id)?;
+    for (name, desc) in self.get_meta().iter() {
+        if let Some(ref default) = desc.default {
+            if doc.get(name).is_none() {
+                doc.set(name, default.clone());
+            }
+        }
+    }
+    Ok(doc)
+}
+
 /// Get the IDs of all documents in the corpus
 fn get_docs(&self) -> Vec<String>;
 
@@ -148,31 +271,790 @@ fn add_docs<D : IntoLayer, DC : DocumentContent<D>>(&mut self, content : Vec<DC>
     for doc in content {
         ids.push(self.add_doc(doc)?);
     }
-    Ok(ids)
+    Ok(ids)
+}
+
+/// Annotate every document with an external function. The function is
+/// called with the text of each document and returns spans as byte offsets
+/// into the text together with a label. These are stored in a new span
+/// layer with string data based on the text layer, which is declared if it
+/// does not already exist. Documents without the text layer are skipped
+///
+/// # Arguments
+///
+/// * `text_layer` - The characters layer to annotate
+/// * `new_layer` - The name of the layer to store the annotations in
+/// * `f` - The function to annotate the text with
+fn annotate<F : FnMut(&str) -> Vec<(u32, u32, String)>>(&mut self, text_layer : &str, new_layer : &str, mut f : F) -> TeangaResult<()> {
+    match self.get_meta().get(text_layer) {
+        Some(desc) if desc.layer_type == LayerType::characters => {},
+        Some(_) => return Err(TeangaError::ModelError(
+            format!("Layer {} is not a characters layer", text_layer))),
+        None => return Err(TeangaError::LayerNotFoundError(text_layer.to_string()))
+    }
+    self.add_layer_meta_checked(new_layer.to_string(), LayerDesc {
+        layer_type: LayerType::span,
+        base: Some(text_layer.to_string()),
+        data: Some(DataType::String),
+        ..LayerDesc::default()
+    })?;
+    for doc_id in self.get_docs() {
+        let doc = self.get_doc_by_id(&doc_id)?;
+        let Some(text) = doc.get(text_layer).and_then(|l| l.characters()) else {
+            continue;
+        };
+        let mut annotations = f(text);
+        annotations.sort();
+        self.update_doc(&doc_id, vec![(new_layer.to_string(), Layer::L2S(annotations))])?;
+    }
+    Ok(())
+}
+
+/// Annotate every document with the matches of a regular expression. The
+/// matches are stored as spans (byte offsets, as in all span layers) in a
+/// new span layer based on the text layer, which is declared if it does not
+/// already exist. Documents without the text layer are skipped
+///
+/// # Arguments
+///
+/// * `text_layer` - The characters layer to annotate
+/// * `new_layer` - The name of the layer to store the matches in
+/// * `pattern` - The regular expression
+/// * `label` - If given, the string data of every match
+fn annotate_regex(&mut self, text_layer : &str, new_layer : &str, pattern : &str, label : Option<&str>) -> TeangaResult<()> {
+    let regex = regex::Regex::new(pattern).map_err(|e| TeangaError::ModelError(
+        format!("Invalid regular expression {}: {}", pattern, e)))?;
+    match self.get_meta().get(text_layer) {
+        Some(desc) if desc.layer_type == LayerType::characters => {},
+        Some(_) => return Err(TeangaError::ModelError(
+            format!("Layer {} is not a characters layer", text_layer))),
+        None => return Err(TeangaError::LayerNotFoundError(text_layer.to_string()))
+    }
+    self.add_layer_meta_checked(new_layer.to_string(), LayerDesc {
+        layer_type: LayerType::span,
+        base: Some(text_layer.to_string()),
+        data: label.map(|_| DataType::String),
+        ..LayerDesc::default()
+    })?;
+    for doc_id in self.get_docs() {
+        let doc = self.get_doc_by_id(&doc_id)?;
+        let Some(text) = doc.get(text_layer).and_then(|l| l.characters()) else {
+            continue;
+        };
+        let matches = regex.find_iter(text).map(|m| (m.start() as u32, m.end() as u32));
+        let layer = match label {
+            Some(label) => Layer::L2S(matches.map(|(s, e)| (s, e, label.to_string())).collect()),
+            None => Layer::L2(matches.collect())
+        };
+        self.update_doc(&doc_id, vec![(new_layer.to_string(), layer)])?;
+    }
+    Ok(())
+}
+
+/// Join the text of the tokens of every document with a separator and store
+/// it in a new characters layer, e.g., to produce normalized text after the
+/// tokens have been filtered or edited. A document with no tokens gets an
+/// empty text and documents without the token layer are skipped.
+///
+/// **Note**: The ID of a document depends on its text, so the IDs of the
+/// documents change
+///
+/// # Arguments
+///
+/// * `token_layer` - The layer with the tokens
+/// * `out_layer` - The name of the new characters layer
+/// * `sep` - The separator to put between the tokens
+fn detokenize(&mut self, token_layer : &str, out_layer : &str, sep : &str) -> TeangaResult<()> {
+    if !self.get_meta().contains_key(token_layer) {
+        return Err(TeangaError::LayerNotFoundError(token_layer.to_string()));
+    }
+    self.add_layer_meta_checked(out_layer.to_string(), LayerDesc {
+        layer_type: LayerType::characters,
+        ..LayerDesc::default()
+    })?;
+    for doc_id in self.get_docs() {
+        let doc = self.get_doc_by_id(&doc_id)?;
+        if doc.get(token_layer).is_none() {
+            continue;
+        }
+        let text = doc.text(token_layer, self.get_meta())?.join(sep);
+        self.update_doc(&doc_id, vec![(out_layer.to_string(), Layer::Characters(text))])?;
+    }
+    Ok(())
+}
+
+/// Apply Unicode normalization to a characters layer in every document. The
+/// offsets of the layers based directly on the text are updated to the
+/// normalized text. Seq layers have an element for each byte of the text,
+/// so if such a layer is based on the text layer this fails without
+/// changing any document.
+///
+/// **Note**: The ID of a document depends on its text, so the IDs of
+/// changed documents change
+///
+/// # Arguments
+///
+/// * `layer` - The characters layer to normalize
+/// * `form` - The normalization form
+#[cfg(feature = "unicode-normalization")]
+fn normalize_text(&mut self, layer : &str, form : NormalizationForm) -> TeangaResult<()> {
+    match self.get_meta().get(layer) {
+        Some(desc) if desc.layer_type == LayerType::characters => {},
+        Some(_) => return Err(TeangaError::ModelError(
+            format!("Layer {} is not a characters layer", layer))),
+        None => return Err(TeangaError::LayerNotFoundError(layer.to_string()))
+    }
+    // Fail before any document is changed
+    if let Some((name, _)) = self.get_meta().iter().sorted_by(|a, b| a.0.cmp(b.0))
+        .find(|(_, desc)| desc.layer_type == LayerType::seq && desc.base.as_deref() == Some(layer)) {
+        return Err(TeangaError::ModelError(
+            format!("Cannot normalize layer {} as the seq layer {} is based on it", layer, name)));
+    }
+    for doc_id in self.get_docs() {
+        let doc = self.get_doc_by_id(&doc_id)?;
+        if doc.get(layer).is_none() {
+            continue;
+        }
+        let layers = normalize::normalize_doc(&doc, layer, form, self.get_meta())?;
+        if layers[0].1 != doc[layer] {
+            self.update_doc(&doc_id, layers)?;
+        }
+    }
+    Ok(())
+}
+
+/// Add multiple documents to the corpus, continuing after a document fails
+/// to be added
+///
+/// # Arguments
+///
+/// * `content` - The content of the documents
+///
+/// # Returns
+///
+/// For each input document in order, either its ID or the reason it could not
+/// be added
+fn add_docs_collect<D : IntoLayer, DC : DocumentContent<D>>(&mut self, content : Vec<DC>) -> Vec<TeangaResult<String>> {
+    content.into_iter().map(|doc| self.add_doc(doc)).collect()
+}
+
+/// Set a label of a document, e.g., its class in a text classification
+/// dataset. Labels are stored in the meta layer `LABEL_LAYER`, which is
+/// declared if it does not already exist
+///
+/// # Arguments
+///
+/// * `id` - The ID of the document
+/// * `key` - The name of the label (e.g., `sentiment`)
+/// * `value` - The value of the label
+fn set_doc_label(&mut self, id : &str, key : &str, value : &str) -> TeangaResult<()> {
+    self.add_layer_meta_checked(LABEL_LAYER.to_string(), LayerDesc {
+        layer_type: LayerType::characters,
+        ..LayerDesc::default()
+    })?;
+    let doc = self.get_doc_by_id(id)?;
+    let mut labels = match doc.get(LABEL_LAYER) {
+        Some(Layer::MetaLayer(values)) => values.first().cloned().unwrap_or_default(),
+        _ => HashMap::new()
+    };
+    labels.insert(key.to_string(), Value::String(value.to_string()));
+    self.update_doc(id, vec![(LABEL_LAYER.to_string(), Layer::MetaLayer(vec![labels]))])?;
+    Ok(())
+}
+
+/// Get a label of a document set with `set_doc_label`
+///
+/// # Arguments
+///
+/// * `id` - The ID of the document
+/// * `key` - The name of the label
+///
+/// # Returns
+///
+/// The value of the label, or None if the document does not have this label
+fn get_doc_label(&self, id : &str, key : &str) -> TeangaResult<Option<String>> {
+    Ok(doc_label(&self.get_doc_by_id(id)?, key))
+}
+
+/// Count the documents with each value of a label, e.g., the class
+/// distribution of a text classification dataset. Documents without the
+/// label are not counted
+///
+/// # Arguments
+///
+/// * `key` - The name of the label
+///
+/// # Returns
+///
+/// The number of documents with each value of the label
+fn labels_freq(&self, key : &str) -> TeangaResult<HashMap<String, usize>> {
+    self.count_by(|doc| doc_label(doc, key))
+}
+
+/// Draw a random sample of the documents with a label that has the same
+/// distribution of the label values as the corpus. The number of documents
+/// for each value is rounded so that the sample has exactly `n` documents.
+/// Documents without the label are not sampled
+///
+/// # Arguments
+///
+/// * `n` - The number of documents to sample
+/// * `label_key` - The name of the label, see `set_doc_label`
+/// * `seed` - The seed of the random number generator, so that the same
+///   sample is drawn each time
+///
+/// # Returns
+///
+/// A new in-memory corpus with the sampled documents in their original order
+fn sample_stratified(&self, n : usize, label_key : &str, seed : u64) -> TeangaResult<SimpleCorpus> {
+    let mut labelled = Vec::new();
+    for doc in self.iter_doc_ids() {
+        let (id, doc) = doc?;
+        if let Some(label) = doc_label(&doc, label_key) {
+            labelled.push((id, label));
+        }
+    }
+    if n > labelled.len() {
+        return Err(TeangaError::ModelError(
+            format!("Cannot sample {} documents as only {} have the label {}", n, labelled.len(), label_key)));
+    }
+    let counts = labelled.iter().map(|(_, label)| label.as_str()).counts();
+    let total = labelled.len().max(1);
+    // Round down the share of each value and give the remaining documents to
+    // the values with the largest remainders
+    let mut quotas : HashMap<&str, usize> = counts.iter()
+        .map(|(label, count)| (*label, n * count / total)).collect();
+    let remaining = n - quotas.values().sum::<usize>();
+    for label in counts.keys().sorted_by_key(|label| (std::cmp::Reverse(n * counts[*label] % total), **label)).take(remaining) {
+        *quotas.get_mut(label).unwrap() += 1;
+    }
+    let mut rng = SplitMix64(seed);
+    let mut seen : HashMap<&str, usize> = HashMap::new();
+    let mut reservoirs : HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, (_, label)) in labelled.iter().enumerate() {
+        let quota = quotas[label.as_str()];
+        let k = seen.entry(label).or_insert(0);
+        let reservoir = reservoirs.entry(label).or_default();
+        if *k < quota {
+            reservoir.push(i);
+        } else {
+            let j = rng.below(*k as u64 + 1) as usize;
+            if j < quota {
+                reservoir[j] = i;
+            }
+        }
+        *k += 1;
+    }
+    let mut order = Vec::new();
+    let mut content = HashMap::new();
+    for i in reservoirs.into_values().flatten().sorted() {
+        let id = &labelled[i].0;
+        content.insert(id.clone(), self.get_doc_by_id(id)?);
+        order.push(id.clone());
+    }
+    Ok(SimpleCorpus {
+        meta: self.get_meta().clone(),
+        order,
+        content
+    })
+}
+
+/// Create a new corpus with the documents for which a predicate holds. The
+/// new corpus has all the layers of this corpus, even if no selected document
+/// uses them
+///
+/// # Arguments
+///
+/// * `pred` - A function of the ID and the document that returns true if the
+///   document should be kept
+///
+/// # Returns
+///
+/// A new in-memory corpus with the selected documents in their original order
+fn filter<F : Fn(&str, &Document) -> bool>(&self, pred : F) -> TeangaResult<SimpleCorpus> {
+    let mut order = Vec::new();
+    let mut content = HashMap::new();
+    for doc in self.iter_doc_ids() {
+        let (id, doc) = doc?;
+        if pred(&id, &doc) {
+            order.push(id.clone());
+            content.insert(id, doc);
+        }
+    }
+    Ok(SimpleCorpus {
+        meta: self.get_meta().clone(),
+        order,
+        content
+    })
+}
+
+/// Split the corpus into parts by hashing the ID of each document, so that
+/// the same corpus is always split in the same way
+///
+/// # Arguments
+///
+/// * `ratios` - The expected share of the documents in each part, which must
+///   sum to 1
+/// * `seed` - The seed for the hash, so that different splits can be made
+///
+/// # Returns
+///
+/// A new in-memory corpus for each part, with the documents in their original
+/// order
+fn split_by_hash(&self, ratios : &[f64], seed : u64) -> TeangaResult<Vec<SimpleCorpus>> {
+    if ratios.iter().any(|r| r.is_nan() || *r < 0.0) || (ratios.iter().sum::<f64>() - 1.0).abs() > 1e-9 {
+        return Err(TeangaError::ModelError(
+            format!("The ratios {:?} must be non-negative and sum to 1", ratios)));
+    }
+    let bucket = |id : &str| {
+        let h = id.bytes().fold(seed, |h, b| SplitMix64(h ^ b as u64).next_u64());
+        let x = (h >> 11) as f64 / (1u64 << 53) as f64;
+        let mut total = 0.0;
+        ratios.iter().position(|r| {
+            total += r;
+            x < total
+        }).unwrap_or(ratios.len() - 1)
+    };
+    (0..ratios.len()).map(|i| self.filter(|id, _| bucket(id) == i)).collect()
+}
+
+/// Calculate the frequency of words in the text layers of the corpus
+///
+/// # Arguments
+///
+/// * `layer` - The layer to calculate the frequency of
+/// * `condition` - A condition that must be met for a word to be counted
+///
+/// # Returns
+///
+/// A map from words to their frequency
+fn text_freq<C: TextMatchCondition>(&self, layer : &str, condition : C) -> TeangaResult<HashMap<String, u32>> {
+    let mut freq = HashMap::new();
+    for doc_id in self.get_docs() {
+        let doc = self.get_doc_by_id(&doc_id)?;
+        let text = doc.text(layer, self.get_meta())?;
+        for word in text {
+            if condition.matches(word) {
+                *freq.entry(word.to_string()).or_insert(0) += 1;
+            }
+        }
+    }
+    Ok(freq)
+}
+
+/// Calculate the frequency of words in the text layers of the corpus after
+/// normalizing them. The condition is checked on the normalized word
+///
+/// # Arguments
+///
+/// * `layer` - The layer to calculate the frequency of
+/// * `condition` - A condition that must be met for a word to be counted
+/// * `opts` - How the words are normalized
+///
+/// # Returns
+///
+/// A map from normalized words to their frequency. Words that are empty after
+/// normalization are not counted
+fn text_freq_with<C: TextMatchCondition>(&self, layer : &str, condition : C, opts : &FreqOptions) -> TeangaResult<HashMap<String, u32>> {
+    #[cfg(not(feature = "unicode-normalization"))]
+    if opts.nfc {
+        return Err(TeangaError::ModelError(
+            "NFC normalization requires the unicode-normalization feature".to_string()));
+    }
+    let punct = regex::Regex::new(r"\p{P}").unwrap();
+    let mut freq = HashMap::new();
+    for doc_id in self.get_docs() {
+        let doc = self.get_doc_by_id(&doc_id)?;
+        let text = doc.text(layer, self.get_meta())?;
+        for word in text {
+            let mut word = word.to_string();
+            #[cfg(feature = "unicode-normalization")]
+            if opts.nfc {
+                word = NormalizationForm::Nfc.normalize(&word);
+            }
+            if opts.lowercase {
+                word = word.to_lowercase();
+            }
+            if opts.strip_punct {
+                word = punct.replace_all(&word, "").into_owned();
+            }
+            if !word.is_empty() && condition.matches(&word) {
+                *freq.entry(word).or_insert(0) += 1;
+            }
+        }
+    }
+    Ok(freq)
+}
+
+/// Calculate the frequency of words in the text layers of the corpus,
+/// counting only words that overlap an annotation in another layer
+///
+/// # Arguments
+///
+/// * `text_layer` - The layer to calculate the frequency of
+/// * `filter_layer` - The layer whose annotations a word must overlap
+/// * `condition` - A condition that must be met for a word to be counted
+///
+/// # Returns
+///
+/// A map from words to their frequency
+fn text_freq_within<C: TextMatchCondition>(&self, text_layer : &str, filter_layer : &str, condition : C) -> TeangaResult<HashMap<String, u32>> {
+    let graph = self.layer_graph();
+    let char_layer = graph.root_of(text_layer)?;
+    if graph.root_of(filter_layer)? != char_layer {
+        return Err(TeangaError::ModelError(
+            format!("Layers {} and {} are not based on the same characters layer", text_layer, filter_layer)));
+    }
+    let mut freq = HashMap::new();
+    for doc_id in self.get_docs() {
+        let doc = self.get_doc_by_id(&doc_id)?;
+        let filter = match doc.get(filter_layer) {
+            Some(_) => doc.indexes(filter_layer, char_layer, self.get_meta())?,
+            None => continue
+        };
+        let text = doc.text(text_layer, self.get_meta())?;
+        let indexes = doc.indexes(text_layer, char_layer, self.get_meta())?;
+        for (word, (start, end)) in text.into_iter().zip(indexes) {
+            if filter.iter().any(|(s, e)| start < *e && *s < end) && condition.matches(word) {
+                *freq.entry(word.to_string()).or_insert(0) += 1;
+            }
+        }
+    }
+    Ok(freq)
+}
+
+/// Count the documents in the corpus grouped by a key
+///
+/// # Arguments
+///
+/// * `key` - A function giving the group of a document, or `None` if the
+///   document should not be counted
+///
+/// # Returns
+///
+/// A map from groups to the number of documents in the group
+fn count_by<F : Fn(&Document) -> Option<String>>(&self, key : F) -> TeangaResult<HashMap<String, usize>> {
+    let mut counts = HashMap::new();
+    for doc in self.iter_docs() {
+        if let Some(k) = key(&doc?) {
+            *counts.entry(k).or_insert(0) += 1;
+        }
+    }
+    Ok(counts)
+}
+
+/// Calculate a histogram of the lengths of the documents in the corpus
+///
+/// # Arguments
+///
+/// * `layer` - The layer to measure the documents by
+/// * `unit` - How the length of a document is measured
+/// * `buckets` - The lower bounds of the buckets in increasing order
+///
+/// # Returns
+///
+/// The number of documents in each bucket, where the document is in the
+/// bucket `i` if its length is at least `buckets[i]` and less than
+/// `buckets[i + 1]`. Lengths greater than or equal to the top bucket are
+/// counted in the last bucket and documents shorter than the first bucket
+/// or without the layer are not counted
+fn length_histogram(&self, layer : &str, unit : LengthUnit, buckets : &[usize]) -> TeangaResult<Vec<usize>> {
+    if !self.get_meta().contains_key(layer) {
+        return Err(TeangaError::LayerNotFoundError(layer.to_string()));
+    }
+    if buckets.windows(2).any(|w| w[0] >= w[1]) {
+        return Err(TeangaError::ModelError("Bucket boundaries must be increasing".to_string()));
+    }
+    let mut counts = vec![0; buckets.len()];
+    for doc in self.iter_docs() {
+        let doc = doc?;
+        if doc.get(layer).is_none() {
+            continue;
+        }
+        let len = match unit {
+            LengthUnit::Chars => doc.text(layer, self.get_meta())?.iter()
+                .map(|t| t.chars().count()).sum(),
+            LengthUnit::Words => doc.text(layer, self.get_meta())?.iter()
+                .map(|t| t.split_whitespace().count()).sum(),
+            LengthUnit::Annotations => doc.get(layer).map_or(0, |l| l.len())
+        };
+        if let Some(i) = buckets.iter().rposition(|b| *b <= len) {
+            counts[i] += 1;
+        }
+    }
+    Ok(counts)
+}
+
+/// Count the annotations of a layer (e.g., the tokens) in all documents of
+/// the corpus. No text is extracted, so this is faster than `text_freq`.
+/// For a characters layer this is the length of the text in bytes
+///
+/// # Arguments
+///
+/// * `layer` - The layer to count
+///
+/// # Returns
+///
+/// The total number of annotations in the layer
+fn total_tokens(&self, layer : &str) -> TeangaResult<u64> {
+    if !self.get_meta().contains_key(layer) {
+        return Err(TeangaError::LayerNotFoundError(layer.to_string()));
+    }
+    let mut total = 0u64;
+    for doc in self.iter_docs() {
+        total += doc?.get(layer).map_or(0, |l| l.len() as u64);
+    }
+    Ok(total)
+}
+
+/// Split the text of every document into overlapping windows, e.g., for
+/// models with a limited input length. The size and stride of the windows
+/// are counted in characters, so windows never split a character, and
+/// windows do not cross document boundaries. The last window of a document
+/// ends at the end of its text
+///
+/// # Arguments
+///
+/// * `layer` - The characters layer
+/// * `window_chars` - The number of characters in each window
+/// * `stride` - The number of characters between the starts of the windows
+///
+/// # Returns
+///
+/// The ID of the document, the byte offset of the start of the window in
+/// the text (as used by span layers) and the text of the window
+fn text_windows(&self, layer : &str, window_chars : usize, stride : usize) -> TeangaResult<Vec<(String, usize, String)>> {
+    match self.get_meta().get(layer) {
+        Some(desc) if desc.layer_type == LayerType::characters => {},
+        Some(_) => return Err(TeangaError::ModelError(
+            format!("Layer {} is not a characters layer", layer))),
+        None => return Err(TeangaError::LayerNotFoundError(layer.to_string()))
+    }
+    if window_chars == 0 || stride == 0 {
+        return Err(TeangaError::ModelError(
+            "The window size and stride must be greater than zero".to_string()));
+    }
+    let mut windows = Vec::new();
+    for doc in self.iter_doc_ids() {
+        let (doc_id, doc) = doc?;
+        let Some(text) = doc.get(layer).and_then(|l| l.characters()) else {
+            continue;
+        };
+        let offsets : Vec<usize> = text.char_indices().map(|(i, _)| i)
+            .chain(std::iter::once(text.len())).collect();
+        let n = offsets.len() - 1;
+        let mut start = 0;
+        while start < n {
+            let end = (start + window_chars).min(n);
+            windows.push((doc_id.clone(), offsets[start], text[offsets[start]..offsets[end]].to_string()));
+            if end == n {
+                break;
+            }
+            start += stride;
+        }
+    }
+    Ok(windows)
+}
+
+/// Compute a MinHash signature for each document from the shingles of its
+/// tokens, see `minhash::find_near_duplicates` to find similar documents.
+/// If the layer is a characters layer, its text is split at whitespace
+///
+/// # Arguments
+///
+/// * `layer` - The layer containing the tokens
+/// * `num_perm` - The number of hash functions, that is the length of each
+///   signature. More hash functions give a more accurate estimate of the
+///   similarity
+///
+/// # Returns
+///
+/// A map from the ID of each document to its signature
+#[cfg(feature = "minhash")]
+fn minhash_signatures(&self, layer : &str, num_perm : usize) -> TeangaResult<HashMap<String, Vec<u64>>> {
+    minhash::minhash_signatures(self, layer, num_perm)
+}
+
+/// Calculate the frequency of n-grams of words in the text layers of the corpus.
+/// N-grams do not cross document boundaries
+///
+/// # Arguments
+///
+/// * `layer` - The layer to calculate the frequency of
+/// * `n` - The length of the n-grams
+/// * `condition` - A condition that must be met by every word in an n-gram for it to be counted
+///
+/// # Returns
+///
+/// A map from n-grams to their frequency
+fn ngram_freq<C: TextMatchCondition>(&self, layer : &str, n : usize, condition : C) -> TeangaResult<HashMap<Vec<String>, u32>> {
+    if n == 0 {
+        return Err(TeangaError::ModelError("N-gram length must be at least 1".to_string()));
+    }
+    let mut freq = HashMap::new();
+    for doc_id in self.get_docs() {
+        let doc = self.get_doc_by_id(&doc_id)?;
+        let text = doc.text(layer, self.get_meta())?;
+        for ngram in text.windows(n) {
+            if ngram.iter().all(|word| condition.matches(word)) {
+                *freq.entry(ngram.iter().map(|w| w.to_string()).collect()).or_insert(0) += 1;
+            }
+        }
+    }
+    Ok(freq)
+}
+
+/// Generate the (target, context) word pairs used to train skip-gram word
+/// embeddings. Pairs do not cross document boundaries
+///
+/// # Arguments
+///
+/// * `layer` - The layer containing the words
+/// * `window` - The maximum distance between the target and context word
+///
+/// # Returns
+///
+/// A vector of target and context word pairs
+fn skipgram_pairs(&self, layer : &str, window : usize) -> TeangaResult<Vec<(String, String)>> {
+    let mut pairs = Vec::new();
+    for doc_id in self.get_docs() {
+        let doc = self.get_doc_by_id(&doc_id)?;
+        let text = doc.text(layer, self.get_meta())?;
+        for (i, target) in text.iter().enumerate() {
+            let end = (i + window + 1).min(text.len());
+            for (j, context) in text.iter().enumerate().take(end).skip(i.saturating_sub(window)) {
+                if i != j {
+                    pairs.push((target.to_string(), context.to_string()));
+                }
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+/// Count how often two words occur near each other. Each pair of positions
+/// within the window is counted once and the words of a pair are sorted, so
+/// that `(a, b)` and `(b, a)` are counted together. Pairs do not cross
+/// document boundaries
+///
+/// # Arguments
+///
+/// * `layer` - The layer containing the words
+/// * `window` - The maximum distance between the two words
+///
+/// # Returns
+///
+/// A map from sorted word pairs to the number of times they co-occur
+fn cooccurrence(&self, layer : &str, window : usize) -> TeangaResult<HashMap<(String, String), u32>> {
+    // Count pairs of vocabulary indexes to avoid cloning the words for each
+    // pair
+    let mut vocab : HashMap<String, usize> = HashMap::new();
+    let mut counts : HashMap<(usize, usize), u32> = HashMap::new();
+    for doc_id in self.get_docs() {
+        let doc = self.get_doc_by_id(&doc_id)?;
+        let text = doc.text(layer, self.get_meta())?;
+        let ids : Vec<usize> = text.iter().map(|word| match vocab.get(*word) {
+            Some(id) => *id,
+            None => {
+                let id = vocab.len();
+                vocab.insert(word.to_string(), id);
+                id
+            }
+        }).collect();
+        for (i, a) in ids.iter().enumerate() {
+            for b in ids.iter().skip(i + 1).take(window) {
+                *counts.entry((*a, *b)).or_insert(0) += 1;
+            }
+        }
+    }
+    let words : Vec<&String> = vocab.iter().sorted_by_key(|(_, id)| **id).map(|(w, _)| w).collect();
+    let mut cooccurrence = HashMap::new();
+    for ((a, b), count) in counts {
+        let (a, b) = (words[a], words[b]);
+        let key = if a <= b { (a.clone(), b.clone()) } else { (b.clone(), a.clone()) };
+        *cooccurrence.entry(key).or_insert(0) += count;
+    }
+    Ok(cooccurrence)
+}
+
+/// Find all occurrences of a keyword in a layer together with their context
+/// (key-word in context)
+///
+/// # Arguments
+///
+/// * `layer` - The layer whose annotations are matched against the keyword
+/// * `needle` - The keyword to find
+/// * `window` - The number of annotations of context on each side
+///
+/// # Returns
+///
+/// A concordance line for each occurrence of the keyword
+fn concordance(&self, layer : &str, needle : &str, window : usize) -> TeangaResult<Vec<Concordance>> {
+    let mut lines = Vec::new();
+    for doc in self.iter_doc_ids() {
+        let (doc_id, doc) = doc?;
+        lines.extend(concordance::doc_concordance(&doc_id, &doc, layer, needle, window, self.get_meta())?);
+    }
+    Ok(lines)
+}
+
+/// Call a function for every annotation of every layer in the corpus with
+/// the span of text it covers. Documents are visited in order and layers in
+/// alphabetical order. Characters layers and metadata layers are skipped
+///
+/// # Arguments
+///
+/// * `f` - The function, called with the document ID, the layer name, the
+///   start and end offsets in the characters layer (as returned by
+///   `Document::indexes_data`) and the data of the annotation
+fn each_annotation<F : FnMut(&str, &str, usize, usize, &TeangaData)>(&self, mut f : F) -> TeangaResult<()> {
+    let graph = self.layer_graph();
+    for doc in self.iter_doc_ids() {
+        let (doc_id, doc) = doc?;
+        for layer in doc.keys().into_iter().sorted() {
+            let layer_desc = self.get_meta().get(&layer)
+                .ok_or_else(|| TeangaError::LayerNotFoundError(layer.clone()))?;
+            if layer_desc.layer_type == LayerType::characters ||
+                matches!(doc.get(&layer), Some(Layer::MetaLayer(_))) {
+                continue;
+            }
+            let char_layer = graph.root_of(&layer)?;
+            for (start, end, data) in doc.indexes_data(&layer, char_layer, self.get_meta())? {
+                f(&doc_id, &layer, start, end, &data);
+            }
+        }
+    }
+    Ok(())
 }
 
-/// Calculate the frequency of words in the text layers of the corpus
+/// Check if two corpora have the same metadata and documents, ignoring the
+/// order of the documents. A document that cannot be read in either corpus
+/// makes the corpora unequal
 ///
 /// # Arguments
 ///
-/// * `layer` - The layer to calculate the frequency of
-/// * `condition` - A condition that must be met for a word to be counted
+/// * `other` - The corpus to compare with
+fn content_eq<C : Corpus>(&self, other : &C) -> bool {
+    if self.get_meta() != other.get_meta() {
+        return false;
+    }
+    let ids : HashSet<String> = self.get_docs().into_iter().collect();
+    if ids.len() != self.get_docs().len() || ids != other.get_docs().into_iter().collect() {
+        return false;
+    }
+    ids.iter().all(|id| match (self.get_doc_by_id(id), other.get_doc_by_id(id)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false
+    })
+}
+
+/// Get the dependency graph of the layers in this corpus
 ///
 /// # Returns
 ///
-/// A map from words to their frequency
-fn text_freq<C: TextMatchCondition>(&self, layer : &str, condition : C) -> TeangaResult<HashMap<String, u32>> {
-    let mut freq = HashMap::new();
-    for doc_id in self.get_docs() {
-        let doc = self.get_doc_by_id(&doc_id)?;
-        let text = doc.text(layer, self.get_meta())?;
-        for word in text {
-            if condition.matches(word) {
-                *freq.entry(word.to_string()).or_insert(0) += 1;
-            }
-        }
-    }
-    Ok(freq)
+/// A graph where each layer is a child of its base layer
+fn layer_graph(&self) -> LayerGraph {
+    LayerGraph::new(self.get_meta())
 }
 
 /// Calculate the frequency of values in a data layer of the corpus
@@ -199,6 +1081,94 @@ fn val_freq<C: DataMatchCondition>(&self, layer : &str, condition : C) -> Teanga
     }
     Ok(freq)
 } 
+
+/// Get the distinct string values that occur in a data layer of the corpus
+///
+/// # Arguments
+///
+/// * `layer` - The layer to collect the values of
+///
+/// # Returns
+///
+/// The set of values in sorted order
+fn distinct_values(&self, layer : &str) -> TeangaResult<BTreeSet<String>> {
+    if !self.get_meta().contains_key(layer) {
+        return Err(TeangaError::LayerNotFoundError(layer.to_string()));
+    }
+    let mut values = BTreeSet::new();
+    for doc in self.iter_docs() {
+        if let Some(data) = doc?.data(layer, self.get_meta()) {
+            for val in data {
+                if let TeangaData::String(s) = val {
+                    values.insert(s);
+                }
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// Copy this corpus into memory. The metadata, documents and their IDs and
+/// the order of the documents are preserved
+///
+/// # Returns
+///
+/// A new in-memory corpus with the same content
+fn to_simple_corpus(&self) -> TeangaResult<SimpleCorpus> {
+    let mut content = HashMap::new();
+    for doc in self.iter_doc_ids() {
+        let (id, doc) = doc?;
+        content.insert(id, doc);
+    }
+    Ok(SimpleCorpus {
+        meta: self.get_meta().clone(),
+        order: self.get_docs(),
+        content
+    })
+}
+
+/// Remove documents with the same text as an earlier document in the
+/// corpus, keeping the first occurrence
+///
+/// # Returns
+///
+/// The number of documents removed
+fn dedup(&mut self) -> TeangaResult<usize> {
+    Ok(self.dedup_ids()?.len())
+}
+
+/// Remove documents with the same text as an earlier document in the
+/// corpus, keeping the first occurrence. Documents are duplicates if they
+/// have the same characters layers, which are compared in full rather than
+/// by their IDs
+///
+/// # Returns
+///
+/// The IDs of the documents removed
+fn dedup_ids(&mut self) -> TeangaResult<Vec<String>> {
+    let removed = duplicate_ids(self)?;
+    for id in removed.iter() {
+        self.remove_doc(id)?;
+    }
+    Ok(removed)
+}
+
+/// Check that the corpus is consistent with its metadata. This checks that:
+///
+/// * the base and target layers are declared and do not form a cycle
+/// * every layer of every document is declared and has annotations of the
+///   form of its layer type
+/// * every index is within the base layer
+/// * every link refers to an annotation that exists
+/// * every value of an enum layer is one of the declared values
+///
+/// # Returns
+///
+/// All inconsistencies that were found
+fn validate(&self) -> Vec<ValidationError> {
+    validation::validate(self)
+}
+
 /// Iterate over all documents in the corpus
 fn iter_docs<'a>(&'a self) -> Box<dyn Iterator<Item=TeangaResult<Document>> + 'a> {
     Box::new(self.get_docs().into_iter().map(move |x| self.get_doc_by_id(&x)))
@@ -208,6 +1178,31 @@ fn iter_doc_ids<'a>(&'a self) -> Box<dyn Iterator<Item=TeangaResult<(String, Doc
     Box::new(self.get_docs().into_iter().map(move |x| self.get_doc_by_id(&x).map(|d| (x, d))))
 }
 
+/// Iterate over all documents in the corpus with their IDs, skipping any
+/// documents that cannot be read. The errors for the skipped documents can
+/// be obtained from the iterator once it is finished
+///
+/// # Examples
+///
+/// ```rust
+/// use teanga::{SimpleCorpus, Corpus};
+/// let corpus = SimpleCorpus::new();
+/// let mut docs = corpus.iter_docs_lenient();
+/// for (id, doc) in &mut docs {
+///     // ...
+/// }
+/// assert!(docs.errors().is_empty());
+/// ```
+fn iter_docs_lenient<'a>(&'a self) -> LenientDocIter<'a> {
+    LenientDocIter {
+        docs: Box::new(self.get_docs().into_iter().map(move |x| {
+            let doc = self.get_doc_by_id(&x);
+            (x, doc)
+        })),
+        errors: Vec::new()
+    }
+}
+
 /// Search the corpus for documents that match a query
 ///
 /// # Arguments
@@ -223,14 +1218,385 @@ fn search<'a>(&'a self, query : Query) -> Box<dyn Iterator<Item=TeangaResult<(St
         Err(_) => false
     }))
 }
+
+/// Search the corpus and return a concordance line for each place that the
+/// query matches, see `Query::match_offsets`
+///
+/// # Arguments
+///
+/// * `query` - The query
+/// * `context` - The number of characters of context on each side of a match
+///
+/// # Returns
+///
+/// An iterator of the concordance lines in document order
+fn search_concordance<'a>(&'a self, query : Query, context : usize)
+    -> Box<dyn Iterator<Item=TeangaResult<Concordance>> + 'a> {
+    Box::new(self.iter_doc_ids().flat_map(move |x| {
+        let lines = x.and_then(|(id, doc)|
+            concordance::query_concordance(&id, &doc, &query, context, self.get_meta()));
+        match lines {
+            Ok(lines) => lines.into_iter().map(Ok).collect::<Vec<_>>(),
+            Err(e) => vec![Err(e)]
+        }
+    }))
+}
+
+/// Search for sequences of annotations, e.g., an adjective followed by a
+/// noun, see `Query::Sequence`
+///
+/// # Arguments
+///
+/// * `layer` - The layer of the sequence
+/// * `patterns` - The pattern that each annotation must match
+///
+/// # Returns
+///
+/// An iterator of the IDs of the documents with at least one match and
+/// the start and end (exclusive) of each match as indexes of the
+/// annotations of the layer
+fn search_spans<'a>(&'a self, layer : &'a str, patterns : Vec<TokenPattern>)
+    -> Box<dyn Iterator<Item=TeangaResult<SpanMatches>> + 'a> {
+    Box::new(self.iter_doc_ids().filter_map(move |x| match x {
+        Ok((id, doc)) => {
+            let spans = query::sequence_spans(layer, &patterns, &doc, self.get_meta());
+            (!spans.is_empty()).then_some(Ok((id, spans)))
+        },
+        Err(e) => Some(Err(e))
+    }))
+}
+}
+
+/// An iterator over the documents of a corpus that skips documents with
+/// errors, created by `Corpus::iter_docs_lenient`
+pub struct LenientDocIter<'a> {
+    docs: Box<dyn Iterator<Item=(String, TeangaResult<Document>)> + 'a>,
+    errors: Vec<(String, TeangaError)>
+}
+
+impl<'a> LenientDocIter<'a> {
+    /// The IDs and errors of the documents skipped so far
+    pub fn errors(&self) -> &[(String, TeangaError)] {
+        &self.errors
+    }
+
+    /// Consume the iterator returning the IDs and errors of the skipped documents
+    pub fn into_errors(self) -> Vec<(String, TeangaError)> {
+        self.errors
+    }
+}
+
+impl<'a> Iterator for LenientDocIter<'a> {
+    type Item = (String, Document);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (id, doc) in self.docs.by_ref() {
+            match doc {
+                Ok(doc) => return Some((id, doc)),
+                Err(e) => self.errors.push((id, e))
+            }
+        }
+        None
+    }
+}
+
+/// The unit used to measure the length of a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthUnit {
+    /// The number of characters in the text of the layer
+    Chars,
+    /// The number of whitespace separated words in the text of the layer
+    Words,
+    /// The number of annotations in the layer
+    Annotations
+}
+
+/// How words are normalized by `text_freq_with`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FreqOptions {
+    /// Convert words to lowercase. This uses the Unicode lowercase mapping
+    /// and is not locale-aware, e.g., Turkish `I` becomes `i` and not `ı`
+    pub lowercase : bool,
+    /// Apply Unicode NFC normalization, which requires the
+    /// `unicode-normalization` feature
+    pub nfc : bool,
+    /// Remove all Unicode punctuation characters
+    pub strip_punct : bool
+}
+
+impl FreqOptions {
+    /// Create new options that count words verbatim
+    pub fn new() -> FreqOptions {
+        FreqOptions::default()
+    }
+
+    /// Set whether words are converted to lowercase
+    ///
+    /// # Arguments
+    ///
+    /// * `lowercase` - Whether to lowercase words
+    pub fn with_lowercase(mut self, lowercase : bool) -> FreqOptions {
+        self.lowercase = lowercase;
+        self
+    }
+
+    /// Set whether words are NFC normalized. Counting fails if this is set
+    /// and the `unicode-normalization` feature is not enabled
+    ///
+    /// # Arguments
+    ///
+    /// * `nfc` - Whether to apply NFC normalization
+    pub fn with_nfc(mut self, nfc : bool) -> FreqOptions {
+        self.nfc = nfc;
+        self
+    }
+
+    /// Set whether punctuation characters are removed from words. Words
+    /// that consist only of punctuation are then not counted
+    ///
+    /// # Arguments
+    ///
+    /// * `strip_punct` - Whether to remove punctuation
+    pub fn with_strip_punct(mut self, strip_punct : bool) -> FreqOptions {
+        self.strip_punct = strip_punct;
+        self
+    }
+}
+
+/// The meta layer that document labels are stored in
+pub const LABEL_LAYER : &str = "_labels";
+
+/// Get a label of a document
+fn doc_label(doc : &Document, key : &str) -> Option<String> {
+    match doc.get(LABEL_LAYER) {
+        Some(Layer::MetaLayer(values)) => match values.first().and_then(|v| v.get(key)) {
+            Some(Value::String(value)) => Some(value.clone()),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/// The IDs of the documents with the same characters layers as an earlier
+/// document, see `Corpus::dedup_ids`
+pub(crate) fn duplicate_ids<C : Corpus + ?Sized>(corpus : &C) -> TeangaResult<Vec<String>> {
+let mut first_ids : HashMap<String, Vec<String>> = HashMap::new();
+let mut duplicates = Vec::new();
+for id in corpus.get_order() {
+    let doc = corpus.get_doc_by_id(id)?;
+    let candidates = first_ids.entry(doc_hash(&doc)).or_default();
+    let mut duplicate = false;
+    for first in candidates.iter() {
+        if characters_layers(&corpus.get_doc_by_id(first)?) == characters_layers(&doc) {
+            duplicate = true;
+            break;
+        }
+    }
+    if duplicate {
+        duplicates.push(id.clone());
+    } else {
+        candidates.push(id.clone());
+    }
+}
+Ok(duplicates)
+}
+
+/// The characters layers of a document, sorted by name
+fn characters_layers(doc : &Document) -> Vec<(&String, &str)> {
+doc.content.iter()
+    .filter_map(|(name, layer)| layer.characters().map(|text| (name, text)))
+    .sorted()
+    .collect()
 }
 
 /// A corpus where the metadata and order can be changed
 pub trait WriteableCorpus : Corpus {
-/// Set the metadata of the corpus
+/// Set the metadata of the corpus. This fails if a base or target layer
+/// is not declared or if the layers are based on each other in a cycle
 fn set_meta(&mut self, meta : HashMap<String, LayerDesc>) -> TeangaResult<()>;
 /// Set the order of the documents in the corpus
 fn set_order(&mut self, order : Vec<String>) -> TeangaResult<()>;
+/// Rename documents, e.g., after deduplicating or merging corpora. The
+/// documents keep their position in the order. This fails if a document to
+/// be renamed is not in the corpus or if two documents would have the same ID
+///
+/// # Arguments
+///
+/// * `mapping` - The new ID of each document to be renamed
+///
+/// Each corpus must implement this, as `add_doc` gives a document an ID
+/// derived from its content and so cannot be used to rename it
+fn apply_id_mapping(&mut self, mapping : &HashMap<String, String>) -> TeangaResult<()>;
+
+/// Change the order of the documents in the corpus to match a given order.
+/// Unlike `set_order`, the order is checked to contain every document in
+/// the corpus exactly once
+///
+/// # Arguments
+///
+/// * `ids` - The IDs of the documents in the new order
+fn reorder_to(&mut self, ids : &[String]) -> TeangaResult<()> {
+    let existing : HashSet<String> = self.get_docs().into_iter().collect();
+    let mut seen = HashSet::new();
+    for id in ids {
+        if !existing.contains(id) {
+            return Err(TeangaError::ModelError(
+                format!("Document {} is not in the corpus", id)));
+        }
+        if !seen.insert(id) {
+            return Err(TeangaError::ModelError(
+                format!("Document {} occurs more than once in the order", id)));
+        }
+    }
+    if seen.len() != existing.len() {
+        let missing = existing.iter().filter(|id| !seen.contains(id)).sorted().join(", ");
+        return Err(TeangaError::ModelError(
+            format!("Documents are missing from the order: {}", missing)));
+    }
+    self.set_order(ids.to_vec())
+}
+
+/// Rename a single value of an enum layer. The position of the value in the
+/// enumeration is preserved, so stored indexes remain valid, and documents
+/// storing the value itself are updated
+///
+/// # Arguments
+///
+/// * `layer` - The layer with enum data
+/// * `old` - The value to rename
+/// * `new` - The new name of the value
+fn rename_enum_value(&mut self, layer : &str, old : &str, new : &str) -> TeangaResult<()> {
+    let mut meta = self.get_meta().clone();
+    let layer_desc = meta.get_mut(layer)
+        .ok_or_else(|| TeangaError::LayerNotFoundError(layer.to_string()))?;
+    match layer_desc.data {
+        Some(DataType::Enum(ref mut vals)) => {
+            if vals.iter().any(|v| v == new) {
+                return Err(TeangaError::ModelError(
+                    format!("Value {} already exists in layer {}", new, layer)));
+            }
+            let val = vals.iter_mut().find(|v| *v == old)
+                .ok_or_else(|| TeangaError::ModelError(
+                    format!("Value {} does not exist in layer {}", old, layer)))?;
+            *val = new.to_string();
+        },
+        _ => return Err(TeangaError::ModelError(
+            format!("Layer {} does not have enum data", layer)))
+    }
+    self.set_meta(meta)?;
+    for doc_id in self.get_docs() {
+        let doc = self.get_doc_by_id(&doc_id)?;
+        let mut changed = false;
+        let rename = |s : &mut String| if s == old {
+            *s = new.to_string();
+            true
+        } else {
+            false
+        };
+        let renamed = match doc.get(layer) {
+            Some(Layer::LS(vals)) => {
+                let mut vals = vals.clone();
+                for v in vals.iter_mut() { changed |= rename(v); }
+                Layer::LS(vals)
+            },
+            Some(Layer::L1S(vals)) => {
+                let mut vals = vals.clone();
+                for v in vals.iter_mut() { changed |= rename(&mut v.1); }
+                Layer::L1S(vals)
+            },
+            Some(Layer::L2S(vals)) => {
+                let mut vals = vals.clone();
+                for v in vals.iter_mut() { changed |= rename(&mut v.2); }
+                Layer::L2S(vals)
+            },
+            Some(Layer::L3S(vals)) => {
+                let mut vals = vals.clone();
+                for v in vals.iter_mut() { changed |= rename(&mut v.3); }
+                Layer::L3S(vals)
+            },
+            _ => continue
+        };
+        if changed {
+            self.update_doc(&doc_id, vec![(layer.to_string(), renamed)])?;
+        }
+    }
+    Ok(())
+}
+
+/// Change the type of a layer and convert the layer in every document with
+/// `Layer::convert_type`. Seq and element layers can be converted to each
+/// other, as can span and div layers. If the layer cannot be converted in
+/// any document, the corpus is not changed
+///
+/// # Arguments
+///
+/// * `layer` - The layer to change
+/// * `new_type` - The new type of the layer
+fn change_layer_type(&mut self, layer : &str, new_type : LayerType) -> TeangaResult<()> {
+    let mut meta = self.get_meta().clone();
+    let layer_desc = meta.get(layer)
+        .ok_or_else(|| TeangaError::LayerNotFoundError(layer.to_string()))?;
+    if new_type == LayerType::seq && layer_desc.data.is_none() {
+        return Err(TeangaError::ModelError(
+            format!("Layer {} cannot be a seq layer as it has no data", layer)));
+    }
+    let mut converted = Vec::new();
+    for (doc_id, doc) in self.iter_doc_ids().collect::<TeangaResult<Vec<_>>>()? {
+        let Some(l) = doc.get(layer) else {
+            continue;
+        };
+        let base_len = match layer_desc.base {
+            Some(ref base) => doc.get(base)
+                .ok_or_else(|| TeangaError::LayerNotFoundError(base.clone()))?.len(),
+            None => 0
+        };
+        let new_layer = l.convert_type(layer_desc, &new_type, base_len)
+            .map_err(|e| TeangaError::ModelError(format!("In document {}: {}", doc_id, e)))?;
+        converted.push((doc_id, new_layer));
+    }
+    meta.get_mut(layer).unwrap().layer_type = new_type;
+    self.set_meta(meta)?;
+    for (doc_id, new_layer) in converted {
+        self.update_doc(&doc_id, vec![(layer.to_string(), new_layer)])?;
+    }
+    Ok(())
+}
+
+/// Split a document into several documents at positions in its text. Every
+/// layer of the document is split with `Layer::split_at`, so that the new
+/// documents together contain the annotations of the original document.
+/// The new documents take the place of the original document in the order.
+///
+/// The document must have a single characters layer. Spans that cross a
+/// split position are dropped and div segments are cut at the position.
+/// If this happens in a layer that other layers are based on, the document
+/// cannot be split. Links are updated to refer to the annotations in the new
+/// document. An annotation linking to an annotation in another of the new
+/// documents is dropped, or, for seq layers (which need a value for every
+/// element), the document cannot be split.
+///
+/// # Arguments
+///
+/// * `id` - The ID of the document to split
+/// * `char_positions` - The byte offsets in the text to split the document at
+///
+/// # Returns
+///
+/// The IDs of the new documents
+fn split_document(&mut self, id : &str, char_positions : &[usize]) -> TeangaResult<Vec<String>> {
+    let doc = self.get_doc_by_id(id)?;
+    let order = self.get_order().clone();
+    let pieces = doc.split_layers(char_positions, self.get_meta())?;
+    let mut ids = Vec::new();
+    for content in pieces {
+        ids.push(self.add_doc(content)?);
+    }
+    self.remove_doc(id)?;
+    self.set_order(order.into_iter()
+        .flat_map(|d| if d == id { ids.clone() } else { vec![d] })
+        .collect())?;
+    Ok(ids)
+}
 }
 
 
@@ -257,6 +1623,40 @@ pub fn read_yaml_header<'de, R: std::io::Read>(&mut self, r: R) -> Result<(), Te
     Ok(crate::serialization::read_yaml_meta(r, self)?)
 }
 
+/// Iterate over a single layer of every document in the corpus without
+/// copying the documents
+///
+/// # Arguments
+///
+/// * `layer` - The name of the layer
+///
+/// # Returns
+///
+/// An iterator over the ID of each document and the layer, if the document
+/// has this layer
+pub fn iter_layer<'a>(&'a self, layer : &'a str) -> Box<dyn Iterator<Item=(&'a str, Option<&'a Layer>)> + 'a> {
+    Box::new(self.order.iter().map(move |id| (id.as_str(),
+        self.content.get(id).and_then(|doc| doc.get(layer)))))
+}
+
+/// Add documents from an iterator, e.g., the output of an annotator. The
+/// layers of the documents must already be declared in the corpus
+///
+/// # Arguments
+///
+/// * `docs` - The documents to add
+///
+/// # Returns
+///
+/// The IDs of the added documents
+pub fn extend_docs<I : IntoIterator<Item=Document>>(&mut self, docs : I) -> TeangaResult<Vec<String>> {
+    let mut ids = Vec::new();
+    for doc in docs {
+        ids.push(self.add_doc(doc)?);
+    }
+    Ok(ids)
+}
+
 }
 
 impl Corpus for SimpleCorpus {
@@ -267,7 +1667,9 @@ fn add_layer_meta(&mut self, name: String, layer_type: LayerType,
     base: Option<String>, data: Option<DataType>, link_types: Option<Vec<String>>, 
     target: Option<String>, default: Option<Layer>,
     meta : HashMap<String, Value>) -> TeangaResult<()> {
-    self.meta.insert(name.clone(), LayerDesc {
+    crate::layer::check_meta_values([&meta])?;
+    let mut new_meta = self.meta.clone();
+    new_meta.insert(name.clone(), LayerDesc {
         layer_type,
         base,
         data,
@@ -276,6 +1678,8 @@ fn add_layer_meta(&mut self, name: String, layer_type: LayerType,
         default,
         meta
     });
+    check_meta(&new_meta)?;
+    self.meta = new_meta;
     Ok(())
 }
 
@@ -291,8 +1695,8 @@ fn update_doc<D : IntoLayer, DC: DocumentContent<D>>(&mut self, id : &str, conte
     let doc = match self.get_doc_by_id(id) {
         Ok(mut doc) => {
             for (key, layer) in content {
-                let layer_desc = self.meta.get(&key).ok_or_else(|| TeangaError::ModelError(
-                    format!("Layer {} is not described in meta", key)))?;
+                let layer_desc = self.meta.get(&key).ok_or_else(||
+                    TeangaError::LayerNotFoundError(key.clone()))?;
                 doc.set(&key, layer.into_layer(layer_desc)?);
             }
             doc
@@ -344,29 +1748,178 @@ fn get_order(&self) -> &Vec<String> {
 
 impl WriteableCorpus for SimpleCorpus {
 fn set_meta(&mut self, meta : HashMap<String, LayerDesc>) -> TeangaResult<()> {
+    check_meta(&meta)?;
     self.meta = meta;
     Ok(())
 }
 
-fn set_order(&mut self, order : Vec<String>) -> TeangaResult<()> {
-    self.order = order;
-    Ok(())
+fn set_order(&mut self, order : Vec<String>) -> TeangaResult<()> {
+    self.order = order;
+    Ok(())
+}
+
+fn apply_id_mapping(&mut self, mapping : &HashMap<String, String>) -> TeangaResult<()> {
+    let order = mapped_order(&self.order, mapping)?;
+    let docs : Vec<(String, Document)> = mapping.iter()
+        .filter_map(|(old, new)| self.content.remove(old).map(|doc| (new.clone(), doc)))
+        .collect();
+    self.content.extend(docs);
+    self.order = order;
+    Ok(())
+}
+}
+
+impl std::ops::Index<usize> for SimpleCorpus {
+type Output = Document;
+
+/// Get the document at a position in the order of the corpus. This panics
+/// if the index is out of bounds, use `Corpus::get_doc_by_index` to get an
+/// error instead. This is the stored document, so layers that only have a
+/// default value in the metadata are not included, as with `get_doc_by_id`.
+/// Use `Corpus::get_doc_resolved` to include them
+fn index(&self, n : usize) -> &Document {
+    &self.content[&self.order[n]]
+}
+}
+
+impl<'a> IntoIterator for &'a SimpleCorpus {
+type Item = &'a Document;
+type IntoIter = SimpleCorpusIter<'a>;
+
+fn into_iter(self) -> SimpleCorpusIter<'a> {
+    SimpleCorpusIter {
+        order: self.order.iter(),
+        content: &self.content
+    }
+}
+}
+
+/// An iterator over the documents of a `SimpleCorpus` in order, without
+/// copying the documents. These are the stored documents, so layers that
+/// only have a default value in the metadata are not included
+pub struct SimpleCorpusIter<'a> {
+    order: std::slice::Iter<'a, String>,
+    content: &'a HashMap<String, Document>
+}
+
+impl<'a> Iterator for SimpleCorpusIter<'a> {
+    type Item = &'a Document;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.order.next().map(|id| &self.content[id])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.order.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for SimpleCorpusIter<'a> {}
+
+#[derive(Debug,Clone,PartialEq, Serialize,Deserialize)]
+/// Any valid JSON/YAML value
+pub enum Value {
+Bool(bool),
+Int(i64),
+Float(f64),
+String(String),
+Array(Vec<Value>),
+Object(#[serde(serialize_with = "crate::serialization::serialize_sorted")] HashMap<String, Value>)
+}
+
+impl Value {
+/// Get this value as an integer, if it is an integer or a float with
+/// no fractional part
+pub fn as_i64(&self) -> Option<i64> {
+    match self {
+        Value::Int(i) => Some(*i),
+        Value::Float(f) if f.fract() == 0.0 && *f >= i64::MIN as f64 && *f <= i64::MAX as f64 => Some(*f as i64),
+        _ => None
+    }
+}
+
+/// Check that this value contains no NaN or infinite floats. Such values
+/// cannot be represented in JSON and so are rejected when added to a corpus
+pub fn is_finite(&self) -> bool {
+    match self {
+        Value::Float(f) => f.is_finite(),
+        Value::Array(vals) => vals.iter().all(|v| v.is_finite()),
+        Value::Object(vals) => vals.values().all(|v| v.is_finite()),
+        _ => true
+    }
+}
+
+/// Get this value as an index (e.g., a link to another annotation), if it
+/// is an integer in the range of `u32`
+pub fn as_u32(&self) -> Option<u32> {
+    self.as_i64().and_then(|i| u32::try_from(i).ok())
+}
+
+/// Get a value nested in objects and arrays. Each element of the path is
+/// a key of an object or the index of an element of an array
+///
+/// # Arguments
+///
+/// * `path` - The keys and indexes to follow
+///
+/// # Returns
+///
+/// The value at the path, or None if the path does not exist
+pub fn get_path(&self, path : &[&str]) -> Option<&Value> {
+    let mut value = self;
+    for key in path {
+        value = match value {
+            Value::Object(vals) => vals.get(*key)?,
+            Value::Array(vals) => vals.get(key.parse::<usize>().ok()?)?,
+            _ => return None
+        };
+    }
+    Some(value)
+}
+
+/// Get a mutable reference to a value nested in objects and arrays. See
+/// `get_path`
+pub fn get_path_mut(&mut self, path : &[&str]) -> Option<&mut Value> {
+    let mut value = self;
+    for key in path {
+        value = match value {
+            Value::Object(vals) => vals.get_mut(*key)?,
+            Value::Array(vals) => vals.get_mut(key.parse::<usize>().ok()?)?,
+            _ => return None
+        };
+    }
+    Some(value)
+}
+}
+
+impl From<i32> for Value {
+fn from(i : i32) -> Value {
+    Value::Int(i as i64)
 }
 }
 
-#[derive(Debug,Clone,PartialEq, Serialize,Deserialize)]
-/// Any valid JSON/YAML value
-pub enum Value {
-Bool(bool),
-Int(i32),
-Float(f64),
-String(String),
-Array(Vec<Value>),
-Object(HashMap<String, Value>)
+impl From<i64> for Value {
+fn from(i : i64) -> Value {
+    Value::Int(i)
+}
+}
+
+impl From<u32> for Value {
+fn from(i : u32) -> Value {
+    Value::Int(i as i64)
+}
 }
 
 /// Generate a unique ID for a document
 ///
+/// The ID is a prefix of the base64 encoded SHA-256 hash of the characters
+/// layers of the document. Other layers are not hashed, so documents that have
+/// no characters layers (e.g., pure annotation documents) all have the same hash
+/// and are distinguished only by extending the prefix in the order they are
+/// added. If the full hash is already used then a numeric suffix is added, so
+/// that the result is always unique and deterministic given `existing_keys`.
+/// Use `teanga_id_checked` to reject such documents instead.
+///
 /// # Arguments
 ///
 /// * `existing_keys` - The existing keys in the corpus
@@ -376,24 +1929,26 @@ Object(HashMap<String, Value>)
 ///
 /// A unique ID for the document
 pub fn teanga_id(existing_keys : &Vec<String>, doc : &Document) -> String {
-let mut hasher = Sha256::new();
-for key in doc.content.keys().sorted() {
-    match doc.content.get(key).unwrap() {
-        Layer::Characters(val) => {
-            hasher.update(key.as_bytes());
-            hasher.update(vec![0u8]);
-            hasher.update(val.as_bytes());
-            hasher.update(vec![0u8]);
-        }
-        _ => ()
-    }
+unique_id(&doc_hash(doc), |id| existing_keys.iter().any(|k| k == id))
 }
-let code = STANDARD.encode(hasher.finalize().as_slice());
-let mut n = 4;
-while existing_keys.contains(&code[..n].to_string()) && n < code.len() {
-    n += 1;
+
+/// Generate a unique ID for a document, failing if the document has no
+/// characters layers and so no text content to identify it
+///
+/// # Arguments
+///
+/// * `existing_keys` - The existing keys in the corpus
+/// * `doc` - The document
+///
+/// # Returns
+///
+/// A unique ID for the document or an error if the document has no text
+pub fn teanga_id_checked(existing_keys : &Vec<String>, doc : &Document) -> TeangaResult<String> {
+if !doc.content.values().any(|l| matches!(l, Layer::Characters(_))) {
+    return Err(TeangaError::ModelError(
+        "Document has no characters layer and so cannot be given an ID".to_string()));
 }
-return code[..n].to_string();
+Ok(teanga_id(existing_keys, doc))
 }
 
 /// Generate a new unique ID for a document. 
@@ -410,6 +1965,10 @@ return code[..n].to_string();
 ///
 /// A unique ID for the document
 pub fn teanga_id_update(prev_val : &str, existing_keys: &Vec<String>, doc : &Document) -> String {
+unique_id(&doc_hash(doc), |id| id != prev_val && existing_keys.iter().any(|k| k == id))
+}
+
+pub(crate) fn doc_hash(doc : &Document) -> String {
 let mut hasher = Sha256::new();
 for key in doc.content.keys().sorted() {
     match doc.content.get(key).unwrap() {
@@ -422,12 +1981,96 @@ for key in doc.content.keys().sorted() {
         _ => ()
     }
 }
-let code = STANDARD.encode(hasher.finalize().as_slice());
+STANDARD.encode(hasher.finalize().as_slice())
+}
+
+/// Check that the base and target of every layer is declared and that the
+/// layers are not based on each other in a cycle
+pub(crate) fn check_meta(meta : &HashMap<String, LayerDesc>) -> TeangaResult<()> {
+for name in meta.keys().sorted() {
+    let layer_desc = &meta[name];
+    for (kind, referenced) in [("base", &layer_desc.base), ("target", &layer_desc.target)] {
+        if let Some(referenced) = referenced.as_ref().filter(|r| !r.is_empty()) {
+            if !meta.contains_key(referenced) {
+                return Err(TeangaError::ModelError(
+                    format!("The {} layer {} of layer {} is not declared", kind, referenced, name)));
+            }
+        }
+    }
+}
+LayerGraph::new(meta).topo_order()?;
+Ok(())
+}
+
+/// The order of the documents after renaming them, checking that only
+/// documents in the corpus are renamed and that the IDs stay unique
+pub(crate) fn mapped_order(order : &[String], mapping : &HashMap<String, String>) -> TeangaResult<Vec<String>> {
+let existing : HashSet<&String> = order.iter().collect();
+if let Some(id) = mapping.keys().sorted().find(|id| !existing.contains(id)) {
+    return Err(TeangaError::ModelError(
+        format!("Document {} is not in the corpus", id)));
+}
+let new_order : Vec<String> = order.iter()
+    .map(|id| mapping.get(id).unwrap_or(id).clone())
+    .collect();
+let mut seen = HashSet::new();
+if let Some(id) = new_order.iter().find(|id| !seen.insert(*id)) {
+    return Err(TeangaError::ModelError(
+        format!("More than one document would have the ID {}", id)));
+}
+Ok(new_order)
+}
+
+/// A small deterministic random number generator (SplitMix64)
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+fn next_u64(&mut self) -> u64 {
+    self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = self.0;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// A number in the range `0..n`
+fn below(&mut self, n : u64) -> u64 {
+    self.next_u64() % n
+}
+}
+
+/// Change the links in a layer with link data, removing annotations for
+/// which the function returns None
+fn remap_links<F : Fn(u32) -> Option<u32>>(layer : &Layer, layer_type : &LayerType, f : F) -> Layer {
+match (layer, layer_type) {
+    (Layer::L1(v), _) => Layer::L1(v.iter().filter_map(|l| f(*l)).collect()),
+    (Layer::L1S(v), LayerType::seq) => Layer::L1S(v.iter()
+        .filter_map(|(l, t)| f(*l).map(|l| (l, t.clone()))).collect()),
+    (Layer::L2(v), t) if *t != LayerType::span => Layer::L2(v.iter()
+        .filter_map(|(i, l)| f(*l).map(|l| (*i, l))).collect()),
+    (Layer::L2S(v), t) if *t != LayerType::span => Layer::L2S(v.iter()
+        .filter_map(|(i, l, t)| f(*l).map(|l| (*i, l, t.clone()))).collect()),
+    (Layer::L3(v), _) => Layer::L3(v.iter()
+        .filter_map(|(s, e, l)| f(*l).map(|l| (*s, *e, l))).collect()),
+    (Layer::L3S(v), _) => Layer::L3S(v.iter()
+        .filter_map(|(s, e, l, t)| f(*l).map(|l| (*s, *e, l, t.clone()))).collect()),
+    _ => layer.clone()
+}
+}
+
+fn unique_id<F : Fn(&str) -> bool>(code : &str, taken : F) -> String {
 let mut n = 4;
-while *prev_val != code[..n] && existing_keys.contains(&code[..n].to_string()) && n < code.len() {
+while taken(&code[..n]) && n < code.len() {
     n += 1;
 }
-return code[..n].to_string();
+if !taken(&code[..n]) {
+    return code[..n].to_string();
+}
+let mut i = 1;
+while taken(&format!("{}{}", code, i)) {
+    i += 1;
+}
+format!("{}{}", code, i)
 }
 
 /// An error type for Teanga
@@ -465,9 +2108,11 @@ pub enum TeangaError {
     #[error("DB commit error: {0}")]
     DBCommitError(#[from] redb::CommitError),
     /// Errors in serializing data
+    #[cfg(feature = "tcf")]
     #[error("Data error: {0}")]
     DataError(#[from] ciborium::ser::Error<std::io::Error>),
     /// Errors in deserializing data
+    #[cfg(feature = "tcf")]
     #[error("Data error: {0}")]
     DataError2(#[from] ciborium::de::Error<std::io::Error>),
     /// Errors due to string encoding
@@ -480,6 +2125,7 @@ pub enum TeangaError {
     #[error("TCF Corpora cannot be mutated")]
     TCFMutError,
     /// Errors readings a file
+    #[cfg(feature = "tcf")]
     #[error("TCF Read Error: {0}")]
     TCFReadError(#[from] crate::tcf::TCFError),
     /// A document does not exist in the corpus
@@ -545,6 +2191,20 @@ mod test {
         assert_eq!(teanga_id(&existing_keys, &doc), expected);
     }
 
+    #[test]
+    fn test_teanga_id_no_text() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        for _ in 0..50 {
+            corpus.add_doc(vec![("words".to_string(), vec![(0, 1)])]).unwrap();
+        }
+        let ids : std::collections::HashSet<String> = corpus.get_docs().into_iter().collect();
+        assert_eq!(ids.len(), 50);
+        let doc = corpus.get_doc_by_id(&corpus.get_docs()[0]).unwrap();
+        assert!(teanga_id_checked(&Vec::new(), &doc).is_err());
+    }
+
     #[test]
     fn test_serialize_layer() {
         let layer = Layer::L1S(vec![(1,"a".to_string()),(2,"b".to_string())]);
@@ -557,6 +2217,837 @@ mod test {
         assert_eq!(layer3, layer4);
     }
 
+    #[test]
+    fn test_value_int_range() {
+        let value = Value::Int(1_700_000_000_000);
+        let s = serde_json::to_string(&value).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&s).unwrap(), value);
+        assert_eq!(Value::from(u32::MAX).as_u32(), Some(u32::MAX));
+        assert_eq!(value.as_u32(), None);
+        assert_eq!(Value::Float(3.0).as_i64(), Some(3));
+    }
+
+    #[test]
+    fn test_value_get_path() {
+        let mut value = Value::Object(HashMap::from([
+            ("source".to_string(), Value::Object(HashMap::from([
+                ("domain".to_string(), Value::String("news".to_string())),
+                ("tags".to_string(), Value::Array(vec![Value::Int(1), Value::Int(2)]))])))]));
+        assert_eq!(value.get_path(&["source", "domain"]), Some(&Value::String("news".to_string())));
+        assert_eq!(value.get_path(&["source", "tags", "1"]), Some(&Value::Int(2)));
+        assert_eq!(value.get_path(&[]), Some(&value));
+        assert_eq!(value.get_path(&["source", "domain", "x"]), None);
+        assert_eq!(value.get_path(&["source", "tags", "x"]), None);
+        *value.get_path_mut(&["source", "domain"]).unwrap() = Value::String("web".to_string());
+        assert_eq!(value.get_path(&["source", "domain"]), Some(&Value::String("web".to_string())));
+    }
+
+    #[test]
+    fn test_set_meta_checked() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        let mut meta = corpus.get_meta().clone();
+        meta.insert("words".to_string(), LayerDesc {
+            layer_type: LayerType::span, base: Some("tokens".to_string()), ..LayerDesc::default() });
+        assert!(corpus.set_meta(meta.clone()).is_err());
+        meta.insert("tokens".to_string(), LayerDesc {
+            layer_type: LayerType::span, base: Some("words".to_string()), ..LayerDesc::default() });
+        match corpus.set_meta(meta) {
+            Err(TeangaError::ModelError(msg)) => assert!(msg.contains("tokens, words")),
+            r => panic!("Expected a cycle error but got {:?}", r)
+        }
+        assert_eq!(corpus.get_meta().len(), 1);
+        assert!(corpus.build_layer("a").base("missing").add().is_err());
+        assert!(!corpus.get_meta().contains_key("a"));
+    }
+
+    #[test]
+    fn test_provenance() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        let mut meta = corpus.get_meta().clone();
+        let desc = meta.get_mut("text").unwrap();
+        assert_eq!(desc.provenance(), None);
+        desc.set_provenance("tokenizer", Some("1.0"), None);
+        corpus.set_meta(meta).unwrap();
+        let mut out = Vec::new();
+        crate::write_json(&mut out, &corpus).unwrap();
+        let mut corpus2 = SimpleCorpus::new();
+        crate::read_json(out.as_slice(), &mut corpus2).unwrap();
+        assert_eq!(corpus2.get_meta()["text"].provenance(), Some(Provenance {
+            tool: "tokenizer".to_string(),
+            version: Some("1.0".to_string()),
+            uri: None
+        }));
+    }
+
+    #[test]
+    fn test_non_finite_values() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("_info").add().unwrap();
+        let mut info = HashMap::new();
+        info.insert("score".to_string(), Value::Array(vec![Value::Float(f64::NAN)]));
+        assert!(corpus.build_doc().layer("text", "Text").unwrap()
+            .layer("_info", info).is_err());
+        let mut info = HashMap::new();
+        info.insert("score".to_string(), Value::Float(f64::INFINITY));
+        assert!(corpus.add_doc(vec![("_info".to_string(), Layer::MetaLayer(vec![info.clone()]))]).is_err());
+        assert!(corpus.add_layer_meta("other".to_string(), LayerType::characters, None, None, None, None, None, info).is_err());
+        let mut info = HashMap::new();
+        info.insert("score".to_string(), Value::Float(0.5));
+        assert!(corpus.build_doc().layer("text", "Text").unwrap()
+            .layer("_info", info).unwrap().add().is_ok());
+    }
+
+    #[test]
+    fn test_rename_enum_value() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("pos").base("words").layer_type(LayerType::seq)
+            .data(DataType::Enum(vec!["NOUN".to_string(), "VERB".to_string()])).add().unwrap();
+        let id = corpus.build_doc()
+            .layer("text", "dogs bark").unwrap()
+            .layer("words", vec![(0, 4), (5, 9)]).unwrap()
+            .layer("pos", vec!["NOUN", "VERB"]).unwrap()
+            .add().unwrap();
+        corpus.rename_enum_value("pos", "NOUN", "N").unwrap();
+        assert_eq!(corpus.get_meta()["pos"].data,
+            Some(DataType::Enum(vec!["N".to_string(), "VERB".to_string()])));
+        assert_eq!(corpus.get_doc_by_id(&id).unwrap().get("pos"),
+            Some(&Layer::LS(vec!["N".to_string(), "VERB".to_string()])));
+        assert!(corpus.rename_enum_value("pos", "VERB", "N").is_err());
+        assert!(corpus.rename_enum_value("pos", "ADJ", "A").is_err());
+        assert!(corpus.rename_enum_value("words", "A", "B").is_err());
+    }
+
+    #[test]
+    fn test_get_doc_resolved() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("document").base("text").layer_type(LayerType::div)
+            .default(Layer::L1(vec![0])).add().unwrap();
+        let id = corpus.build_doc().layer("text", "This is a document.").unwrap().add().unwrap();
+        assert!(corpus.get_doc_by_id(&id).unwrap().get("document").is_none());
+        let doc = corpus.get_doc_resolved(&id).unwrap();
+        assert_eq!(doc.get("document"), Some(&Layer::L1(vec![0])));
+        assert_eq!(doc.text("document", corpus.get_meta()).unwrap(), vec!["This is a document."]);
+    }
+
+    #[test]
+    fn test_get_doc_by_prefix() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        let id1 = corpus.build_doc().layer("text", "One").unwrap().add().unwrap();
+        let id2 = corpus.build_doc().layer("text", "Two").unwrap().add().unwrap();
+        let (id, doc) = corpus.get_doc_by_prefix(&id1[..3]).unwrap();
+        assert_eq!(id, id1);
+        assert_eq!(doc.text("text", corpus.get_meta()).unwrap(), vec!["One"]);
+        assert!(matches!(corpus.get_doc_by_prefix("!"), Err(TeangaError::DocumentNotFoundError)));
+        match corpus.get_doc_by_prefix("") {
+            Err(TeangaError::ModelError(msg)) => {
+                assert!(msg.contains(&id1));
+                assert!(msg.contains(&id2));
+            },
+            _ => panic!("Expected an ambiguous prefix error")
+        }
+    }
+
+    #[test]
+    fn test_get_doc_by_index() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        assert!(corpus.is_empty());
+        corpus.build_doc().layer("text", "One").unwrap().add().unwrap();
+        let id2 = corpus.build_doc().layer("text", "Two").unwrap().add().unwrap();
+        assert_eq!(corpus.len(), 2);
+        let (id, doc) = corpus.get_doc_by_index(1).unwrap();
+        assert_eq!(id, id2);
+        assert_eq!(doc.text("text", corpus.get_meta()).unwrap(), vec!["Two"]);
+        match corpus.get_doc_by_index(2) {
+            Err(TeangaError::ModelError(msg)) => assert!(msg.contains("out of bounds")),
+            r => panic!("Expected an out of bounds error but got {:?}", r)
+        }
+    }
+
+    #[test]
+    fn test_dedup() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("title").add().unwrap();
+        let id = corpus.build_doc().layer("text", "Dia duit").unwrap().add().unwrap();
+        corpus.build_doc().layer("text", "Dia duit").unwrap()
+            .layer("title", "Beannú").unwrap().add().unwrap();
+        let duplicate = corpus.build_doc().layer("text", "Dia duit").unwrap().add().unwrap();
+        assert_ne!(id, duplicate);
+        assert_eq!(corpus.dedup().unwrap(), 1);
+        assert_eq!(corpus.len(), 2);
+        assert_eq!(corpus.get_order()[0], id);
+        assert!(corpus.get_doc_by_id(&duplicate).is_err());
+        assert_eq!(corpus.dedup().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_simple_corpus_index_iter() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_doc().layer("text", "One").unwrap().add().unwrap();
+        corpus.build_doc().layer("text", "Two").unwrap().add().unwrap();
+        assert_eq!(corpus[1].text("text", corpus.get_meta()).unwrap(), vec!["Two"]);
+        let mut texts = Vec::new();
+        for doc in &corpus {
+            texts.extend(doc.text("text", corpus.get_meta()).unwrap());
+        }
+        assert_eq!(texts, vec!["One", "Two"]);
+        assert_eq!((&corpus).into_iter().len(), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_simple_corpus_index_out_of_bounds() {
+        let corpus = SimpleCorpus::new();
+        let _ = &corpus[0];
+    }
+
+    #[test]
+    fn test_skipgram_pairs() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_doc()
+            .layer("text", "a b c").unwrap()
+            .layer("words", vec![(0, 1), (2, 3), (4, 5)]).unwrap()
+            .add().unwrap();
+        corpus.build_doc()
+            .layer("text", "d e").unwrap()
+            .layer("words", vec![(0, 1), (2, 3)]).unwrap()
+            .add().unwrap();
+        let pairs = corpus.skipgram_pairs("words", 1).unwrap();
+        let pairs : Vec<(&str, &str)> = pairs.iter().map(|(t, c)| (t.as_str(), c.as_str())).collect();
+        assert_eq!(pairs, vec![("a", "b"), ("b", "a"), ("b", "c"), ("c", "b"), ("d", "e"), ("e", "d")]);
+    }
+
+    #[test]
+    fn test_text_freq_within() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("quote").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_doc()
+            .layer("text", "he said go now").unwrap()
+            .layer("words", vec![(0, 2), (3, 7), (8, 10), (11, 14)]).unwrap()
+            .layer("quote", vec![(8, 14)]).unwrap()
+            .add().unwrap();
+        corpus.build_doc()
+            .layer("text", "go").unwrap()
+            .layer("words", vec![(0, 2)]).unwrap()
+            .add().unwrap();
+        let freq = corpus.text_freq_within("words", "quote", crate::match_condition::AnyText).unwrap();
+        assert_eq!(freq.len(), 2);
+        assert_eq!(freq["go"], 1);
+        assert_eq!(freq["now"], 1);
+    }
+
+    #[test]
+    fn test_text_freq_with() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_doc()
+            .layer("text", "The cat, the \"end\" .").unwrap()
+            .layer("words", vec![(0, 3), (4, 8), (9, 12), (13, 18), (19, 20)]).unwrap()
+            .add().unwrap();
+        let freq = corpus.text_freq_with("words", crate::match_condition::AnyText,
+            &FreqOptions::new().with_lowercase(true)).unwrap();
+        assert_eq!(freq["the"], 2);
+        assert_eq!(freq["cat,"], 1);
+        let freq = corpus.text_freq_with("words", crate::match_condition::AnyText,
+            &FreqOptions::new().with_strip_punct(true)).unwrap();
+        assert_eq!(freq, HashMap::from([("The".to_string(), 1), ("cat".to_string(), 1),
+            ("the".to_string(), 1), ("end".to_string(), 1)]));
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    #[test]
+    fn test_text_freq_with_nfc() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_doc()
+            .layer("text", "Cafe\u{301} café").unwrap()
+            .layer("words", vec![(0, 6), (7, 12)]).unwrap()
+            .add().unwrap();
+        let freq = corpus.text_freq_with("words", crate::match_condition::AnyText,
+            &FreqOptions::new().with_nfc(true).with_lowercase(true)).unwrap();
+        assert_eq!(freq, HashMap::from([("café".to_string(), 2)]));
+    }
+
+    #[test]
+    fn test_text_freq_regex() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_doc()
+            .layer("text", "In 1922 Dublin had 300000 people").unwrap()
+            .layer("words", vec![(0, 2), (3, 7), (8, 14), (15, 18), (19, 25), (26, 32)]).unwrap()
+            .add().unwrap();
+        let freq = corpus.text_freq("words", RegexTextMatch::new(r"^[A-Z].*").unwrap()).unwrap();
+        assert_eq!(freq, HashMap::from([("In".to_string(), 1), ("Dublin".to_string(), 1)]));
+        let freq = corpus.text_freq("words", RegexTextMatch::new(r"^\d+$").unwrap()).unwrap();
+        assert_eq!(freq.len(), 2);
+        assert!(RegexTextMatch::new("(").is_err());
+    }
+
+    #[test]
+    fn test_reorder_to() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        let id1 = corpus.build_doc().layer("text", "One").unwrap().add().unwrap();
+        let id2 = corpus.build_doc().layer("text", "Two").unwrap().add().unwrap();
+        corpus.reorder_to(&[id2.clone(), id1.clone()]).unwrap();
+        assert_eq!(corpus.get_docs(), vec![id2.clone(), id1.clone()]);
+        assert!(corpus.reorder_to(std::slice::from_ref(&id1)).is_err());
+        assert!(corpus.reorder_to(&[id1.clone(), id1.clone()]).is_err());
+        assert!(corpus.reorder_to(&[id1.clone(), id2.clone(), "other".to_string()]).is_err());
+        assert_eq!(corpus.get_docs(), vec![id2, id1]);
+    }
+
+    #[test]
+    fn test_add_layer_meta_checked() {
+        let mut corpus = SimpleCorpus::new();
+        let pos = Some(DataType::Enum(vec!["N".to_string(), "V".to_string()]));
+        let pos_desc = |data : Option<DataType>| LayerDesc {
+            layer_type: LayerType::seq,
+            base: Some("text".to_string()),
+            data,
+            ..LayerDesc::default()
+        };
+        corpus.add_layer_meta_checked("text".to_string(), LayerDesc::default()).unwrap();
+        corpus.add_layer_meta_checked("pos".to_string(), pos_desc(pos.clone())).unwrap();
+        corpus.add_layer_meta_checked("pos".to_string(), pos_desc(pos.clone())).unwrap();
+        assert!(corpus.add_layer_meta_checked("pos".to_string(),
+            pos_desc(Some(DataType::Enum(vec!["V".to_string(), "N".to_string()])))).is_err());
+        assert_eq!(corpus.get_meta()["pos"].data, pos);
+    }
+
+    #[test]
+    fn test_distinct_values() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("pos").base("words").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        corpus.build_doc().layer("text", "dogs bark").unwrap()
+            .layer("words", vec![(0, 4), (5, 9)]).unwrap()
+            .layer("pos", vec!["NOUN", "VERB"]).unwrap().add().unwrap();
+        corpus.build_doc().layer("text", "cats").unwrap()
+            .layer("words", vec![(0, 4)]).unwrap()
+            .layer("pos", vec!["NOUN"]).unwrap().add().unwrap();
+        let values : Vec<String> = corpus.distinct_values("pos").unwrap().into_iter().collect();
+        assert_eq!(values, vec!["NOUN".to_string(), "VERB".to_string()]);
+        assert!(corpus.distinct_values("missing").is_err());
+    }
+
+    #[test]
+    fn test_iter_layer() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        let id1 = corpus.build_doc().layer("text", "a b").unwrap()
+            .layer("words", vec![(0, 1), (2, 3)]).unwrap().add().unwrap();
+        let id2 = corpus.build_doc().layer("text", "c").unwrap().add().unwrap();
+        let layers : Vec<(&str, Option<&Layer>)> = corpus.iter_layer("words").collect();
+        assert_eq!(layers, vec![(id1.as_str(), Some(&Layer::L2(vec![(0, 1), (2, 3)]))),
+            (id2.as_str(), None)]);
+    }
+
+    #[test]
+    fn test_extend_docs() {
+        let mut source = SimpleCorpus::new();
+        source.build_layer("text").add().unwrap();
+        source.build_doc().layer("text", "Aon").unwrap().add().unwrap();
+        source.build_doc().layer("text", "Dó").unwrap().add().unwrap();
+        let mut corpus = SimpleCorpus::new();
+        assert!(corpus.extend_docs(source.iter_docs().map(|d| d.unwrap())).is_err());
+        corpus.set_meta(source.get_meta().clone()).unwrap();
+        let ids = corpus.extend_docs(source.iter_docs().map(|d| d.unwrap())).unwrap();
+        assert_eq!(&ids, source.get_order());
+        assert_eq!(corpus.get_order(), source.get_order());
+    }
+
+    #[test]
+    fn test_apply_id_mapping() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        let ids = corpus.add_docs(vec![
+            vec![("text".to_string(), "Aon")],
+            vec![("text".to_string(), "Dó")],
+            vec![("text".to_string(), "Trí")]]).unwrap();
+        let doc = corpus.get_doc_by_id(&ids[0]).unwrap();
+        corpus.apply_id_mapping(&HashMap::from([
+            (ids[0].clone(), ids[1].clone()), (ids[1].clone(), ids[0].clone())])).unwrap();
+        assert_eq!(corpus.get_order(), &vec![ids[1].clone(), ids[0].clone(), ids[2].clone()]);
+        assert_eq!(corpus.get_doc_by_id(&ids[1]).unwrap(), doc);
+        assert!(corpus.apply_id_mapping(&HashMap::from([
+            ("missing".to_string(), "x".to_string())])).is_err());
+        assert!(corpus.apply_id_mapping(&HashMap::from([
+            (ids[0].clone(), ids[2].clone())])).is_err());
+        assert_eq!(corpus.get_doc_by_id(&ids[1]).unwrap(), doc);
+    }
+
+    #[test]
+    fn test_content_eq() {
+        let mut corpus1 = SimpleCorpus::new();
+        corpus1.build_layer("text").add().unwrap();
+        corpus1.add_doc(vec![("text".to_string(), "Aon")]).unwrap();
+        corpus1.add_doc(vec![("text".to_string(), "Dó")]).unwrap();
+        let mut corpus2 = SimpleCorpus::new();
+        corpus2.build_layer("text").add().unwrap();
+        corpus2.add_doc(vec![("text".to_string(), "Dó")]).unwrap();
+        corpus2.add_doc(vec![("text".to_string(), "Aon")]).unwrap();
+        assert_ne!(corpus1, corpus2);
+        assert!(corpus1.content_eq(&corpus2));
+        corpus2.add_doc(vec![("text".to_string(), "Trí")]).unwrap();
+        assert!(!corpus1.content_eq(&corpus2));
+        assert!(!corpus2.content_eq(&corpus1));
+    }
+
+    #[test]
+    fn test_iter_docs_lenient() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        let id1 = corpus.build_doc().layer("text", "One").unwrap().add().unwrap();
+        let id2 = corpus.build_doc().layer("text", "Two").unwrap().add().unwrap();
+        corpus.set_order(vec![id1.clone(), "missing".to_string(), id2.clone()]).unwrap();
+        let mut docs = corpus.iter_docs_lenient();
+        let ids : Vec<String> = (&mut docs).map(|(id, _)| id).collect();
+        assert_eq!(ids, vec![id1, id2]);
+        let errors = docs.into_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, "missing");
+        assert!(matches!(errors[0].1, TeangaError::DocumentNotFoundError));
+    }
+
+    #[test]
+    fn test_length_histogram() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_doc().layer("text", "Fáilte").unwrap()
+            .layer("words", vec![(0, 7)]).unwrap().add().unwrap();
+        corpus.build_doc().layer("text", "Dia duit a chara").unwrap()
+            .layer("words", vec![(0, 3), (4, 8), (9, 10), (11, 16)]).unwrap().add().unwrap();
+        corpus.build_doc().layer("text", "").unwrap().add().unwrap();
+        assert_eq!(corpus.length_histogram("text", LengthUnit::Chars, &[1, 10]).unwrap(), vec![1, 1]);
+        assert_eq!(corpus.length_histogram("text", LengthUnit::Words, &[0, 2, 4]).unwrap(), vec![2, 0, 1]);
+        assert_eq!(corpus.length_histogram("words", LengthUnit::Annotations, &[0, 3]).unwrap(), vec![1, 1]);
+        assert!(corpus.length_histogram("text", LengthUnit::Chars, &[5, 5]).is_err());
+    }
+
+    #[test]
+    fn test_total_tokens() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_doc().layer("text", "Dia duit").unwrap()
+            .layer("words", vec![(0, 3), (4, 8)]).unwrap().add().unwrap();
+        corpus.build_doc().layer("text", "Slán").unwrap()
+            .layer("words", vec![(0, 5)]).unwrap().add().unwrap();
+        corpus.build_doc().layer("text", "").unwrap().add().unwrap();
+        assert_eq!(corpus.total_tokens("words").unwrap(), 3);
+        assert_eq!(corpus.total_tokens("text").unwrap(), 13);
+        assert!(corpus.total_tokens("missing").is_err());
+    }
+
+    #[test]
+    fn test_text_windows() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        let id1 = corpus.add_doc(vec![("text".to_string(), "Dúnáras")]).unwrap();
+        let id2 = corpus.add_doc(vec![("text".to_string(), "Sí")]).unwrap();
+        corpus.add_doc(vec![("text".to_string(), "")]).unwrap();
+        let windows = corpus.text_windows("text", 4, 2).unwrap();
+        assert_eq!(windows, vec![
+            (id1.clone(), 0, "Dúná".to_string()),
+            (id1.clone(), 3, "nára".to_string()),
+            (id1.clone(), 6, "ras".to_string()),
+            (id2.clone(), 0, "Sí".to_string())]);
+        assert_eq!(corpus.text_windows("text", 3, 5).unwrap()[1], (id1, 7, "as".to_string()));
+        assert!(corpus.text_windows("text", 4, 0).is_err());
+    }
+
+    #[test]
+    fn test_annotate() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        let id = corpus.build_doc().layer("text", "Tá sé fuar").unwrap().add().unwrap();
+        corpus.annotate("text", "tokens", |text| {
+            let mut spans = Vec::new();
+            let mut start = 0;
+            for word in text.split(' ') {
+                spans.push((start as u32, (start + word.len()) as u32, word.to_lowercase()));
+                start += word.len() + 1;
+            }
+            spans
+        }).unwrap();
+        assert_eq!(corpus.get_meta()["tokens"].base, Some("text".to_string()));
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        assert_eq!(doc.text("tokens", corpus.get_meta()).unwrap(), vec!["Tá", "sé", "fuar"]);
+        assert_eq!(doc.data("tokens", corpus.get_meta()).unwrap()[0], TeangaData::String("tá".to_string()));
+        assert!(corpus.annotate("tokens", "other", |_| Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_annotate_regex() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        let id = corpus.build_doc().layer("text", "Bhí sé ann in 1916 agus 1922").unwrap().add().unwrap();
+        corpus.annotate_regex("text", "years", r"\d{4}", Some("YEAR")).unwrap();
+        corpus.annotate_regex("text", "words", r"\w+", None).unwrap();
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        assert_eq!(doc["years"], Layer::L2S(vec![(16, 20, "YEAR".to_string()), (26, 30, "YEAR".to_string())]));
+        assert_eq!(doc.text("words", corpus.get_meta()).unwrap(), vec!["Bhí", "sé", "ann", "in", "1916", "agus", "1922"]);
+        assert!(corpus.annotate_regex("text", "bad", "(", None).is_err());
+        assert!(corpus.annotate_regex("text", "years", "x", None).is_err());
+    }
+
+    #[test]
+    fn test_detokenize() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_doc().layer("text", "Tá sé  fuar!").unwrap()
+            .layer("words", vec![(0, 3), (4, 7), (9, 13), (13, 14)]).unwrap().add().unwrap();
+        corpus.build_doc().layer("text", "   ").unwrap()
+            .layer("words", Layer::L2(Vec::new())).unwrap().add().unwrap();
+        corpus.build_doc().layer("text", "Gan focail").unwrap().add().unwrap();
+        corpus.detokenize("words", "clean", " ").unwrap();
+        let texts : Vec<Option<Layer>> = corpus.iter_docs()
+            .map(|d| d.unwrap().get("clean").cloned()).collect();
+        assert_eq!(texts, vec![Some(Layer::Characters("Tá sé fuar !".to_string())),
+            Some(Layer::Characters(String::new())), None]);
+        assert!(corpus.detokenize("missing", "out", " ").is_err());
+    }
+
+    #[test]
+    fn test_layer_not_found() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        let id = corpus.add_doc(vec![("text".to_string(), "Téacs")]).unwrap();
+        assert!(matches!(corpus.add_doc(vec![("words".to_string(), "x")]),
+            Err(TeangaError::LayerNotFoundError(_))));
+        assert!(matches!(corpus.update_doc(&id, vec![("words".to_string(), "x")]),
+            Err(TeangaError::LayerNotFoundError(_))));
+        assert!(matches!(corpus.build_doc().layer("words", "x"),
+            Err(TeangaError::LayerNotFoundError(_))));
+    }
+
+    #[test]
+    fn test_add_docs_collect() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        let results = corpus.add_docs_collect(vec![
+            vec![("text".to_string(), "First")],
+            vec![("missing".to_string(), "Second")],
+            vec![("text".to_string(), "Third")]]);
+        assert_eq!(results.len(), 3);
+        assert!(matches!(results[1], Err(TeangaError::LayerNotFoundError(ref l)) if l == "missing"));
+        let ids : Vec<String> = results.into_iter().filter_map(|r| r.ok()).collect();
+        assert_eq!(corpus.get_docs(), ids);
+    }
+
+    #[test]
+    fn test_each_annotation() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("pos").base("words").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        let id = corpus.build_doc().layer("text", "Lá breá").unwrap()
+            .layer("words", vec![(0, 3), (4, 9)]).unwrap()
+            .layer("pos", vec!["NOUN", "ADJ"]).unwrap().add().unwrap();
+        let mut annotations = Vec::new();
+        corpus.each_annotation(|doc_id, layer, start, end, data| {
+            annotations.push((doc_id.to_string(), layer.to_string(), start, end, data.clone()));
+        }).unwrap();
+        assert_eq!(annotations, vec![
+            (id.clone(), "pos".to_string(), 0, 3, TeangaData::String("NOUN".to_string())),
+            (id.clone(), "pos".to_string(), 4, 9, TeangaData::String("ADJ".to_string())),
+            (id.clone(), "words".to_string(), 0, 3, TeangaData::None),
+            (id.clone(), "words".to_string(), 4, 9, TeangaData::None)]);
+    }
+
+    #[test]
+    fn test_layer_split_at() {
+        let desc = |layer_type, data| LayerDesc { layer_type, base: Some("text".to_string()),
+            data, ..LayerDesc::default() };
+        let span = desc(LayerType::span, Some(DataType::String));
+        let words = Layer::L2S(vec![(0, 4, "a".to_string()), (3, 8, "b".to_string()), (8, 10, "c".to_string())]);
+        assert_eq!(words.split_at(6, 10, &span, SplitPolicy::Drop).unwrap(),
+            (Layer::L2S(vec![(0, 4, "a".to_string())]), Layer::L2S(vec![(2, 4, "c".to_string())])));
+        assert_eq!(words.split_at(6, 10, &span, SplitPolicy::Clip).unwrap(),
+            (Layer::L2S(vec![(0, 4, "a".to_string()), (3, 6, "b".to_string())]),
+             Layer::L2S(vec![(0, 2, "b".to_string()), (2, 4, "c".to_string())])));
+        let div = desc(LayerType::div, None);
+        assert_eq!(Layer::L1(vec![0, 5]).split_at(7, 10, &div, SplitPolicy::Drop).unwrap(),
+            (Layer::L1(vec![0, 5]), Layer::L1(vec![0])));
+        let element = desc(LayerType::element, Some(DataType::Link));
+        assert_eq!(Layer::L2(vec![(1, 3), (7, 1)]).split_at(5, 10, &element, SplitPolicy::Drop).unwrap(),
+            (Layer::L2(vec![(1, 3)]), Layer::L2(vec![(2, 1)])));
+        let seq = desc(LayerType::seq, Some(DataType::String));
+        assert_eq!(Layer::LS(vec!["x".to_string(), "y".to_string()]).split_at(1, 2, &seq, SplitPolicy::Drop).unwrap(),
+            (Layer::LS(vec!["x".to_string()]), Layer::LS(vec!["y".to_string()])));
+        let chars = LayerDesc::default();
+        assert_eq!(Layer::Characters("Dia is Muire".to_string()).split_at(7, 12, &chars, SplitPolicy::Drop).unwrap(),
+            (Layer::Characters("Dia is ".to_string()), Layer::Characters("Muire".to_string())));
+        assert!(Layer::Characters("é".to_string()).split_at(1, 2, &chars, SplitPolicy::Drop).is_err());
+    }
+
+    #[test]
+    fn test_layer_remap_indices() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("tokens").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("pos").base("tokens").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        corpus.build_layer("phrases").base("tokens").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        corpus.build_layer("sentences").base("tokens").layer_type(LayerType::div).add().unwrap();
+        corpus.build_layer("heads").base("tokens").layer_type(LayerType::element)
+            .data(DataType::Link).add().unwrap();
+        let meta = corpus.get_meta();
+        // Delete tokens 0 and 3 of 5
+        let mapping = vec![None, Some(0), Some(1), None, Some(2)];
+        let mut pos = Layer::LS(vec!["a", "b", "c", "d", "e"].into_iter().map(|s| s.to_string()).collect());
+        pos.remap_indices(&meta["pos"], &mapping).unwrap();
+        assert_eq!(pos, Layer::LS(vec!["b".to_string(), "c".to_string(), "e".to_string()]));
+        let mut phrases = Layer::L2S(vec![(0, 2, "NP".to_string()), (3, 4, "X".to_string()), (2, 5, "VP".to_string())]);
+        phrases.remap_indices(&meta["phrases"], &mapping).unwrap();
+        assert_eq!(phrases, Layer::L2S(vec![(0, 1, "NP".to_string()), (1, 3, "VP".to_string())]));
+        let mut sentences = Layer::L1(vec![0, 3]);
+        sentences.remap_indices(&meta["sentences"], &mapping).unwrap();
+        assert_eq!(sentences, Layer::L1(vec![0, 2]));
+        let mut heads = Layer::L2(vec![(1, 2), (2, 4), (3, 1), (4, 0)]);
+        heads.remap_indices(&meta["heads"], &mapping).unwrap();
+        assert_eq!(heads, Layer::L2(vec![(0, 1), (1, 2)]));
+        // Reorder the tokens
+        let mut pos = Layer::LS(vec!["a".to_string(), "b".to_string()]);
+        pos.remap_indices(&meta["pos"], &[Some(1), Some(0)]).unwrap();
+        assert_eq!(pos, Layer::LS(vec!["b".to_string(), "a".to_string()]));
+        assert!(pos.remap_indices(&meta["pos"], &[Some(0), Some(0)]).is_err());
+        assert!(Layer::Characters("ab".to_string()).remap_indices(&meta["text"], &[None, None]).is_err());
+    }
+
+    #[test]
+    fn test_change_layer_type() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("pos").base("words").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        corpus.build_layer("clauses").base("words").layer_type(LayerType::span).add().unwrap();
+        let id = corpus.build_doc().layer("text", "Tá sé fuar").unwrap()
+            .layer("words", vec![(0, 3), (4, 7), (8, 12)]).unwrap()
+            .layer("pos", vec!["VERB", "PRON", "ADJ"]).unwrap()
+            .layer("clauses", vec![(0, 2), (2, 3)]).unwrap().add().unwrap();
+        corpus.change_layer_type("pos", LayerType::element).unwrap();
+        corpus.change_layer_type("clauses", LayerType::div).unwrap();
+        assert_eq!(corpus.get_meta()["pos"].layer_type, LayerType::element);
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        assert_eq!(doc["pos"], Layer::L1S(vec![(0, "VERB".to_string()), (1, "PRON".to_string()), (2, "ADJ".to_string())]));
+        assert_eq!(doc["clauses"], Layer::L1(vec![0, 2]));
+        corpus.change_layer_type("pos", LayerType::seq).unwrap();
+        corpus.change_layer_type("clauses", LayerType::span).unwrap();
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        assert_eq!(doc["pos"], Layer::LS(vec!["VERB".to_string(), "PRON".to_string(), "ADJ".to_string()]));
+        assert_eq!(doc["clauses"], Layer::L2(vec![(0, 2), (2, 3)]));
+        assert!(corpus.change_layer_type("words", LayerType::div).is_err());
+        assert_eq!(corpus.get_meta()["words"].layer_type, LayerType::span);
+        assert!(corpus.change_layer_type("words", LayerType::seq).is_err());
+    }
+
+    #[test]
+    fn test_split_document() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("sentences").base("text").layer_type(LayerType::div).add().unwrap();
+        corpus.build_layer("heads").base("words").layer_type(LayerType::seq)
+            .data(DataType::Link).add().unwrap();
+        corpus.build_layer("names").base("text").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        let first = corpus.build_doc().layer("text", "Tosach").unwrap().add().unwrap();
+        let id = corpus.build_doc().layer("text", "Tá sé fuar. Níl sé te.").unwrap()
+            .layer("words", vec![(0, 3), (4, 7), (8, 12), (12, 13), (14, 18), (19, 22), (23, 25), (25, 26)]).unwrap()
+            .layer("sentences", vec![0, 14]).unwrap()
+            .layer("heads", vec![2, 2, 2, 2, 6, 6, 6, 6]).unwrap()
+            .layer("names", vec![(8, 18, "fuar níl")]).unwrap()
+            .add().unwrap();
+        let last = corpus.build_doc().layer("text", "Deireadh").unwrap().add().unwrap();
+        let ids = corpus.split_document(&id, &[14]).unwrap();
+        assert_eq!(ids.len(), 2);
+        assert_eq!(corpus.get_order(), &vec![first, ids[0].clone(), ids[1].clone(), last]);
+        assert!(corpus.get_doc_by_id(&id).is_err());
+        let doc = corpus.get_doc_by_id(&ids[1]).unwrap();
+        assert_eq!(doc["text"], Layer::Characters("Níl sé te.".to_string()));
+        assert_eq!(doc["words"], Layer::L2(vec![(0, 4), (5, 8), (9, 11), (11, 12)]));
+        assert_eq!(doc["sentences"], Layer::L1(vec![0]));
+        assert_eq!(doc["heads"], Layer::L1(vec![2, 2, 2, 2]));
+        assert_eq!(doc["names"], Layer::L2S(vec![]));
+        assert!(corpus.split_document(&ids[0], &[5]).is_err());
+        assert!(corpus.split_document(&ids[0], &[20]).is_err());
+    }
+
+    #[test]
+    fn test_count_by() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("_info").add().unwrap();
+        for (text, lang) in [("Hello", "en"), ("Dia duit", "ga"), ("Goodbye", "en")] {
+            let mut info = HashMap::new();
+            info.insert("lang".to_string(), Value::String(lang.to_string()));
+            corpus.build_doc().layer("text", text).unwrap()
+                .layer("_info", info).unwrap().add().unwrap();
+        }
+        corpus.build_doc().layer("text", "No language").unwrap().add().unwrap();
+        let counts = corpus.count_by(|doc| match doc.get("_info") {
+            Some(Layer::MetaLayer(info)) => match info[0].get("lang") {
+                Some(Value::String(lang)) => Some(lang.clone()),
+                _ => None
+            },
+            _ => None
+        }).unwrap();
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts["en"], 2);
+        assert_eq!(counts["ga"], 1);
+    }
+
+    #[test]
+    fn test_doc_labels() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        let ids = corpus.add_docs(vec![
+            vec![("text".to_string(), "Go hiontach")],
+            vec![("text".to_string(), "Go dona")],
+            vec![("text".to_string(), "Ar fheabhas")],
+            vec![("text".to_string(), "Gan lipéad")]]).unwrap();
+        corpus.set_doc_label(&ids[0], "sentiment", "pos").unwrap();
+        corpus.set_doc_label(&ids[1], "sentiment", "neg").unwrap();
+        corpus.set_doc_label(&ids[2], "sentiment", "pos").unwrap();
+        corpus.set_doc_label(&ids[2], "topic", "review").unwrap();
+        assert_eq!(corpus.get_docs(), ids);
+        assert_eq!(corpus.get_doc_label(&ids[2], "sentiment").unwrap(), Some("pos".to_string()));
+        assert_eq!(corpus.get_doc_label(&ids[2], "topic").unwrap(), Some("review".to_string()));
+        assert_eq!(corpus.get_doc_label(&ids[3], "sentiment").unwrap(), None);
+        let freq = corpus.labels_freq("sentiment").unwrap();
+        assert_eq!(freq.len(), 2);
+        assert_eq!(freq["pos"], 2);
+        assert_eq!(freq["neg"], 1);
+        assert!(corpus.set_doc_label("missing", "sentiment", "pos").is_err());
+    }
+
+    #[test]
+    fn test_sample_stratified() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        for i in 0..20 {
+            let id = corpus.add_doc(vec![("text".to_string(), format!("Doiciméad {}", i))]).unwrap();
+            let label = if i % 4 == 0 { "neg" } else { "pos" };
+            corpus.set_doc_label(&id, "sentiment", label).unwrap();
+        }
+        corpus.add_doc(vec![("text".to_string(), "Gan lipéad")]).unwrap();
+        let sample = corpus.sample_stratified(7, "sentiment", 42).unwrap();
+        assert_eq!(sample.get_docs().len(), 7);
+        let freq = sample.labels_freq("sentiment").unwrap();
+        assert_eq!(freq["pos"], 5);
+        assert_eq!(freq["neg"], 2);
+        let order : Vec<usize> = sample.get_docs().iter()
+            .map(|id| corpus.get_docs().iter().position(|d| d == id).unwrap()).collect();
+        assert!(order.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(corpus.sample_stratified(7, "sentiment", 42).unwrap(), sample);
+        assert_ne!(corpus.sample_stratified(7, "sentiment", 7).unwrap(), sample);
+        assert!(corpus.sample_stratified(21, "sentiment", 42).is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        let id1 = corpus.build_doc().layer("text", "Short").unwrap().add().unwrap();
+        corpus.build_doc().layer("text", "A much longer document").unwrap()
+            .layer("words", vec![(0, 1), (2, 6), (7, 13), (14, 22)]).unwrap().add().unwrap();
+        let id3 = corpus.build_doc().layer("text", "Tiny").unwrap().add().unwrap();
+        let short = corpus.filter(|_, doc| doc.get("words").is_none()).unwrap();
+        assert_eq!(short.get_order(), &vec![id1, id3.clone()]);
+        assert_eq!(short.get_meta(), corpus.get_meta());
+        let by_id = corpus.filter(|id, _| id == id3).unwrap();
+        assert_eq!(by_id.get_docs(), vec![id3]);
+    }
+
+    #[test]
+    fn test_split_by_hash() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        for i in 0..100 {
+            corpus.build_doc().layer("text", format!("Document {}", i).as_str()).unwrap().add().unwrap();
+        }
+        let splits = corpus.split_by_hash(&[0.8, 0.1, 0.1], 42).unwrap();
+        assert_eq!(splits.len(), 3);
+        assert_eq!(splits.iter().map(|s| s.get_docs().len()).sum::<usize>(), 100);
+        assert!(splits[0].get_docs().len() > splits[1].get_docs().len());
+        for split in splits.iter() {
+            assert_eq!(split.get_meta(), corpus.get_meta());
+            let positions : Vec<usize> = split.get_order().iter()
+                .map(|id| corpus.get_order().iter().position(|i| i == id).unwrap()).collect();
+            assert!(positions.windows(2).all(|w| w[0] < w[1]));
+        }
+        let again = corpus.split_by_hash(&[0.8, 0.1, 0.1], 42).unwrap();
+        assert_eq!(again[1].get_order(), splits[1].get_order());
+        assert!(corpus.split_by_hash(&[0.5, 0.4], 42).is_err());
+        assert!(corpus.split_by_hash(&[1.5, -0.5], 42).is_err());
+    }
+
+    #[test]
+    fn test_cooccurrence() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_doc().layer("text", "a b c a").unwrap()
+            .layer("words", vec![(0, 1), (2, 3), (4, 5), (6, 7)]).unwrap().add().unwrap();
+        corpus.build_doc().layer("text", "c b").unwrap()
+            .layer("words", vec![(0, 1), (2, 3)]).unwrap().add().unwrap();
+        let pair = |a : &str, b : &str| (a.to_string(), b.to_string());
+        let counts = corpus.cooccurrence("words", 1).unwrap();
+        assert_eq!(counts, HashMap::from([(pair("a", "b"), 1), (pair("b", "c"), 2), (pair("a", "c"), 1)]));
+        let counts = corpus.cooccurrence("words", 3).unwrap();
+        assert_eq!(counts[&pair("a", "a")], 1);
+        assert_eq!(counts[&pair("a", "b")], 2);
+        assert_eq!(counts[&pair("a", "c")], 2);
+        assert!(corpus.cooccurrence("words", 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ngram_freq() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_doc().layer("text", "a b a b").unwrap()
+            .layer("words", vec![(0, 1), (2, 3), (4, 5), (6, 7)]).unwrap().add().unwrap();
+        corpus.build_doc().layer("text", "b a").unwrap()
+            .layer("words", vec![(0, 1), (2, 3)]).unwrap().add().unwrap();
+        let freq = corpus.ngram_freq("words", 2, crate::match_condition::AnyText).unwrap();
+        assert_eq!(freq[&vec!["a".to_string(), "b".to_string()]], 2);
+        assert_eq!(freq[&vec!["b".to_string(), "a".to_string()]], 2);
+        assert_eq!(freq.len(), 2);
+        let unigrams = corpus.ngram_freq("words", 1, crate::match_condition::AnyText).unwrap();
+        let words = corpus.text_freq("words", crate::match_condition::AnyText).unwrap();
+        assert_eq!(unigrams, words.into_iter().map(|(w, c)| (vec![w], c)).collect());
+        assert_eq!(corpus.ngram_freq("words", 4, crate::match_condition::AnyText).unwrap().len(), 1);
+        assert!(corpus.ngram_freq("words", 0, crate::match_condition::AnyText).is_err());
+    }
+
     #[test]
     fn test_update_doc() {
         let mut corpus = SimpleCorpus::new();
@@ -3,11 +3,22 @@
 //! This module provides a trait for whether a section
 //! of text matches a condition.
 use crate::layer::TeangaData;
+use regex::Regex;
 
 /// Matching condition for text
 pub trait TextMatchCondition {
     /// Check if the text matches the condition
     fn matches(&self, text: &str) -> bool;
+
+    /// The start and end (exclusive) byte offsets of each match of the
+    /// condition in the text. By default the condition matches the whole text
+    fn find(&self, text: &str) -> Vec<(usize, usize)> {
+        if self.matches(text) {
+            vec![(0, text.len())]
+        } else {
+            Vec::new()
+        }
+    }
 }
 
 impl TextMatchCondition for String {
@@ -30,6 +41,37 @@ impl TextMatchCondition for AnyText {
     }
 }
 
+/// Matches text that contains a match of a regular expression. Use `^` and
+/// `$` to match the whole text
+pub struct RegexTextMatch(Regex);
+
+impl RegexTextMatch {
+    /// Compile a regular expression
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(RegexTextMatch(Regex::new(pattern)?))
+    }
+}
+
+impl TextMatchCondition for RegexTextMatch {
+    fn matches(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+
+    fn find(&self, text: &str) -> Vec<(usize, usize)> {
+        TextMatchCondition::find(&self.0, text)
+    }
+}
+
+impl TextMatchCondition for Regex {
+    fn matches(&self, text: &str) -> bool {
+        self.is_match(text)
+    }
+
+    fn find(&self, text: &str) -> Vec<(usize, usize)> {
+        self.find_iter(text).map(|m| (m.start(), m.end())).collect()
+    }
+}
+
 /// Data match condition
 pub trait DataMatchCondition {
     /// Check if the data matches the condition
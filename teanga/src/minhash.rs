@@ -0,0 +1,183 @@
+//! Near-duplicate detection with MinHash
+//!
+//! Each document is reduced to a signature of the minimum hashes of its
+//! shingles (overlapping sequences of tokens) under a number of hash
+//! functions. The fraction of positions where two signatures agree estimates
+//! the Jaccard similarity of the documents' shingles. Locality sensitive
+//! hashing groups the signatures into bands so that only documents that agree
+//! on a whole band are compared.
+//!
+//! # Examples
+//! ```rust
+//! use teanga::{SimpleCorpus, Corpus};
+//! use teanga::minhash::find_near_duplicates;
+//! let mut corpus = SimpleCorpus::new();
+//! corpus.build_layer("text").add().unwrap();
+//! corpus.build_doc().layer("text", "the cat sat on the mat today").unwrap().add().unwrap();
+//! corpus.build_doc().layer("text", "the cat sat on the mat today!").unwrap().add().unwrap();
+//! corpus.build_doc().layer("text", "something else entirely").unwrap().add().unwrap();
+//! let signatures = corpus.minhash_signatures("text", 128).unwrap();
+//! let pairs = find_near_duplicates(&signatures, 0.5);
+//! assert_eq!(pairs.len(), 1);
+//! ```
+use std::collections::{HashMap, HashSet};
+use itertools::Itertools;
+use crate::{Corpus, LayerType, TeangaError, TeangaResult};
+
+/// The number of consecutive tokens in a shingle
+pub const SHINGLE_SIZE : usize = 3;
+
+/// Compute the MinHash signatures of the documents of a corpus, see
+/// `Corpus::minhash_signatures`
+pub(crate) fn minhash_signatures<C : Corpus + ?Sized>(corpus : &C, layer : &str,
+    num_perm : usize) -> TeangaResult<HashMap<String, Vec<u64>>> {
+    if num_perm == 0 {
+        return Err(TeangaError::ModelError("The number of permutations must be at least 1".to_string()));
+    }
+    let layer_desc = corpus.get_meta().get(layer)
+        .ok_or_else(|| TeangaError::LayerNotFoundError(layer.to_string()))?;
+    let seeds : Vec<u64> = (0..num_perm as u64).map(|i| mix(i.wrapping_add(1))).collect();
+    let mut signatures = HashMap::new();
+    for id in corpus.get_order() {
+        let doc = corpus.get_doc_by_id(id)?;
+        let text = doc.text(layer, corpus.get_meta())?;
+        // The text of a characters layer is split into words
+        let tokens : Vec<&str> = if layer_desc.layer_type == LayerType::characters {
+            text.iter().flat_map(|t| t.split_whitespace()).collect()
+        } else {
+            text
+        };
+        let shingles : Vec<u64> = if tokens.len() < SHINGLE_SIZE {
+            vec![shingle_hash(&tokens)]
+        } else {
+            tokens.windows(SHINGLE_SIZE).map(shingle_hash).collect()
+        };
+        let signature = seeds.iter().map(|seed| shingles.iter()
+            .map(|h| mix(h ^ seed))
+            .min().unwrap_or(u64::MAX)).collect();
+        signatures.insert(id.clone(), signature);
+    }
+    Ok(signatures)
+}
+
+/// Find the pairs of documents whose signatures are similar. Candidate pairs
+/// are found with locality sensitive hashing, with the number of bands chosen
+/// for the threshold, and are returned if the estimated Jaccard similarity
+/// is at least the threshold
+///
+/// # Arguments
+///
+/// * `signatures` - The signatures from `Corpus::minhash_signatures`
+/// * `threshold` - The minimum Jaccard similarity, between 0 and 1
+///
+/// # Returns
+///
+/// The IDs of the similar documents as pairs, with the smaller ID first,
+/// in sorted order
+pub fn find_near_duplicates(signatures : &HashMap<String, Vec<u64>>, threshold : f64) -> Vec<(String, String)> {
+    let num_perm = signatures.values().map(|s| s.len()).min().unwrap_or(0);
+    if num_perm == 0 {
+        return Vec::new();
+    }
+    let (bands, rows) = band_params(num_perm, threshold);
+    let mut candidates = HashSet::new();
+    for band in 0..bands {
+        let mut buckets : HashMap<&[u64], Vec<&String>> = HashMap::new();
+        for (id, signature) in signatures.iter() {
+            buckets.entry(&signature[band * rows..(band + 1) * rows]).or_default().push(id);
+        }
+        for ids in buckets.values() {
+            for (i, a) in ids.iter().enumerate() {
+                for b in ids[i + 1..].iter() {
+                    candidates.insert(if a < b { (*a, *b) } else { (*b, *a) });
+                }
+            }
+        }
+    }
+    candidates.into_iter()
+        .filter(|(a, b)| jaccard(&signatures[*a], &signatures[*b]) >= threshold)
+        .map(|(a, b)| (a.clone(), b.clone()))
+        .sorted()
+        .collect()
+}
+
+/// Estimate the Jaccard similarity of two documents from their signatures
+///
+/// # Arguments
+///
+/// * `a` - The signature of the first document
+/// * `b` - The signature of the second document
+///
+/// # Returns
+///
+/// The fraction of positions where the signatures are equal
+pub fn jaccard(a : &[u64], b : &[u64]) -> f64 {
+    let n = a.len().min(b.len());
+    if n == 0 {
+        return 0.0;
+    }
+    a.iter().zip(b.iter()).filter(|(x, y)| x == y).count() as f64 / n as f64
+}
+
+/// Choose the number of bands and rows per band so that pairs with a
+/// similarity of about the threshold have an even chance of being a candidate
+fn band_params(num_perm : usize, threshold : f64) -> (usize, usize) {
+    (1..=num_perm).map(|rows| (num_perm / rows, rows))
+        .min_by(|(b1, r1), (b2, r2)| {
+            let d1 = ((1.0 / *b1 as f64).powf(1.0 / *r1 as f64) - threshold).abs();
+            let d2 = ((1.0 / *b2 as f64).powf(1.0 / *r2 as f64) - threshold).abs();
+            d1.total_cmp(&d2)
+        })
+        .unwrap_or((1, num_perm))
+}
+
+/// Hash a shingle with FNV-1a, separating the tokens with a zero byte
+fn shingle_hash(tokens : &[&str]) -> u64 {
+    let mut hash : u64 = 0xcbf29ce484222325;
+    for token in tokens {
+        for byte in token.bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+/// The SplitMix64 finalizer, used to derive a hash function from a seed
+fn mix(x : u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimpleCorpus;
+
+    #[test]
+    fn test_minhash() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        let id1 = corpus.build_doc()
+            .layer("text", "tá an aimsir go breá inniu agus tá an ghrian ag taitneamh").unwrap().add().unwrap();
+        let id2 = corpus.build_doc()
+            .layer("text", "tá an aimsir go breá inniu agus tá an ghrian ag taitneamh go láidir").unwrap().add().unwrap();
+        corpus.build_doc()
+            .layer("text", "bhí sé ag cur báistí ar feadh an lae ar fad inné").unwrap().add().unwrap();
+        let signatures = corpus.minhash_signatures("text", 64).unwrap();
+        assert_eq!(signatures.len(), 3);
+        assert!(signatures.values().all(|s| s.len() == 64));
+        assert!(jaccard(&signatures[&id1], &signatures[&id2]) > 0.5);
+        let expected = if id1 < id2 { (id1, id2) } else { (id2, id1) };
+        assert_eq!(find_near_duplicates(&signatures, 0.5), vec![expected]);
+        assert!(corpus.minhash_signatures("text", 0).is_err());
+    }
+
+    #[test]
+    fn test_band_params() {
+        assert_eq!(band_params(128, 0.5), (25, 5));
+        assert_eq!(band_params(1, 0.5), (1, 1));
+    }
+}
@@ -0,0 +1,182 @@
+//! Unicode normalization of text layers
+//!
+//! Normalizing a text can change its length, so the offsets of the layers
+//! based on the text are updated to refer to the normalized text.
+use std::collections::HashMap;
+use itertools::Itertools;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::canonical_combining_class;
+use crate::{Document, Layer, LayerDesc, LayerType, TeangaError, TeangaResult};
+
+/// A Unicode normalization form
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalizationForm {
+    /// Canonical composition
+    Nfc,
+    /// Canonical decomposition
+    Nfd,
+    /// Compatibility composition
+    Nfkc,
+    /// Compatibility decomposition
+    Nfkd
+}
+
+impl NormalizationForm {
+    /// Normalize a string with this form
+    pub fn normalize(&self, text : &str) -> String {
+        match self {
+            NormalizationForm::Nfc => text.nfc().collect(),
+            NormalizationForm::Nfd => text.nfd().collect(),
+            NormalizationForm::Nfkc => text.nfkc().collect(),
+            NormalizationForm::Nfkd => text.nfkd().collect()
+        }
+    }
+}
+
+/// Normalize the text of a document and update the offsets of the layers
+/// based on it. An offset inside a sequence of a character and its combining
+/// marks is moved to the end of the sequence
+///
+/// # Arguments
+///
+/// * `doc` - The document
+/// * `layer` - The characters layer to normalize
+/// * `form` - The normalization form
+/// * `meta` - The metadata of the corpus
+///
+/// # Returns
+///
+/// The normalized text layer and the layers based on it with updated offsets
+pub fn normalize_doc(doc : &Document, layer : &str, form : NormalizationForm,
+    meta : &HashMap<String, LayerDesc>) -> TeangaResult<Vec<(String, Layer)>> {
+    let text = doc.get(layer).and_then(|l| l.characters())
+        .ok_or_else(|| TeangaError::LayerNotFoundError(layer.to_string()))?;
+    let (normalized, offsets) = normalize_with_offsets(text, form);
+    let map = |o : u32| {
+        let i = offsets.partition_point(|(old, _)| *old < o as usize);
+        offsets.get(i).map_or(normalized.len(), |(_, new)| *new) as u32
+    };
+    let mut layers = vec![(layer.to_string(), Layer::Characters(normalized.clone()))];
+    for name in doc.keys().into_iter().sorted() {
+        let layer_desc = &meta[&name];
+        if layer_desc.base.as_deref() != Some(layer) {
+            continue;
+        }
+        let l = &doc[name.as_str()];
+        let remapped = match (l, &layer_desc.layer_type) {
+            (_, LayerType::seq) => return Err(TeangaError::ModelError(
+                format!("Cannot normalize layer {} as the seq layer {} is based on it", layer, name))),
+            (Layer::L2(v), LayerType::span) => Layer::L2(v.iter()
+                .map(|(s, e)| (map(*s), map(*e))).collect()),
+            (Layer::L2S(v), LayerType::span) => Layer::L2S(v.iter()
+                .map(|(s, e, d)| (map(*s), map(*e), d.clone())).collect()),
+            (Layer::L3(v), _) => Layer::L3(v.iter()
+                .map(|(s, e, d)| (map(*s), map(*e), *d)).collect()),
+            (Layer::L3S(v), _) => Layer::L3S(v.iter()
+                .map(|(s, e, d, t)| (map(*s), map(*e), *d, t.clone())).collect()),
+            (Layer::L1(v), _) => Layer::L1(v.iter().map(|i| map(*i)).collect()),
+            (Layer::L2(v), _) => Layer::L2(v.iter().map(|(i, d)| (map(*i), *d)).collect()),
+            (Layer::L1S(v), _) => Layer::L1S(v.iter().map(|(i, d)| (map(*i), d.clone())).collect()),
+            (Layer::L2S(v), _) => Layer::L2S(v.iter()
+                .map(|(i, d, t)| (map(*i), *d, t.clone())).collect()),
+            _ => continue
+        };
+        layers.push((name, remapped));
+    }
+    Ok(layers)
+}
+
+/// Normalize a text in pieces that can be normalized independently
+///
+/// # Returns
+///
+/// The normalized text and the offsets of the start of each piece in the
+/// original and the normalized text
+fn normalize_with_offsets(text : &str, form : NormalizationForm) -> (String, Vec<(usize, usize)>) {
+    // Start a new piece at each character that does not combine with the
+    // previous character
+    let mut pieces : Vec<&str> = Vec::new();
+    let mut start = 0;
+    for (i, c) in text.char_indices().skip(1) {
+        if canonical_combining_class(c) == 0 {
+            pieces.push(&text[start..i]);
+            start = i;
+        }
+    }
+    if start < text.len() {
+        pieces.push(&text[start..]);
+    }
+    // Some starters still compose with the previous character, so join
+    // pieces that are not normalized independently
+    let mut normalized = String::new();
+    let mut offsets = Vec::new();
+    let mut start = 0;
+    let mut current = String::new();
+    for piece in pieces {
+        let joined = format!("{}{}", current, piece);
+        if !current.is_empty() && form.normalize(&joined) !=
+            format!("{}{}", form.normalize(&current), form.normalize(piece)) {
+            current = joined;
+            continue;
+        }
+        if !current.is_empty() {
+            offsets.push((start, normalized.len()));
+            normalized.push_str(&form.normalize(&current));
+            start += current.len();
+        }
+        current = piece.to_string();
+    }
+    if !current.is_empty() {
+        offsets.push((start, normalized.len()));
+        normalized.push_str(&form.normalize(&current));
+    }
+    offsets.push((text.len(), normalized.len()));
+    (normalized, offsets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Corpus, SimpleCorpus, DataType};
+
+    #[test]
+    fn test_normalize_text() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        corpus.build_layer("pos").base("words").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        corpus.build_doc().layer("text", "Cafe\u{301} na he\u{301}ireann").unwrap()
+            .layer("words", vec![(0, 6, "café"), (7, 9, "na"), (10, 20, "éireann")]).unwrap()
+            .layer("pos", vec!["NOUN", "DET", "PROPN"]).unwrap()
+            .add().unwrap();
+        corpus.normalize_text("text", NormalizationForm::Nfc).unwrap();
+        let doc = corpus.iter_docs().next().unwrap().unwrap();
+        assert_eq!(doc.text("words", corpus.get_meta()).unwrap(), vec!["Café", "na", "héireann"]);
+        assert_eq!(doc["pos"], Layer::LS(vec!["NOUN".to_string(), "DET".to_string(), "PROPN".to_string()]));
+        corpus.normalize_text("text", NormalizationForm::Nfd).unwrap();
+        let doc = corpus.iter_docs().next().unwrap().unwrap();
+        assert_eq!(doc.text("words", corpus.get_meta()).unwrap(), vec!["Cafe\u{301}", "na", "he\u{301}ireann"]);
+    }
+
+    #[test]
+    fn test_normalize_with_offsets() {
+        let (text, offsets) = normalize_with_offsets("e\u{301}\u{1100}\u{1161}x", NormalizationForm::Nfc);
+        assert_eq!(text, "é\u{ac00}x");
+        assert_eq!(offsets, vec![(0, 0), (3, 2), (9, 5), (10, 6)]);
+    }
+
+    #[test]
+    fn test_normalize_seq_layer() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("chars").base("text").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        let id = corpus.build_doc().layer("text", "e\u{301}").unwrap().add().unwrap();
+        corpus.build_doc().layer("text", "e\u{301}x").unwrap()
+            .layer("chars", vec!["a", "b", "c", "d"]).unwrap().add().unwrap();
+        assert!(corpus.normalize_text("text", NormalizationForm::Nfc).is_err());
+        assert_eq!(corpus.get_doc_by_id(&id).unwrap()["text"], Layer::Characters("e\u{301}".to_string()));
+    }
+}
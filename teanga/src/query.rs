@@ -11,8 +11,15 @@
 //!     .text("words", "fox")
 //!     .build();
 //! ```
+//!
+//! Queries can also be combined directly:
+//!
+//! ```
+//! use teanga::query::Query;
+//! let query = Query::text("words", "fox").and(!Query::exists("ner"));
+//! ```
 use std::collections::{HashMap, HashSet};
-use crate::{Document, LayerDesc, TeangaData};
+use crate::{Document, LayerDesc, LayerGraph, TeangaData, TextMatchCondition};
 use regex::Regex;
 
 /// A query for searching a corpus
@@ -49,10 +56,82 @@ pub enum Query {
     /// A query does not match
     Not(Box<Query>),
     /// A layer is present in a document
-    Exists(String)
+    Exists(String),
+    /// A layer is not present in a document
+    NotExists(String),
+    /// A text value `a` occurs within `distance` annotations of a text
+    /// value `b` in a layer (in either order)
+    Near {
+        layer: String,
+        a: String,
+        b: String,
+        distance: usize
+    },
+    /// Consecutive annotations of a layer match a sequence of patterns, e.g.,
+    /// an adjective followed by a noun
+    Sequence {
+        layer: String,
+        patterns: Vec<TokenPattern>
+    }
+}
+
+/// A condition on a single annotation in a `Query::Sequence`
+#[derive(Debug, Clone)]
+pub enum TokenPattern {
+    /// The text of the annotation matches a regex. As with `Query::TextRegex`
+    /// the regex may match part of the text, use `^` and `$` to match all of it
+    Text(Regex),
+    /// A layer has this value for the annotation. The layer may be the layer
+    /// of the sequence or a layer based on it, in which case its annotations
+    /// apply to every annotation of the sequence layer that they cover
+    Value(String, TeangaData),
+    /// Any annotation
+    Any
 }
 
 impl Query {
+    /// A query that a text value in a layer matches
+    pub fn text(layer : &str, text : &str) -> Query {
+        Query::Text(layer.to_string(), text.to_string())
+    }
+
+    /// A query that a data value in a layer matches
+    pub fn value<D : Into<TeangaData>>(layer : &str, value : D) -> Query {
+        Query::Value(layer.to_string(), value.into())
+    }
+
+    /// A query that a layer is present in a document
+    pub fn exists(layer : &str) -> Query {
+        Query::Exists(layer.to_string())
+    }
+
+    /// A query that a layer is not present in a document
+    pub fn not_exists(layer : &str) -> Query {
+        Query::NotExists(layer.to_string())
+    }
+
+    /// Combine this query with another query, both of which must match
+    pub fn and(self, other : Query) -> Query {
+        match self {
+            Query::And(mut and) => {
+                and.push(other);
+                Query::And(and)
+            },
+            q => Query::And(vec![q, other])
+        }
+    }
+
+    /// Combine this query with another query, either of which must match
+    pub fn or(self, other : Query) -> Query {
+        match self {
+            Query::Or(mut or) => {
+                or.push(other);
+                Query::Or(or)
+            },
+            q => Query::Or(vec![q, other])
+        }
+    }
+
     pub fn matches(&self, document : &Document,
         meta : &HashMap<String, LayerDesc>) -> bool {
         match self {
@@ -118,9 +197,250 @@ impl Query {
             },
             Query::Exists(field) => {
                 document.get(field).is_some()
+            },
+            Query::NotExists(field) => {
+                document.get(field).is_none()
+            },
+            Query::Near { layer, a, b, distance } => {
+                document.text(layer, meta).is_ok_and(|t| {
+                    let b_positions : Vec<usize> = t.iter().enumerate()
+                        .filter(|(_, t)| *t == b)
+                        .map(|(i, _)| i)
+                        .collect();
+                    t.iter().enumerate()
+                        .filter(|(_, t)| *t == a)
+                        .any(|(i, _)| b_positions.iter()
+                            .any(|j| i != *j && i.abs_diff(*j) <= *distance))
+                })
+            },
+            Query::Sequence { layer, patterns } => {
+                !sequence_spans(layer, patterns, document, meta).is_empty()
             }
         }
     }
+
+    /// Find where this query matches in a document. Conditions on a
+    /// characters layer give the offsets of each match in the text, while
+    /// conditions on other layers give the offsets of the matching
+    /// annotations. `Not`, `Exists` and `NotExists` do not have a position
+    /// and so give no offsets
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - The document to search
+    /// * `meta` - The metadata of the corpus
+    ///
+    /// # Returns
+    ///
+    /// The characters layer and the start and end (exclusive) byte offsets
+    /// in it of each match, sorted and without duplicates
+    pub fn match_offsets(&self, document : &Document,
+        meta : &HashMap<String, LayerDesc>) -> Vec<(String, usize, usize)> {
+        let mut offsets = match self {
+            Query::Text(layer, text) => text_offsets(layer, text, document, meta),
+            Query::TextNot(layer, text) => {
+                annotation_offsets(layer, document, meta, |char_layer, chars, indexes| {
+                    indexes.iter()
+                        .filter(|(start, end)| &chars[*start..*end] != text)
+                        .map(|(start, end)| (char_layer.to_string(), *start, *end))
+                        .collect()
+                })
+            },
+            Query::TextRegex(layer, regex) => text_offsets(layer, regex, document, meta),
+            Query::Value(layer, value) => {
+                data_offsets(layer, |v| v == value, document, meta)
+            },
+            Query::ValueNot(layer, value) => {
+                data_offsets(layer, |v| v != value, document, meta)
+            },
+            Query::LessThan(layer, value) => {
+                data_offsets(layer, |v| v < value, document, meta)
+            },
+            Query::LessThanEqual(layer, value) => {
+                data_offsets(layer, |v| v <= value, document, meta)
+            },
+            Query::GreaterThan(layer, value) => {
+                data_offsets(layer, |v| v > value, document, meta)
+            },
+            Query::GreaterThanEqual(layer, value) => {
+                data_offsets(layer, |v| v >= value, document, meta)
+            },
+            Query::In(layer, values) => {
+                data_offsets(layer, |v| values.contains(v), document, meta)
+            },
+            Query::NotIn(layer, values) => {
+                data_offsets(layer, |v| !values.contains(v), document, meta)
+            },
+            Query::Regex(layer, regex) => {
+                data_offsets(layer, |v| match v {
+                    TeangaData::String(v) => regex.is_match(v),
+                    _ => false
+                }, document, meta)
+            },
+            Query::And(and) => {
+                if self.matches(document, meta) {
+                    and.iter().flat_map(|q| q.match_offsets(document, meta)).collect()
+                } else {
+                    Vec::new()
+                }
+            },
+            Query::Or(or) => {
+                or.iter().flat_map(|q| q.match_offsets(document, meta)).collect()
+            },
+            Query::Not(_) | Query::Exists(_) | Query::NotExists(_) => Vec::new(),
+            Query::Near { layer, a, b, distance } => {
+                annotation_offsets(layer, document, meta, |char_layer, text, indexes| {
+                    let texts : Vec<&str> = indexes.iter().map(|(s, e)| &text[*s..*e]).collect();
+                    let mut offsets = Vec::new();
+                    for (i, _) in texts.iter().enumerate().filter(|(_, t)| *t == a) {
+                        for (j, _) in texts.iter().enumerate().filter(|(_, t)| *t == b) {
+                            if i != j && i.abs_diff(j) <= *distance {
+                                let (first, last) = (i.min(j), i.max(j));
+                                offsets.push((char_layer.to_string(), indexes[first].0, indexes[last].1));
+                            }
+                        }
+                    }
+                    offsets
+                })
+            },
+            Query::Sequence { layer, patterns } => {
+                let spans = sequence_spans(layer, patterns, document, meta);
+                annotation_offsets(layer, document, meta, |char_layer, _, indexes| {
+                    spans.iter().map(|(start, end)|
+                        (char_layer.to_string(), indexes[*start].0, indexes[end - 1].1))
+                        .collect()
+                })
+            }
+        };
+        offsets.sort();
+        offsets.dedup();
+        offsets
+    }
+}
+
+// Call a function with the characters layer, its text and the offsets in it
+// of the annotations of a layer
+fn annotation_offsets<F>(layer : &str, document : &Document,
+    meta : &HashMap<String, LayerDesc>, f : F) -> Vec<(String, usize, usize)>
+    where F : FnOnce(&str, &str, &[(usize, usize)]) -> Vec<(String, usize, usize)> {
+    let graph = LayerGraph::new(meta);
+    let Ok(char_layer) = graph.root_of(layer) else {
+        return Vec::new();
+    };
+    let Some(text) = document.get(char_layer).and_then(|l| l.characters()) else {
+        return Vec::new();
+    };
+    if char_layer == layer {
+        return f(char_layer, text, &[(0, text.len())]);
+    }
+    match document.indexes(layer, char_layer, meta) {
+        Ok(indexes) => f(char_layer, text, &indexes),
+        Err(_) => Vec::new()
+    }
+}
+
+// The offsets of the matches of a text condition. Matches are found in the
+// whole text of a characters layer or are whole annotations of other layers
+fn text_offsets<C : TextMatchCondition + ?Sized>(layer : &str, condition : &C,
+    document : &Document, meta : &HashMap<String, LayerDesc>) -> Vec<(String, usize, usize)> {
+    annotation_offsets(layer, document, meta, |char_layer, text, indexes| {
+        if char_layer == layer {
+            condition.find(text).into_iter()
+                .map(|(start, end)| (char_layer.to_string(), start, end))
+                .collect()
+        } else {
+            indexes.iter()
+                .filter(|(start, end)| condition.matches(&text[*start..*end]))
+                .map(|(start, end)| (char_layer.to_string(), *start, *end))
+                .collect()
+        }
+    })
+}
+
+// The offsets of the annotations of a layer whose data satisfies a predicate
+fn data_offsets<F : Fn(&TeangaData) -> bool>(layer : &str, f : F,
+    document : &Document, meta : &HashMap<String, LayerDesc>) -> Vec<(String, usize, usize)> {
+    let graph = LayerGraph::new(meta);
+    let Ok(char_layer) = graph.root_of(layer) else {
+        return Vec::new();
+    };
+    document.indexes_data(layer, char_layer, meta).map_or(Vec::new(), |data| {
+        data.into_iter()
+            .filter(|(_, _, v)| f(v))
+            .map(|(start, end, _)| (char_layer.to_string(), start, end))
+            .collect()
+    })
+}
+
+/// The ID of a document and the start and end (exclusive) of each match in
+/// it, as returned by `Corpus::search_spans`
+pub type SpanMatches = (String, Vec<(usize, usize)>);
+
+/// Find the places where consecutive annotations of a layer match a sequence
+/// of patterns
+///
+/// # Arguments
+///
+/// * `layer` - The layer of the sequence
+/// * `patterns` - The pattern that each annotation must match
+/// * `document` - The document to search
+/// * `meta` - The metadata of the corpus
+///
+/// # Returns
+///
+/// The start and end (exclusive) of each match as indexes of the annotations
+/// of the layer. Matches may overlap
+pub fn sequence_spans(layer : &str, patterns : &[TokenPattern], document : &Document,
+    meta : &HashMap<String, LayerDesc>) -> Vec<(usize, usize)> {
+    let Ok(text) = document.text(layer, meta) else {
+        return Vec::new();
+    };
+    if patterns.is_empty() || patterns.len() > text.len() {
+        return Vec::new();
+    }
+    let mut values : HashMap<&str, Vec<Vec<TeangaData>>> = HashMap::new();
+    for pattern in patterns.iter() {
+        if let TokenPattern::Value(value_layer, _) = pattern {
+            values.entry(value_layer).or_insert_with(||
+                token_values(layer, value_layer, text.len(), document, meta));
+        }
+    }
+    let pattern_matches = |i : usize, pattern : &TokenPattern| match pattern {
+        TokenPattern::Text(regex) => regex.is_match(text[i]),
+        TokenPattern::Value(value_layer, value) => values[value_layer.as_str()][i].contains(value),
+        TokenPattern::Any => true
+    };
+    (0..=text.len() - patterns.len())
+        .filter(|start| patterns.iter().enumerate().all(|(k, p)| pattern_matches(start + k, p)))
+        .map(|start| (start, start + patterns.len()))
+        .collect()
+}
+
+// The values of a layer for each annotation of the sequence layer
+fn token_values(layer : &str, value_layer : &str, n : usize, document : &Document,
+    meta : &HashMap<String, LayerDesc>) -> Vec<Vec<TeangaData>> {
+    let mut values = vec![Vec::new(); n];
+    if value_layer == layer {
+        for (i, value) in document.data(layer, meta).unwrap_or_default().into_iter().enumerate().take(n) {
+            values[i].push(value);
+        }
+    } else if let Ok(data) = document.indexes_data(value_layer, layer, meta) {
+        for (start, end, value) in data {
+            for v in values.iter_mut().take(end).skip(start) {
+                v.push(value.clone());
+            }
+        }
+    }
+    values
+}
+
+impl std::ops::Not for Query {
+    type Output = Query;
+
+    /// A query that this query does not match
+    fn not(self) -> Query {
+        Query::Not(Box::new(self))
+    }
 }
 
 /// Utility for building queries
@@ -316,6 +636,50 @@ impl QueryBuilder {
             QueryBuilder(Query::And(vec![Query::Exists(field.to_string()), self.0]))
         }
     }
+
+    /// Add a condition that a layer is not present to the query
+    pub fn not_exists(self, field: &str) -> QueryBuilder {
+        if let Query::And(and) = self.0 {
+            let mut q = and;
+            q.push(Query::NotExists(field.to_string()));
+            QueryBuilder(Query::And(q))
+        } else {
+            QueryBuilder(Query::And(vec![Query::NotExists(field.to_string()), self.0]))
+        }
+    }
+
+    /// Add a proximity condition to the query
+    pub fn near(self, layer : &str, a : &str, b : &str, distance : usize) -> QueryBuilder {
+        let near = Query::Near {
+            layer: layer.to_string(),
+            a: a.to_string(),
+            b: b.to_string(),
+            distance
+        };
+        if let Query::And(and) = self.0 {
+            let mut q = and;
+            q.push(near);
+            QueryBuilder(Query::And(q))
+        } else {
+            QueryBuilder(Query::And(vec![near, self.0]))
+        }
+    }
+
+    /// Add a condition that consecutive annotations of a layer match a
+    /// sequence of patterns to the query
+    pub fn sequence(self, layer : &str, patterns : Vec<TokenPattern>) -> QueryBuilder {
+        let sequence = Query::Sequence {
+            layer: layer.to_string(),
+            patterns
+        };
+        if let Query::And(and) = self.0 {
+            let mut q = and;
+            q.push(sequence);
+            QueryBuilder(Query::And(q))
+        } else {
+            QueryBuilder(Query::And(vec![sequence, self.0]))
+        }
+    }
 }
 
 #[cfg(test)]
@@ -375,5 +739,112 @@ mod test {
         let mut iter = corpus.search(query);
         assert!(iter.next().is_some());
     }
-}
 
+    #[test]
+    fn test_query_near() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words")
+            .layer_type(LayerType::span)
+            .base("text").add().unwrap();
+        corpus.build_doc()
+            .layer("text", "the quick brown fox jumps over the lazy dog").unwrap()
+            .layer("words", vec![(0, 3), (4, 9), (10, 15), (16, 19), (20, 25), (26, 30), (31, 34), (35, 39), (40, 43)]).unwrap()
+            .add().unwrap();
+        let doc = corpus.get_doc_by_id(&corpus.get_docs()[0]).unwrap();
+        let meta = corpus.get_meta();
+        assert!(QueryBuilder::new().near("words", "dog", "fox", 5).build().matches(&doc, meta));
+        assert!(!QueryBuilder::new().near("words", "dog", "fox", 4).build().matches(&doc, meta));
+        assert!(QueryBuilder::new().near("words", "the", "the", 6).build().matches(&doc, meta));
+        assert!(!QueryBuilder::new().near("words", "fox", "fox", 3).build().matches(&doc, meta));
+        assert!(!QueryBuilder::new().near("words", "cat", "fox", 3).build().matches(&doc, meta));
+    }
+
+    #[test]
+    fn test_query_not_exists() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").layer_type(LayerType::span).base("text").add().unwrap();
+        corpus.build_layer("pos").layer_type(LayerType::seq).base("words")
+            .data(DataType::String).add().unwrap();
+        corpus.build_layer("ner").layer_type(LayerType::span).base("words")
+            .data(DataType::String).add().unwrap();
+        let partial = corpus.build_doc()
+            .layer("text", "Tá Seán anseo").unwrap()
+            .layer("words", vec![(0, 3), (4, 9), (10, 15)]).unwrap()
+            .layer("pos", vec!["VERB", "PROPN", "ADV"]).unwrap()
+            .add().unwrap();
+        corpus.build_doc()
+            .layer("text", "Tá Máire anseo").unwrap()
+            .layer("words", vec![(0, 3), (4, 10), (11, 16)]).unwrap()
+            .layer("pos", vec!["VERB", "PROPN", "ADV"]).unwrap()
+            .layer("ner", vec![(1, 2, "PER")]).unwrap()
+            .add().unwrap();
+        let query = QueryBuilder::new().exists("pos").not_exists("ner").build();
+        let ids : Vec<String> = corpus.search(query).map(|r| r.unwrap().0).collect();
+        assert_eq!(ids, vec![partial]);
+    }
+
+    #[test]
+    fn test_query_combinators() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").layer_type(LayerType::span).base("text").add().unwrap();
+        corpus.build_layer("ner").layer_type(LayerType::span).base("words")
+            .data(DataType::String).add().unwrap();
+        corpus.build_doc()
+            .layer("text", "Tá Seán anseo").unwrap()
+            .layer("words", vec![(0, 3), (4, 9), (10, 15)]).unwrap()
+            .layer("ner", vec![(1, 2, "PER")]).unwrap()
+            .add().unwrap();
+        let doc = corpus.get_doc_by_id(&corpus.get_docs()[0]).unwrap();
+        let meta = corpus.get_meta();
+        let query = Query::text("words", "Seán").and(Query::exists("ner"));
+        assert!(matches!(&query, Query::And(q) if q.len() == 2));
+        assert!(query.matches(&doc, meta));
+        assert!(!Query::text("words", "Máire").and(Query::exists("ner")).matches(&doc, meta));
+        assert!(Query::text("words", "Máire").or(Query::value("ner", "PER")).matches(&doc, meta));
+        assert!(!(!Query::exists("ner")).matches(&doc, meta));
+        assert!(Query::text("words", "Seán").and(Query::exists("ner")).and(!Query::exists("pos"))
+            .matches(&doc, meta));
+    }
+
+    #[test]
+    fn test_query_sequence() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").layer_type(LayerType::span).base("text").add().unwrap();
+        corpus.build_layer("pos").layer_type(LayerType::seq).base("words")
+            .data(DataType::String).add().unwrap();
+        corpus.build_layer("ner").layer_type(LayerType::span).base("words")
+            .data(DataType::String).add().unwrap();
+        let id = corpus.build_doc()
+            .layer("text", "the lazy old dog saw Old Trafford").unwrap()
+            .layer("words", vec![(0, 3), (4, 8), (9, 12), (13, 16), (17, 20), (21, 24), (25, 33)]).unwrap()
+            .layer("pos", vec!["DET", "ADJ", "ADJ", "NOUN", "VERB", "PROPN", "PROPN"]).unwrap()
+            .layer("ner", vec![(5, 7, "LOC")]).unwrap()
+            .add().unwrap();
+        let doc = corpus.get_doc_by_id(&id).unwrap();
+        let meta = corpus.get_meta();
+        let adj_noun = vec![
+            TokenPattern::Value("pos".to_string(), "ADJ".into()),
+            TokenPattern::Value("pos".to_string(), "NOUN".into())];
+        assert_eq!(sequence_spans("words", &adj_noun, &doc, meta), vec![(2, 4)]);
+        let adj_any = vec![
+            TokenPattern::Value("pos".to_string(), "ADJ".into()),
+            TokenPattern::Any];
+        assert_eq!(sequence_spans("words", &adj_any, &doc, meta), vec![(1, 3), (2, 4)]);
+        let loc = vec![
+            TokenPattern::Text(Regex::new("^[A-Z]").unwrap()),
+            TokenPattern::Value("ner".to_string(), "LOC".into())];
+        assert_eq!(sequence_spans("words", &loc, &doc, meta), vec![(5, 7)]);
+        assert!(QueryBuilder::new().sequence("words", adj_noun).build().matches(&doc, meta));
+        let noun_adj = vec![
+            TokenPattern::Value("pos".to_string(), "NOUN".into()),
+            TokenPattern::Value("pos".to_string(), "ADJ".into())];
+        assert!(!QueryBuilder::new().sequence("words", noun_adj.clone()).build().matches(&doc, meta));
+        let spans : Vec<_> = corpus.search_spans("words", loc).collect::<Result<_, _>>().unwrap();
+        assert_eq!(spans, vec![(id, vec![(5, 7)])]);
+        assert_eq!(corpus.search_spans("words", noun_adj).count(), 0);
+    }
+}
@@ -0,0 +1,152 @@
+//! Differences between two versions of the metadata of a corpus
+//!
+//! This is useful when migrating a corpus from one schema to another, to
+//! check which layers were added, removed or changed.
+//!
+//! # Examples
+//! ```rust
+//! use teanga::{SimpleCorpus, Corpus, LayerType, schema_diff};
+//! let mut corpus = SimpleCorpus::new();
+//! corpus.build_layer("text").add().unwrap();
+//! let before = corpus.get_meta().clone();
+//! corpus.build_layer("words")
+//!   .base("text")
+//!   .layer_type(LayerType::span)
+//!   .add().unwrap();
+//! let diff = schema_diff(&before, corpus.get_meta());
+//! assert_eq!(diff.added, vec!["words"]);
+//! ```
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display, Formatter};
+use crate::LayerDesc;
+
+/// The differences between two versions of the metadata of a corpus
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SchemaDiff {
+    /// The layers that only occur in the new version
+    pub added: Vec<String>,
+    /// The layers that only occur in the old version
+    pub removed: Vec<String>,
+    /// The layers that occur in both versions with a different definition,
+    /// together with the fields (`type`, `base`, `data`, `link_types`,
+    /// `target`, `default` and `meta`) that differ
+    pub changed: BTreeMap<String, Vec<String>>
+}
+
+impl SchemaDiff {
+    /// Check if the two versions are the same
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl Display for SchemaDiff {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for layer in self.added.iter() {
+            writeln!(f, "+ {}", layer)?;
+        }
+        for layer in self.removed.iter() {
+            writeln!(f, "- {}", layer)?;
+        }
+        for (layer, fields) in self.changed.iter() {
+            writeln!(f, "~ {}: {}", layer, fields.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare two versions of the metadata of a corpus
+///
+/// # Arguments
+///
+/// * `a` - The old version of the metadata
+/// * `b` - The new version of the metadata
+///
+/// # Returns
+///
+/// The differences between the versions, with the layers in sorted order
+pub fn schema_diff(a : &HashMap<String, LayerDesc>, b : &HashMap<String, LayerDesc>) -> SchemaDiff {
+    let mut diff = SchemaDiff::default();
+    for (name, desc_b) in b.iter() {
+        match a.get(name) {
+            Some(desc_a) => {
+                let fields = changed_fields(desc_a, desc_b);
+                if !fields.is_empty() {
+                    diff.changed.insert(name.clone(), fields);
+                }
+            },
+            None => diff.added.push(name.clone())
+        }
+    }
+    diff.removed = a.keys().filter(|name| !b.contains_key(*name)).cloned().collect();
+    diff.added.sort();
+    diff.removed.sort();
+    diff
+}
+
+fn changed_fields(a : &LayerDesc, b : &LayerDesc) -> Vec<String> {
+    let mut fields = Vec::new();
+    if a.layer_type != b.layer_type {
+        fields.push("type".to_string());
+    }
+    if a.base != b.base {
+        fields.push("base".to_string());
+    }
+    if a.data != b.data {
+        fields.push("data".to_string());
+    }
+    if a.link_types != b.link_types {
+        fields.push("link_types".to_string());
+    }
+    if a.target != b.target {
+        fields.push("target".to_string());
+    }
+    if a.default != b.default {
+        fields.push("default".to_string());
+    }
+    if a.meta != b.meta {
+        fields.push("meta".to_string());
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Corpus, SimpleCorpus, LayerType, DataType};
+
+    #[test]
+    fn test_schema_diff() {
+        let mut old = SimpleCorpus::new();
+        old.build_layer("text").add().unwrap();
+        old.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        old.build_layer("pos").base("words").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        let mut new = SimpleCorpus::new();
+        new.build_layer("text").add().unwrap();
+        new.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        new.build_layer("upos").base("words").layer_type(LayerType::seq)
+            .data(DataType::Enum(vec!["NOUN".to_string(), "VERB".to_string()])).add().unwrap();
+        new.build_layer("sentences").base("text").layer_type(LayerType::div).add().unwrap();
+        let diff = schema_diff(old.get_meta(), new.get_meta());
+        assert_eq!(diff.added, vec!["sentences", "upos"]);
+        assert_eq!(diff.removed, vec!["pos"]);
+        assert!(diff.changed.is_empty());
+        assert!(schema_diff(new.get_meta(), new.get_meta()).is_empty());
+    }
+
+    #[test]
+    fn test_schema_diff_changed() {
+        let mut old = SimpleCorpus::new();
+        old.build_layer("text").add().unwrap();
+        old.build_layer("pos").base("text").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        let mut new = SimpleCorpus::new();
+        new.build_layer("text").add().unwrap();
+        new.build_layer("pos").base("text").layer_type(LayerType::div)
+            .data(DataType::Enum(vec!["NOUN".to_string()])).add().unwrap();
+        let diff = schema_diff(old.get_meta(), new.get_meta());
+        assert_eq!(diff.changed["pos"], vec!["type", "data"]);
+        assert_eq!(diff.to_string(), "~ pos: type, data\n");
+    }
+}
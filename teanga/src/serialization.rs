@@ -1,18 +1,35 @@
 //! Serialization support for Teanga
-use crate::{Corpus, WriteableCorpus, LayerDesc, Layer, TeangaJsonError, Document};
+use crate::{doc_hash, Corpus, WriteableCorpus, LayerDesc, LayerGraph, Layer, DataType, LayerType, TeangaData, TeangaError, TeangaJsonError, TeangaResult, Document, Value};
 use itertools::Itertools;
 use serde::Deserializer;
 use serde::Serialize;
 use serde::de::Visitor;
 use serde::ser::{Serializer, SerializeMap};
 use std::cmp::min;
-use std::collections::HashMap;
-use std::io::BufRead;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader};
 use std::io::Read;
+use std::io::{Seek, SeekFrom};
 use std::io::Write;
+use std::fs::File;
+use std::path::Path;
 use thiserror::Error;
 
-struct TeangaVisitor2<'a, C : WriteableCorpus>(&'a mut C, bool);
+/// How documents are loaded into a corpus that already has documents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadMode {
+    /// Remove the documents of the corpus and replace its metadata with the
+    /// metadata of the file. The corpus is only changed once the whole file
+    /// has been read
+    Replace,
+    /// Keep the documents of the corpus and add the documents of the file
+    /// after them. The metadata of the file is merged with the metadata of
+    /// the corpus, and it is an error if a layer is defined differently in
+    /// both. Documents that are already in the corpus are not added again
+    Append
+}
+
+struct TeangaVisitor2<'a, C : WriteableCorpus>(&'a mut C, bool, LoadMode);
 
 impl <'de,'a, C: WriteableCorpus> Visitor<'de> for TeangaVisitor2<'a, C> {
     type Value = ();
@@ -24,23 +41,70 @@ impl <'de,'a, C: WriteableCorpus> Visitor<'de> for TeangaVisitor2<'a, C> {
     fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
         where A: serde::de::MapAccess<'de>
     {
+        // When replacing, the documents are only removed once the whole
+        // file has been parsed, so that a corpus is not emptied by a bad file
+        let deferred = !self.1 && self.2 == LoadMode::Replace;
+        let existing = self.0.get_order().clone();
+        let loaded = if !self.1 && self.2 == LoadMode::Append {
+            loaded_hashes(self.0, &existing).map_err(serde::de::Error::custom)?
+        } else {
+            HashSet::new()
+        };
+        let mut added = HashMap::new();
         let mut order = None;
+        let mut meta = None;
+        let mut docs = Vec::new();
         while let Some(ref key) = map.next_key::<String>()? {
             if key == "_meta" {
                 let data = map.next_value::<HashMap<String, LayerDesc>>()?;
-                self.0.set_meta(data)
-                    .map_err(serde::de::Error::custom)?;
+                let data = match self.2 {
+                    LoadMode::Replace => data,
+                    LoadMode::Append => merge_meta(self.0.get_meta(), data)
+                        .map_err(serde::de::Error::custom)?
+                };
+                if deferred {
+                    meta = Some(data);
+                } else {
+                    self.0.set_meta(data)
+                        .map_err(serde::de::Error::custom)?;
+                }
             } else if !self.1 && key == "_order" {
                 order = Some(map.next_value::<Vec<String>>()?);
+            } else if deferred {
+                docs.push((key.clone(), map.next_value::<HashMap<String, Layer>>()?));
             } else if !self.1 {
                 let doc = map.next_value::<HashMap<String, Layer>>()?;
-                let id = self.0.add_doc(doc).map_err(serde::de::Error::custom)?;
-                if id[..min(id.len(), key.len())] != key[..min(id.len(), key.len())] {
-                    return Err(serde::de::Error::custom(format!("Document fails hash check: {} != {}", id, key)))
+                let doc = Document::new(doc, self.0.get_meta()).map_err(serde::de::Error::custom)?;
+                if loaded.contains(&doc_hash(&doc)) {
+                    continue;
                 }
+                let id = self.0.add_doc(doc).map_err(serde::de::Error::custom)?;
+                check_hash(&id, key).map_err(serde::de::Error::custom)?;
+                added.insert(key.clone(), id);
+            }
+        }
+        if deferred {
+            let meta = meta.unwrap_or_else(|| self.0.get_meta().clone());
+            let docs = docs.into_iter()
+                .map(|(key, doc)| Document::new(doc, &meta).map(|doc| (key, doc)))
+                .collect::<TeangaResult<Vec<_>>>()
+                .map_err(serde::de::Error::custom)?;
+            for id in self.0.get_docs() {
+                self.0.remove_doc(&id).map_err(serde::de::Error::custom)?;
+            }
+            self.0.set_meta(meta).map_err(serde::de::Error::custom)?;
+            for (key, doc) in docs {
+                let id = self.0.add_doc(doc).map_err(serde::de::Error::custom)?;
+                check_hash(&id, &key).map_err(serde::de::Error::custom)?;
             }
         }
         if let Some(order) = order {
+            let order = match self.2 {
+                LoadMode::Replace => order,
+                LoadMode::Append => existing.into_iter()
+                    .chain(order.iter().filter_map(|key| added.get(key).cloned()))
+                    .collect()
+            };
             self.0.set_order(order)
                 .map_err(serde::de::Error::custom)?;
         }
@@ -48,6 +112,39 @@ impl <'de,'a, C: WriteableCorpus> Visitor<'de> for TeangaVisitor2<'a, C> {
     }
 }
 
+/// Merge the metadata of a file into the metadata of a corpus, failing if
+/// a layer is defined differently in both
+fn merge_meta(meta : &HashMap<String, LayerDesc>, new_meta : HashMap<String, LayerDesc>)
+    -> TeangaResult<HashMap<String, LayerDesc>> {
+    let mut meta = meta.clone();
+    for (name, layer_desc) in new_meta {
+        match meta.get(&name) {
+            Some(existing) if *existing != layer_desc => return Err(TeangaError::ModelError(
+                format!("Layer {} already exists with a different definition", name))),
+            Some(_) => {},
+            None => {
+                meta.insert(name, layer_desc);
+            }
+        }
+    }
+    Ok(meta)
+}
+
+/// The hashes of the documents already in a corpus, so that documents that
+/// are loaded again can be skipped
+fn loaded_hashes<C : Corpus>(corpus : &C, existing : &[String]) -> TeangaResult<HashSet<String>> {
+    existing.iter().map(|id| Ok(doc_hash(&corpus.get_doc_by_id(id)?))).collect()
+}
+
+/// Check that the ID given to a document agrees with its key in the file
+fn check_hash(id : &str, key : &str) -> Result<(), String> {
+    if id[..min(id.len(), key.len())] != key[..min(id.len(), key.len())] {
+        Err(format!("Document fails hash check: {} != {}", id, key))
+    } else {
+        Ok(())
+    }
+}
+
 fn corpus_serialize<C : Corpus, S>(c : &C, serializer: S) -> Result<S::Ok, S::Error>
     where S: Serializer, C::Content : Serialize
 {
@@ -59,6 +156,52 @@ fn corpus_serialize<C : Corpus, S>(c : &C, serializer: S) -> Result<S::Ok, S::Er
     map.end()
 }
 
+/// Configuration for writing pretty YAML
+#[derive(Debug, Clone, PartialEq)]
+pub struct YamlWriteConfig {
+    /// The number of spaces to indent each level by
+    pub indent : usize,
+    /// Whether the output ends with a newline
+    pub trailing_newline : bool
+}
+
+impl Default for YamlWriteConfig {
+    fn default() -> Self {
+        YamlWriteConfig {
+            indent : 4,
+            trailing_newline : true
+        }
+    }
+}
+
+impl YamlWriteConfig {
+    /// Create a new configuration with an indent of 4 spaces and a trailing
+    /// newline
+    pub fn new() -> YamlWriteConfig {
+        YamlWriteConfig::default()
+    }
+
+    /// Set the number of spaces to indent each level by
+    ///
+    /// # Arguments
+    ///
+    /// * `indent` - The number of spaces
+    pub fn with_indent(mut self, indent : usize) -> YamlWriteConfig {
+        self.indent = indent;
+        self
+    }
+
+    /// Set whether the output ends with a newline
+    ///
+    /// # Arguments
+    ///
+    /// * `trailing_newline` - Whether to end the output with a newline
+    pub fn with_trailing_newline(mut self, trailing_newline : bool) -> YamlWriteConfig {
+        self.trailing_newline = trailing_newline;
+        self
+    }
+}
+
 /// Write a corpus as pretty YAML
 ///
 /// # Arguments
@@ -69,70 +212,115 @@ fn corpus_serialize<C : Corpus, S>(c : &C, serializer: S) -> Result<S::Ok, S::Er
 /// # Returns
 ///
 /// A result indicating success or failure
-pub fn pretty_yaml_serialize<W : Write, C: Corpus>(corpus: &C, mut writer: W) -> Result<(), SerializeError> {
-    writer.write_all(b"_meta:\n")?;
+pub fn pretty_yaml_serialize<W : Write, C: Corpus>(corpus: &C, writer: W) -> Result<(), SerializeError> {
+    pretty_yaml_serialize_with_config(corpus, writer, &YamlWriteConfig::default())
+}
+
+/// Write a corpus as pretty YAML with a specific configuration
+///
+/// # Arguments
+///
+/// * `corpus` - The corpus to write
+/// * `writer` - The writer to write to
+/// * `config` - The indentation and trailing newline to use
+///
+/// # Returns
+///
+/// A result indicating success or failure
+pub fn pretty_yaml_serialize_with_config<W : Write, C: Corpus>(corpus: &C, mut writer: W,
+    config : &YamlWriteConfig) -> Result<(), SerializeError> {
+    let indent1 = " ".repeat(config.indent);
+    let indent2 = " ".repeat(config.indent * 2);
+    // Each section is buffered so the final newline can be removed
+    let mut section = Vec::new();
+    section.write_all(b"_meta:\n")?;
     for name in corpus.get_meta().keys().sorted() {
         let meta = &corpus.get_meta()[name];
-        writer.write_all(b"    ")?;
-        writer.write_all(name.as_bytes())?;
-        writer.write_all(b":\n")?;
-        writer.write_all(b"        type: ")?;
-        writer.write_all(serde_yaml::to_string(&meta.layer_type)?.as_bytes())?;
+        section.write_all(indent1.as_bytes())?;
+        section.write_all(name.as_bytes())?;
+        section.write_all(b":\n")?;
+        section.write_all(indent2.as_bytes())?;
+        section.write_all(b"type: ")?;
+        section.write_all(serde_yaml::to_string(&meta.layer_type)?.as_bytes())?;
         if meta.base != Some("".to_string()) && meta.base != None {
-            writer.write_all(b"        base: ")?;
-            writer.write_all(serde_yaml::to_string(&meta.base)?.as_bytes())?;
+            section.write_all(indent2.as_bytes())?;
+            section.write_all(b"base: ")?;
+            section.write_all(serde_yaml::to_string(&meta.base)?.as_bytes())?;
         }
         if let Some(ref data) = meta.data {
-            writer.write_all(b"        data: ")?;
-            writer.write_all(serde_yaml::to_string(data)?.as_bytes())?;
+            section.write_all(indent2.as_bytes())?;
+            section.write_all(b"data: ")?;
+            section.write_all(serde_yaml::to_string(data)?.as_bytes())?;
         }
         if let Some(ref values) = meta.link_types {
-            writer.write_all(b"        link_types: ")?;
-            writer.write_all(serde_json::to_string(values)?.as_bytes())?;
-            writer.write_all(b"\n")?;
+            section.write_all(indent2.as_bytes())?;
+            section.write_all(b"link_types: ")?;
+            section.write_all(serde_json::to_string(values)?.as_bytes())?;
+            section.write_all(b"\n")?;
         }
         if let Some(ref target) = meta.target {
-            writer.write_all(b"        target: ")?;
-            writer.write_all(serde_yaml::to_string(target)?.as_bytes())?;
+            section.write_all(indent2.as_bytes())?;
+            section.write_all(b"target: ")?;
+            section.write_all(serde_yaml::to_string(target)?.as_bytes())?;
         }
         if let Some(ref default) = meta.default {
-            writer.write_all(b"        default: ")?;
-            writer.write_all(serde_json::to_string(default)?.as_bytes())?;
-            writer.write_all(b"\n")?;
+            section.write_all(indent2.as_bytes())?;
+            section.write_all(b"default: ")?;
+            section.write_all(serde_json::to_string(default)?.as_bytes())?;
+            section.write_all(b"\n")?;
         }
     }
     for id in corpus.get_order() {
-        writer.write_all(id.as_bytes())?;
-        writer.write_all(b":\n")?;
+        writer.write_all(&section)?;
+        section.clear();
+        section.write_all(id.as_bytes())?;
+        section.write_all(b":\n")?;
         let doc = corpus.get_doc_by_id(id)?;
         for name in doc.keys().iter().sorted() {
             let layer = &doc[name];
             if let Layer::Characters(_) = layer {
-                writer.write_all(b"    ")?;
-                writer.write_all(name.as_bytes())?;
-                writer.write_all(b": ")?;
-                writer.write_all(serde_yaml::to_string(layer)?.as_bytes())?;
+                section.write_all(indent1.as_bytes())?;
+                section.write_all(name.as_bytes())?;
+                section.write_all(b": ")?;
+                section.write_all(serde_yaml::to_string(layer)?.as_bytes())?;
             } else {
-                writer.write_all(b"    ")?;
-                writer.write_all(name.as_bytes())?;
-                writer.write_all(b": ")?;
-                writer.write_all(serde_json::to_string(layer)?.as_bytes())?;
-                writer.write_all(b"\n")?;
+                section.write_all(indent1.as_bytes())?;
+                section.write_all(name.as_bytes())?;
+                section.write_all(b": ")?;
+                section.write_all(serde_json::to_string(layer)?.as_bytes())?;
+                section.write_all(b"\n")?;
             }
         }
     }
+    if !config.trailing_newline && section.last() == Some(&b'\n') {
+        section.pop();
+    }
+    writer.write_all(&section)?;
     Ok(())
 }
 
-/// Read a corpus from JSON
+/// Read a corpus from JSON. The documents are added to any documents
+/// already in the corpus, see `read_json_with_mode` to replace them instead
 ///
 /// # Arguments
 ///
 /// * `reader` - The reader to read from
 /// * `corpus` - The corpus to read into
 pub fn read_json<'de, R: Read, C: WriteableCorpus>(reader: R, corpus : &mut C) -> Result<(), serde_json::Error> {
+    read_json_with_mode(reader, corpus, LoadMode::Append)
+}
+
+/// Read a corpus from JSON into a corpus that may already have documents
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read from
+/// * `corpus` - The corpus to read into
+/// * `mode` - Whether to replace or append to the documents of the corpus
+pub fn read_json_with_mode<R: Read, C: WriteableCorpus>(reader: R, corpus : &mut C,
+    mode : LoadMode) -> Result<(), serde_json::Error> {
     let mut deserializer = serde_json::Deserializer::from_reader(reader);
-    deserializer.deserialize_any(TeangaVisitor2(corpus, false))
+    deserializer.deserialize_any(TeangaVisitor2(corpus, false, mode))
 }
 
 /// Read only the metadata from a JSON file
@@ -143,19 +331,33 @@ pub fn read_json<'de, R: Read, C: WriteableCorpus>(reader: R, corpus : &mut C) -
 /// * `corpus` - The corpus to read into
 pub fn read_json_meta<'de, R: Read, C: WriteableCorpus>(reader: R, corpus : &mut C) -> Result<(), serde_json::Error> {
     let mut deserializer = serde_json::Deserializer::from_reader(reader);
-    deserializer.deserialize_any(TeangaVisitor2(corpus, true))
+    deserializer.deserialize_any(TeangaVisitor2(corpus, true, LoadMode::Replace))
 }
 
-/// Read a corpus from YAML
+/// Read a corpus from YAML. The documents are added to any documents
+/// already in the corpus, see `read_yaml_with_mode` to replace them instead
 ///
 /// # Arguments
 ///
 /// * `reader` - The reader to read from
 /// * `corpus` - The corpus to read into
 /// * `meta_only` - Whether to read only the metadata
+///
+/// Anchors (`&a`) and aliases (`*a`) are resolved, as are merge keys (`<<: *a`)
 pub fn read_yaml<'de, R: Read, C: WriteableCorpus>(reader: R, corpus : &mut C) -> Result<(), serde_yaml::Error> {
-    let deserializer = serde_yaml::Deserializer::from_reader(reader);
-    deserializer.deserialize_any(TeangaVisitor2(corpus, false))
+    read_yaml_with_mode(reader, corpus, LoadMode::Append)
+}
+
+/// Read a corpus from YAML into a corpus that may already have documents
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read from
+/// * `corpus` - The corpus to read into
+/// * `mode` - Whether to replace or append to the documents of the corpus
+pub fn read_yaml_with_mode<R: Read, C: WriteableCorpus>(reader: R, corpus : &mut C,
+    mode : LoadMode) -> Result<(), serde_yaml::Error> {
+    yaml_value(reader)?.deserialize_any(TeangaVisitor2(corpus, false, mode))
 }
 
 // Read only the metadata from a YAML file
@@ -165,8 +367,27 @@ pub fn read_yaml<'de, R: Read, C: WriteableCorpus>(reader: R, corpus : &mut C) -
 // * `reader` - The reader to read from
 // * `corpus` - The corpus to read into
 pub fn read_yaml_meta<'de, R: Read, C: WriteableCorpus>(reader: R, corpus : &mut C) -> Result<(), serde_yaml::Error> {
-    let deserializer = serde_yaml::Deserializer::from_reader(reader);
-    deserializer.deserialize_any(TeangaVisitor2(corpus, true))
+    read_yaml_meta_with_mode(reader, corpus, LoadMode::Replace)
+}
+
+/// Read only the metadata from a YAML file, replacing or merging with the
+/// metadata of the corpus. The documents of the corpus are not changed
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read from
+/// * `corpus` - The corpus to read into
+/// * `mode` - Whether to replace or merge with the metadata of the corpus
+pub fn read_yaml_meta_with_mode<R: Read, C: WriteableCorpus>(reader: R, corpus : &mut C,
+    mode : LoadMode) -> Result<(), serde_yaml::Error> {
+    yaml_value(reader)?.deserialize_any(TeangaVisitor2(corpus, true, mode))
+}
+
+// Parse a YAML file resolving aliases and merge keys
+fn yaml_value<R: Read>(reader: R) -> Result<serde_yaml::Value, serde_yaml::Error> {
+    let mut value : serde_yaml::Value = serde_yaml::from_reader(reader)?;
+    value.apply_merge()?;
+    Ok(value)
 }
 
 /// Read a corpus from JSONL. That is a file with one JSON document per line. 
@@ -185,6 +406,193 @@ pub fn read_jsonl<'de, R: BufRead, C : WriteableCorpus>(reader: R, corpus : &mut
     Ok(())
 }
 
+/// Read a corpus from JSONL where consecutive lines with the same value for
+/// a field are parts (e.g., sentences) of the same document. The parts are
+/// merged into a single document: text is concatenated with a space between
+/// each part and the indexes of the other layers are adjusted accordingly.
+/// All parts of a document must have the same layers. As this format does
+/// not have metadata, the corpus must have already been initialized with
+/// metadata.
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read from
+/// * `corpus` - The corpus to read into
+/// * `group_field` - The field of each line giving the document it belongs to
+pub fn read_jsonl_grouped<R: BufRead, C : WriteableCorpus>(reader: R, corpus : &mut C,
+    group_field : &str) -> Result<(), TeangaJsonError> {
+    let mut current : Option<(String, HashMap<String, Layer>)> = None;
+    for line in reader.lines() {
+        let mut part : serde_json::Map<String, serde_json::Value> = serde_json::from_str(&line?)?;
+        let group = match part.remove(group_field) {
+            Some(serde_json::Value::String(group)) => group,
+            Some(group) => group.to_string(),
+            None => return Err(TeangaError::ModelError(
+                format!("Line does not have the field {}", group_field)).into())
+        };
+        let part : HashMap<String, Layer> = serde_json::from_value(serde_json::Value::Object(part))?;
+        match current {
+            Some((ref g, ref mut doc)) if *g == group => merge_part(doc, part, corpus.get_meta())?,
+            _ => {
+                if let Some((_, doc)) = current.take() {
+                    corpus.add_doc(doc)?;
+                }
+                let mut doc = HashMap::new();
+                merge_part(&mut doc, part, corpus.get_meta())?;
+                current = Some((group, doc));
+            }
+        }
+    }
+    if let Some((_, doc)) = current {
+        corpus.add_doc(doc)?;
+    }
+    Ok(())
+}
+
+const PART_SEPARATOR : &str = " ";
+
+// Append a part of a document to the document, shifting its indexes so that
+// they refer to the part's annotations in the merged layers. All parts must
+// have the same layers, as otherwise the offsets would be wrong
+fn merge_part(doc : &mut HashMap<String, Layer>, part : HashMap<String, Layer>,
+    meta : &HashMap<String, LayerDesc>) -> TeangaResult<()> {
+    if !doc.is_empty() && doc.keys().sorted().ne(part.keys().sorted()) {
+        return Err(TeangaError::ModelError(
+            format!("Cannot merge parts of a document with layers {} and {}",
+                doc.keys().sorted().join(", "), part.keys().sorted().join(", "))));
+    }
+    let offset = |name : &str| match doc.get(name) {
+        Some(Layer::Characters(text)) if !text.is_empty() => (text.len() + PART_SEPARATOR.len()) as u32,
+        Some(layer) => layer.len() as u32,
+        None => 0
+    };
+    let mut shifted = Vec::new();
+    for (name, layer) in part {
+        let desc = meta.get(&name).ok_or_else(|| TeangaError::LayerNotFoundError(name.clone()))?;
+        let idx = desc.base.as_deref().map_or(0, offset);
+        let link = desc.target.as_deref().or(desc.base.as_deref()).map_or(0, offset);
+        let is_link = desc.data == Some(DataType::Link);
+        let layer = match layer {
+            Layer::L1(v) => Layer::L1(v.into_iter().map(|i|
+                if desc.layer_type == LayerType::seq { i + link } else { i + idx }).collect()),
+            Layer::L2(v) => Layer::L2(v.into_iter().map(|(i, j)|
+                if is_link { (i + idx, j + link) } else { (i + idx, j + idx) }).collect()),
+            Layer::L3(v) => Layer::L3(v.into_iter().map(|(i, j, k)| (i + idx, j + idx, k + link)).collect()),
+            Layer::L1S(v) => Layer::L1S(v.into_iter().map(|(i, s)|
+                if desc.layer_type == LayerType::seq { (i + link, s) } else { (i + idx, s) }).collect()),
+            Layer::L2S(v) => Layer::L2S(v.into_iter().map(|(i, j, s)|
+                if desc.layer_type == LayerType::span { (i + idx, j + idx, s) } else { (i + idx, j + link, s) }).collect()),
+            Layer::L3S(v) => Layer::L3S(v.into_iter().map(|(i, j, k, s)| (i + idx, j + idx, k + link, s)).collect()),
+            layer => layer
+        };
+        shifted.push((name, layer));
+    }
+    for (name, layer) in shifted {
+        match (doc.get_mut(&name), layer) {
+            (None, layer) => { doc.insert(name, layer); },
+            (Some(Layer::Characters(a)), Layer::Characters(b)) => {
+                if !a.is_empty() {
+                    a.push_str(PART_SEPARATOR);
+                }
+                a.push_str(&b);
+            },
+            (Some(Layer::L1(a)), Layer::L1(b)) => a.extend(b),
+            (Some(Layer::L2(a)), Layer::L2(b)) => a.extend(b),
+            (Some(Layer::L3(a)), Layer::L3(b)) => a.extend(b),
+            (Some(Layer::LS(a)), Layer::LS(b)) => a.extend(b),
+            (Some(Layer::L1S(a)), Layer::L1S(b)) => a.extend(b),
+            (Some(Layer::L2S(a)), Layer::L2S(b)) => a.extend(b),
+            (Some(Layer::L3S(a)), Layer::L3S(b)) => a.extend(b),
+            (Some(Layer::MetaLayer(a)), Layer::MetaLayer(b)) => a.extend(b),
+            _ => return Err(TeangaError::ModelError(
+                format!("Layer {} has different types in parts of the same document", name)))
+        }
+    }
+    Ok(())
+}
+
+/// Open a file for reading, decompressing it if it is compressed with gzip
+/// or zstd. The compression is detected from the first bytes of the file, so
+/// the extension of the file does not matter. Reading gzip requires the
+/// `gzip` feature and reading zstd requires the `zstd` feature
+///
+/// # Arguments
+///
+/// * `path` - The path of the file
+///
+/// # Returns
+///
+/// A reader for the decompressed contents of the file
+pub fn open_maybe_compressed(path : &Path) -> std::io::Result<Box<dyn BufRead>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let magic = reader.fill_buf()?;
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        #[cfg(feature = "gzip")]
+        {
+            Ok(Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader))))
+        }
+        #[cfg(not(feature = "gzip"))]
+        {
+            Err(std::io::Error::new(std::io::ErrorKind::Unsupported,
+                format!("{} is compressed with gzip, which requires the gzip feature", path.display())))
+        }
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        #[cfg(feature = "zstd")]
+        {
+            Ok(Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)))
+        }
+        #[cfg(not(feature = "zstd"))]
+        {
+            Err(std::io::Error::new(std::io::ErrorKind::Unsupported,
+                format!("{} is compressed with zstd, which requires the zstd feature", path.display())))
+        }
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Read a corpus from a tar archive containing a `meta.yaml` file with the
+/// metadata and any number of JSONL files (with the extension `.jsonl`)
+/// with the documents. The archive is read as a stream, and any JSONL files
+/// that occur before the metadata are kept in memory until it is read. For
+/// a `.tar.gz` file, wrap the reader in a gzip decoder.
+///
+/// # Arguments
+///
+/// * `reader` - The reader for the tar archive
+/// * `corpus` - The corpus to read into
+#[cfg(feature = "tar")]
+pub fn read_jsonl_tar<R: Read, C : WriteableCorpus>(reader: R, corpus : &mut C) -> Result<(), TeangaJsonError> {
+    let mut archive = tar::Archive::new(reader);
+    let mut has_meta = false;
+    let mut pending = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let file_name = path.file_name().and_then(|f| f.to_str()).unwrap_or("");
+        if file_name == "meta.yaml" || file_name == "meta.yml" {
+            read_yaml_meta(&mut entry, corpus).map_err(SerializeError::from)?;
+            has_meta = true;
+            for shard in pending.drain(..) {
+                read_jsonl(std::io::Cursor::new(shard), corpus)?;
+            }
+        } else if file_name.ends_with(".jsonl") {
+            if has_meta {
+                read_jsonl(std::io::BufReader::new(&mut entry), corpus)?;
+            } else {
+                let mut shard = Vec::new();
+                entry.read_to_end(&mut shard)?;
+                pending.push(shard);
+            }
+        }
+    }
+    if !has_meta {
+        return Err(TeangaError::ModelError(
+            "Archive does not contain a meta.yaml file".to_string()).into());
+    }
+    Ok(())
+}
+
 /// Read a single line of JSON as a JSON-L document
 ///
 /// # Arguments
@@ -197,6 +605,13 @@ pub fn read_jsonl_line<'de, C : WriteableCorpus>(line: String,
         Ok(Document::new(doc, corpus.get_meta())?)
 }
 
+// Serialize a map with its keys in sorted order, so that the output does not
+// depend on the iteration order of the map
+pub(crate) fn serialize_sorted<S : Serializer, V : Serialize>(map : &HashMap<String, V>,
+    serializer : S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(map.iter().sorted_by(|a, b| a.0.cmp(b.0)))
+}
+
 /// Write a corpus as JSON
 ///
 /// # Arguments
@@ -209,6 +624,39 @@ pub fn write_json<W : Write, C : Corpus>(mut writer : W, corpus : &C) -> Result<
     corpus_serialize(corpus, &mut ser)
 }
 
+/// The number of documents written by `write_json_streaming` between
+/// flushes of the writer
+const STREAMING_FLUSH_DOCS : usize = 1000;
+
+/// Write a corpus as JSON from its metadata and an iterator over its
+/// documents, so that only one document is held in memory at a time. The
+/// output is the same as `write_json` for a corpus with the same metadata and
+/// documents
+///
+/// # Arguments
+///
+/// * `writer` - The writer to write to
+/// * `meta` - The metadata of the corpus
+/// * `docs` - The IDs and documents of the corpus, in order
+pub fn write_json_streaming<W : Write>(mut writer : W, meta : &HashMap<String, LayerDesc>,
+    docs : impl Iterator<Item=TeangaResult<(String, Document)>>) -> Result<(), SerializeError> {
+    writer.write_all(b"{\"_meta\":")?;
+    serde_json::to_writer(&mut writer, meta)?;
+    for (n, doc) in docs.enumerate() {
+        let (id, doc) = doc?;
+        writer.write_all(b",")?;
+        serde_json::to_writer(&mut writer, &id)?;
+        writer.write_all(b":")?;
+        serde_json::to_writer(&mut writer, &doc)?;
+        if (n + 1) % STREAMING_FLUSH_DOCS == 0 {
+            writer.flush()?;
+        }
+    }
+    writer.write_all(b"}")?;
+    writer.flush()?;
+    Ok(())
+}
+
 /// Write a corpus as YAML
 ///
 /// # Arguments
@@ -238,6 +686,905 @@ pub fn write_jsonl<W : Write, C : Corpus>(mut writer : W, corpus : &C) -> Result
     Ok(())
 }
 
+/// Write a corpus as JSONL together with an index giving the byte offset
+/// of each document. The index has one line per document consisting of the
+/// document ID and the offset separated by a tab, and can be read with
+/// `read_jsonl_index`. This will not write the metadata of the corpus.
+///
+/// # Arguments
+///
+/// * `writer` - The writer to write the JSONL to
+/// * `index_writer` - The writer to write the index to
+/// * `corpus` - The corpus to write
+pub fn write_jsonl_indexed<W : Write, I : Write, C : Corpus>(mut writer : W,
+    mut index_writer : I, corpus : &C) -> Result<(), SerializeError>
+    where C::Content : Serialize {
+    let mut offset = 0u64;
+    for id in corpus.get_order() {
+        let doc = corpus.get_doc_by_id(id)?;
+        let mut line = serde_json::to_vec(&doc)?;
+        line.push(b'\n');
+        writer.write_all(&line)?;
+        writeln!(index_writer, "{}\t{}", id, offset)?;
+        offset += line.len() as u64;
+    }
+    Ok(())
+}
+
+/// Selects which layers of a corpus are written in the spaCy JSON format
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpacyMapping {
+    /// The characters layer with the text of the document
+    pub text_layer: String,
+    /// The layer with the tokens
+    pub token_layer: Option<String>,
+    /// A layer with a string value for each token
+    pub tag_layer: Option<String>,
+    /// A span layer with the entities. If the layer has string data this is
+    /// used as the label, otherwise the name of the layer is used
+    pub entity_layer: Option<String>
+}
+
+impl SpacyMapping {
+    /// Create a mapping that writes only the text
+    ///
+    /// # Arguments
+    ///
+    /// * `text_layer` - The characters layer
+    pub fn new(text_layer : &str) -> SpacyMapping {
+        SpacyMapping {
+            text_layer: text_layer.to_string(),
+            token_layer: None,
+            tag_layer: None,
+            entity_layer: None
+        }
+    }
+
+    /// Set the layer with the tokens
+    pub fn tokens(mut self, layer : &str) -> SpacyMapping {
+        self.token_layer = Some(layer.to_string());
+        self
+    }
+
+    /// Set the layer with the tags of the tokens
+    pub fn tags(mut self, layer : &str) -> SpacyMapping {
+        self.tag_layer = Some(layer.to_string());
+        self
+    }
+
+    /// Set the layer with the entities
+    pub fn entities(mut self, layer : &str) -> SpacyMapping {
+        self.entity_layer = Some(layer.to_string());
+        self
+    }
+}
+
+/// Write a corpus in spaCy's JSON training format. The output is an array
+/// with an object for each document containing the `text`, the `tokens`
+/// with their `start`, `end` and `tag` and the `entities` as
+/// `[start, end, label]` triples. Offsets are converted to character
+/// offsets into the text, as expected by spaCy
+///
+/// # Arguments
+///
+/// * `writer` - The writer to write to
+/// * `corpus` - The corpus to write
+/// * `mapping` - The layers to write
+pub fn write_spacy_json<W : Write, C : Corpus>(mut writer : W, corpus : &C,
+    mapping : &SpacyMapping) -> Result<(), SerializeError> {
+    let meta = corpus.get_meta();
+    writer.write_all(b"[")?;
+    for (n, id) in corpus.get_order().iter().enumerate() {
+        let doc = corpus.get_doc_by_id(id)?;
+        let text = doc.get(&mapping.text_layer)
+            .and_then(|l| l.characters())
+            .ok_or_else(|| TeangaError::LayerNotFoundError(mapping.text_layer.clone()))?;
+        let char_offset = |offset : usize| text[..offset].chars().count();
+        let mut obj = serde_json::Map::new();
+        obj.insert("text".to_string(), text.into());
+        if let Some(ref token_layer) = mapping.token_layer {
+            let tokens = doc.indexes(token_layer, &mapping.text_layer, meta)?;
+            let tags = match mapping.tag_layer {
+                Some(ref tag_layer) => {
+                    let tags = doc.data(tag_layer, meta)
+                        .ok_or_else(|| TeangaError::LayerNotFoundError(tag_layer.clone()))?;
+                    if tags.len() != tokens.len() {
+                        return Err(TeangaError::ModelError(
+                            format!("Layer {} does not have a tag for each token in document {}", tag_layer, id)).into());
+                    }
+                    tags.into_iter().map(|t| match t {
+                        TeangaData::String(s) => Some(s),
+                        _ => None
+                    }).collect()
+                },
+                None => vec![None; tokens.len()]
+            };
+            obj.insert("tokens".to_string(), tokens.iter().zip(tags).enumerate()
+                .map(|(i, ((start, end), tag))| {
+                    let mut token = serde_json::Map::new();
+                    token.insert("id".to_string(), i.into());
+                    token.insert("start".to_string(), char_offset(*start).into());
+                    token.insert("end".to_string(), char_offset(*end).into());
+                    if let Some(tag) = tag {
+                        token.insert("tag".to_string(), tag.into());
+                    }
+                    serde_json::Value::Object(token)
+                }).collect());
+        }
+        if let Some(ref entity_layer) = mapping.entity_layer {
+            let entities = if doc.get(entity_layer).is_some() {
+                doc.indexes_data(entity_layer, &mapping.text_layer, meta)?
+            } else {
+                Vec::new()
+            };
+            obj.insert("entities".to_string(), entities.into_iter()
+                .map(|(start, end, data)| {
+                    let label = match data {
+                        TeangaData::String(s) => s,
+                        _ => entity_layer.clone()
+                    };
+                    serde_json::json!([char_offset(start), char_offset(end), label])
+                }).collect());
+        }
+        if n > 0 {
+            writer.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut writer, &obj)?;
+    }
+    writer.write_all(b"]")?;
+    Ok(())
+}
+
+/// Selects which layers of a corpus are written as verticalized text (VRT)
+#[derive(Debug, Clone, PartialEq)]
+pub struct VrtConfig {
+    /// The layer with the tokens, written one per line
+    pub token_layer: String,
+    /// Layers with a value for each token, written as positional attributes
+    /// after the token
+    pub attributes: Vec<String>,
+    /// Layers written as structural tags around the tokens, outermost first
+    pub structures: Vec<String>
+}
+
+impl VrtConfig {
+    /// Create a configuration that writes only the tokens
+    ///
+    /// # Arguments
+    ///
+    /// * `token_layer` - The layer with the tokens
+    pub fn new(token_layer : &str) -> VrtConfig {
+        VrtConfig {
+            token_layer: token_layer.to_string(),
+            attributes: Vec::new(),
+            structures: Vec::new()
+        }
+    }
+
+    /// Add a positional attribute
+    pub fn attribute(mut self, layer : &str) -> VrtConfig {
+        self.attributes.push(layer.to_string());
+        self
+    }
+
+    /// Add a structural attribute
+    pub fn structure(mut self, layer : &str) -> VrtConfig {
+        self.structures.push(layer.to_string());
+        self
+    }
+}
+
+/// Write a corpus as verticalized text (VRT) as used by the IMS Open Corpus
+/// Workbench and (No)SketchEngine. Each document is written in a `<text>`
+/// element with its ID and each token is written on its own line followed by
+/// its positional attributes, separated by tabs. Missing values are written
+/// as `_`.
+///
+/// Each annotation of a structural layer is written as an element of the
+/// same name around the tokens that are inside it. If the layer has string
+/// data this is written as the `value` attribute of the element
+///
+/// # Arguments
+///
+/// * `writer` - The writer to write to
+/// * `corpus` - The corpus to write
+/// * `config` - The layers to write
+pub fn write_vrt<W : Write, C : Corpus>(mut writer : W, corpus : &C,
+    config : &VrtConfig) -> Result<(), SerializeError> {
+    let meta = corpus.get_meta();
+    let graph = LayerGraph::new(meta);
+    let char_layer = graph.root_of(&config.token_layer)?;
+    for layer in config.attributes.iter().chain(config.structures.iter()) {
+        if !meta.contains_key(layer) {
+            return Err(TeangaError::LayerNotFoundError(layer.clone()).into());
+        }
+    }
+    for id in corpus.get_order() {
+        let doc = corpus.get_doc_by_id(id)?;
+        let text = doc.get(char_layer)
+            .and_then(|l| l.characters())
+            .ok_or_else(|| TeangaError::LayerNotFoundError(char_layer.to_string()))?;
+        let tokens = doc.indexes(&config.token_layer, char_layer, meta)?;
+        let attributes = config.attributes.iter().map(|layer| {
+            let values = doc.data(layer, meta).unwrap_or_default();
+            if !values.is_empty() && values.len() != tokens.len() {
+                return Err(TeangaError::ModelError(
+                    format!("Layer {} does not have a value for each token in document {}", layer, id)));
+            }
+            Ok(values)
+        }).collect::<TeangaResult<Vec<_>>>()?;
+        let mut opens = vec![Vec::new(); tokens.len()];
+        let mut closes = vec![Vec::new(); tokens.len()];
+        for layer in config.structures.iter() {
+            if doc.get(layer).is_none() {
+                continue;
+            }
+            for (start, end, data) in doc.indexes_data(layer, char_layer, meta)? {
+                let first = tokens.iter().position(|(s, _)| *s >= start);
+                let last = tokens.iter().rposition(|(_, e)| *e <= end);
+                if let (Some(first), Some(last)) = (first, last) {
+                    if first <= last {
+                        let tag = match data {
+                            TeangaData::String(v) => format!("<{} value=\"{}\">", layer, vrt_escape(&v)),
+                            _ => format!("<{}>", layer)
+                        };
+                        opens[first].push(tag);
+                        closes[last].insert(0, format!("</{}>", layer));
+                    }
+                }
+            }
+        }
+        writeln!(writer, "<text id=\"{}\">", vrt_escape(id))?;
+        for (i, (start, end)) in tokens.iter().enumerate() {
+            for tag in opens[i].iter() {
+                writeln!(writer, "{}", tag)?;
+            }
+            write!(writer, "{}", vrt_escape(&text[*start..*end]))?;
+            for values in attributes.iter() {
+                match values.get(i) {
+                    Some(TeangaData::String(v)) => write!(writer, "\t{}", vrt_escape(v))?,
+                    _ => write!(writer, "\t_")?
+                }
+            }
+            writeln!(writer)?;
+            for tag in closes[i].iter() {
+                writeln!(writer, "{}", tag)?;
+            }
+        }
+        writeln!(writer, "</text>")?;
+    }
+    Ok(())
+}
+
+/// Write the annotations of the elements of a layer as tab-separated values,
+/// e.g., for use in a spreadsheet. Each document starts with a comment row
+/// `# <id>` and is followed by one row for each element of the base layer,
+/// with its text and the value of each data layer for that element. Values
+/// of a data layer that spans several elements are repeated for each element
+/// and missing values are written as empty cells. Documents are separated by
+/// a blank line.
+///
+/// Cells containing tabs, newlines or quotes are quoted as in CSV, that is
+/// between double quotes with any quotes doubled
+///
+/// # Arguments
+///
+/// * `writer` - The writer to write to
+/// * `corpus` - The corpus to write
+/// * `base_layer` - The layer with an element for each row
+/// * `data_layers` - The layers with a column for each row
+pub fn write_layer_tsv<W : Write, C : Corpus>(mut writer : W, corpus : &C,
+    base_layer : &str, data_layers : &[&str]) -> Result<(), SerializeError> {
+    let meta = corpus.get_meta();
+    for layer in std::iter::once(&base_layer).chain(data_layers.iter()) {
+        if !meta.contains_key(*layer) {
+            return Err(TeangaError::LayerNotFoundError(layer.to_string()).into());
+        }
+    }
+    for (i, id) in corpus.get_order().iter().enumerate() {
+        let doc = corpus.get_doc_by_id(id)?;
+        let words = doc.text(base_layer, meta)?;
+        let mut columns = Vec::new();
+        for layer in data_layers.iter() {
+            let mut values = vec![String::new(); words.len()];
+            if let Some(l) = doc.get(layer) {
+                for (start, end, data) in l.indexes_data(layer, base_layer, &doc, meta)? {
+                    let value = match data {
+                        TeangaData::None => continue,
+                        TeangaData::String(s) => s,
+                        TeangaData::Link(j) => j.to_string(),
+                        TeangaData::TypedLink(j, t) => format!("{}:{}", t, j)
+                    };
+                    for cell in values.iter_mut().take(end).skip(start) {
+                        *cell = value.clone();
+                    }
+                }
+            }
+            columns.push(values);
+        }
+        if i > 0 {
+            writeln!(writer)?;
+        }
+        writeln!(writer, "# {}", id)?;
+        for (j, word) in words.iter().enumerate() {
+            write!(writer, "{}", tsv_escape(word))?;
+            for values in columns.iter() {
+                write!(writer, "\t{}", tsv_escape(&values[j]))?;
+            }
+            writeln!(writer)?;
+        }
+    }
+    Ok(())
+}
+
+// Quote a cell of a TSV file if it contains a tab, newline or quote
+fn tsv_escape(s : &str) -> String {
+    if s.contains(['\t', '\n', '\r', '"']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+// Escape a string for VRT, which is XML-like and line and tab based
+fn vrt_escape(s : &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace(['\t', '\n', '\r'], " ")
+}
+
+/// Configuration for reading CoNLL-U with `read_conllu`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ConlluConfig {
+    /// Whether multiword tokens (e.g., `1-2`) are recorded in the meta layer
+    /// `_multiword_tokens`. Otherwise they are only used for the text
+    pub multiword_tokens : bool,
+    /// Whether empty nodes (e.g., `1.1`) are recorded in the meta layer
+    /// `_empty_nodes`. Otherwise they are skipped
+    pub empty_nodes : bool
+}
+
+impl ConlluConfig {
+    /// Create a new configuration that skips multiword tokens and empty nodes
+    pub fn new() -> ConlluConfig {
+        ConlluConfig::default()
+    }
+
+    /// Set whether multiword tokens are recorded in the meta layer
+    /// `_multiword_tokens`
+    ///
+    /// # Arguments
+    ///
+    /// * `multiword_tokens` - Whether to record multiword tokens
+    pub fn with_multiword_tokens(mut self, multiword_tokens : bool) -> ConlluConfig {
+        self.multiword_tokens = multiword_tokens;
+        self
+    }
+
+    /// Set whether empty nodes are recorded in the meta layer `_empty_nodes`
+    ///
+    /// # Arguments
+    ///
+    /// * `empty_nodes` - Whether to record empty nodes
+    pub fn with_empty_nodes(mut self, empty_nodes : bool) -> ConlluConfig {
+        self.empty_nodes = empty_nodes;
+        self
+    }
+}
+
+const CONLLU_COLUMNS : [&str; 10] = ["id", "form", "lemma", "upos", "xpos",
+    "feats", "head", "deprel", "deps", "misc"];
+
+/// Read a corpus in the CoNLL-U format. Each sentence becomes a document
+/// with the layers
+///
+/// * `text` - The text of the sentence, made from the forms of the tokens
+///   separated by spaces unless the token has `SpaceAfter=No`
+/// * `tokens` - A span for each word. The words of a multiword token (e.g.,
+///   `del` = `de el`) all have the span of the multiword token
+/// * `form`, `upos`, `xpos`, `lemma`, `feats`, `deprel`, `deps` and `misc` -
+///   The columns of each word, with `_` for missing values
+/// * `head` - An element layer with a link from each word to its head. The
+///   root of the sentence has no head and so no annotation
+/// * `_comments` - The comments before the sentence (e.g., `# sent_id = 1`
+///   or `# newdoc`) other than `# text`, as a meta layer with one entry per
+///   comment. A comment without a value is recorded as `true`
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read from
+/// * `corpus` - The corpus to read into
+/// * `config` - How multiword tokens and empty nodes are read
+pub fn read_conllu<R : BufRead, C : WriteableCorpus>(reader : R, corpus : &mut C,
+    config : &ConlluConfig) -> Result<(), SerializeError> {
+    let characters = || LayerDesc {
+        layer_type: LayerType::characters,
+        ..LayerDesc::default()
+    };
+    let on_tokens = |layer_type : LayerType, data : DataType| LayerDesc {
+        layer_type,
+        base: Some("tokens".to_string()),
+        data: Some(data),
+        ..LayerDesc::default()
+    };
+    corpus.add_layer_meta_checked("text".to_string(), characters())?;
+    corpus.add_layer_meta_checked("tokens".to_string(), LayerDesc {
+        layer_type: LayerType::span,
+        base: Some("text".to_string()),
+        ..LayerDesc::default()
+    })?;
+    for layer in ["form", "upos", "xpos", "lemma", "feats", "deprel", "deps", "misc"] {
+        corpus.add_layer_meta_checked(layer.to_string(),
+            on_tokens(LayerType::seq, DataType::String))?;
+    }
+    corpus.add_layer_meta_checked("head".to_string(),
+        on_tokens(LayerType::element, DataType::Link))?;
+    corpus.add_layer_meta_checked("_comments".to_string(), characters())?;
+    if config.multiword_tokens {
+        corpus.add_layer_meta_checked("_multiword_tokens".to_string(), characters())?;
+    }
+    if config.empty_nodes {
+        corpus.add_layer_meta_checked("_empty_nodes".to_string(), characters())?;
+    }
+    let mut sentence = ConlluSentence::default();
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            if !sentence.words.is_empty() {
+                corpus.add_doc(sentence.into_doc(config)?)?;
+            }
+            sentence = ConlluSentence::default();
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix('#') {
+            let comment = match comment.split_once('=') {
+                Some((key, value)) => (key.trim().to_string(), Value::String(value.trim().to_string())),
+                None => (comment.trim().to_string(), Value::Bool(true))
+            };
+            if comment.0 != "text" {
+                sentence.comments.push(HashMap::from([comment]));
+            }
+            continue;
+        }
+        let cols : Vec<String> = line.split('\t').map(|c| c.to_string()).collect();
+        if cols.len() != CONLLU_COLUMNS.len() {
+            return Err(TeangaError::ModelError(
+                format!("Line {}: expected {} columns but found {}", n + 1, CONLLU_COLUMNS.len(), cols.len())).into());
+        }
+        if let Some((start, end)) = cols[0].split_once('-') {
+            let (start, end) = start.parse::<usize>().ok().zip(end.parse::<usize>().ok())
+                .ok_or_else(|| TeangaError::ModelError(
+                    format!("Line {}: invalid multiword token ID {}", n + 1, cols[0])))?;
+            sentence.multiword_tokens.insert(start, (end, cols));
+        } else if cols[0].contains('.') {
+            sentence.empty_nodes.push(cols);
+        } else {
+            sentence.words.push((n + 1, cols));
+        }
+    }
+    if !sentence.words.is_empty() {
+        corpus.add_doc(sentence.into_doc(config)?)?;
+    }
+    Ok(())
+}
+
+/// The lines of a CoNLL-U sentence
+#[derive(Default)]
+struct ConlluSentence {
+    /// The line number and columns of each word
+    words : Vec<(usize, Vec<String>)>,
+    /// The last word and the columns of each multiword token by its first word
+    multiword_tokens : HashMap<usize, (usize, Vec<String>)>,
+    empty_nodes : Vec<Vec<String>>,
+    /// The comments before the sentence, other than `# text`
+    comments : Vec<HashMap<String, Value>>
+}
+
+impl ConlluSentence {
+    fn into_doc(self, config : &ConlluConfig) -> TeangaResult<Vec<(String, Layer)>> {
+        let space_after = |misc : &str| !misc.split('|').any(|f| f == "SpaceAfter=No");
+        let mut text = String::new();
+        let mut tokens = Vec::new();
+        let mut space = false;
+        let mut i = 0;
+        while i < self.words.len() {
+            if space {
+                text.push(' ');
+            }
+            let start = text.len() as u32;
+            match self.multiword_tokens.get(&(i + 1)) {
+                Some((end, cols)) if *end > i => {
+                    text.push_str(&cols[1]);
+                    let n = (*end - i).min(self.words.len() - i);
+                    tokens.extend(std::iter::repeat_n((start, text.len() as u32), n));
+                    space = space_after(&cols[9]);
+                    i += n;
+                },
+                _ => {
+                    text.push_str(&self.words[i].1[1]);
+                    tokens.push((start, text.len() as u32));
+                    space = space_after(&self.words[i].1[9]);
+                    i += 1;
+                }
+            }
+        }
+        let column = |c : usize| Layer::LS(self.words.iter().map(|(_, cols)| cols[c].clone()).collect());
+        let mut heads = Vec::new();
+        for (i, (line, cols)) in self.words.iter().enumerate() {
+            let head = cols[6].parse::<u32>().ok()
+                .filter(|h| *h as usize <= self.words.len())
+                .ok_or_else(|| TeangaError::ModelError(
+                    format!("Line {}: invalid head {}", line, cols[6])))?;
+            if head > 0 {
+                heads.push((i as u32, head - 1));
+            }
+        }
+        let mut doc = vec![
+            ("text".to_string(), Layer::Characters(text)),
+            ("tokens".to_string(), Layer::L2(tokens)),
+            ("form".to_string(), column(1)),
+            ("upos".to_string(), column(3)),
+            ("xpos".to_string(), column(4)),
+            ("lemma".to_string(), column(2)),
+            ("feats".to_string(), column(5)),
+            ("deprel".to_string(), column(7)),
+            ("deps".to_string(), column(8)),
+            ("misc".to_string(), column(9)),
+            ("head".to_string(), Layer::L2(heads))];
+        if !self.comments.is_empty() {
+            doc.push(("_comments".to_string(), Layer::MetaLayer(self.comments)));
+        }
+        let as_meta = |cols : &Vec<String>| CONLLU_COLUMNS.iter().zip(cols.iter())
+            .filter(|(_, v)| *v != "_")
+            .map(|(k, v)| (k.to_string(), Value::String(v.clone())))
+            .collect::<HashMap<String, Value>>();
+        if config.multiword_tokens && !self.multiword_tokens.is_empty() {
+            doc.push(("_multiword_tokens".to_string(), Layer::MetaLayer(
+                self.multiword_tokens.iter().sorted_by_key(|(start, _)| **start)
+                    .map(|(_, (_, cols))| as_meta(cols)).collect())));
+        }
+        if config.empty_nodes && !self.empty_nodes.is_empty() {
+            doc.push(("_empty_nodes".to_string(), Layer::MetaLayer(
+                self.empty_nodes.iter().map(as_meta).collect())));
+        }
+        Ok(doc)
+    }
+}
+
+/// The layers that are written as CoNLL-U by `write_conllu`. By default
+/// these are the layers created by `read_conllu`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConlluWriteConfig {
+    /// The span layer with the tokens
+    pub tokens : String,
+    /// The layer with the form of each token. This is only used for the
+    /// words of multiword tokens, as the form of other tokens is taken from
+    /// the text
+    pub form : String,
+    /// The layer with the universal part-of-speech tag of each token
+    pub upos : String,
+    /// The layer with the language-specific part-of-speech tag of each token
+    pub xpos : String,
+    /// The layer with the lemma of each token
+    pub lemma : String,
+    /// The layer with the morphological features of each token
+    pub feats : String,
+    /// The layer linking each token to its head
+    pub head : String,
+    /// The layer with the dependency relation of each token to its head
+    pub deprel : String,
+    /// The layer with the enhanced dependencies of each token
+    pub deps : String,
+    /// The layer with the miscellaneous annotation of each token. If a
+    /// document does not have this layer, `SpaceAfter=No` is written from
+    /// the text
+    pub misc : String,
+    /// The meta layer with the comments written before the first sentence
+    /// of each document
+    pub comments : String,
+    /// The div layer that splits the text into sentences. If a document does
+    /// not have this layer it is written as a single sentence
+    pub sentences : String
+}
+
+impl Default for ConlluWriteConfig {
+    fn default() -> Self {
+        ConlluWriteConfig {
+            tokens : "tokens".to_string(),
+            form : "form".to_string(),
+            upos : "upos".to_string(),
+            xpos : "xpos".to_string(),
+            lemma : "lemma".to_string(),
+            feats : "feats".to_string(),
+            head : "head".to_string(),
+            deprel : "deprel".to_string(),
+            deps : "deps".to_string(),
+            misc : "misc".to_string(),
+            comments : "_comments".to_string(),
+            sentences : "sentences".to_string()
+        }
+    }
+}
+
+impl ConlluWriteConfig {
+    /// Create a new configuration with the layer names used by `read_conllu`
+    pub fn new() -> ConlluWriteConfig {
+        ConlluWriteConfig::default()
+    }
+
+    /// Set the span layer with the tokens
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - The name of the layer
+    pub fn with_tokens(mut self, tokens : &str) -> ConlluWriteConfig {
+        self.tokens = tokens.to_string();
+        self
+    }
+
+    /// Set the layer with the form of the words of multiword tokens
+    ///
+    /// # Arguments
+    ///
+    /// * `form` - The name of the layer
+    pub fn with_form(mut self, form : &str) -> ConlluWriteConfig {
+        self.form = form.to_string();
+        self
+    }
+
+    /// Set the layer written in the UPOS column
+    ///
+    /// # Arguments
+    ///
+    /// * `upos` - The name of the layer
+    pub fn with_upos(mut self, upos : &str) -> ConlluWriteConfig {
+        self.upos = upos.to_string();
+        self
+    }
+
+    /// Set the layer written in the XPOS column
+    ///
+    /// # Arguments
+    ///
+    /// * `xpos` - The name of the layer
+    pub fn with_xpos(mut self, xpos : &str) -> ConlluWriteConfig {
+        self.xpos = xpos.to_string();
+        self
+    }
+
+    /// Set the layer written in the LEMMA column
+    ///
+    /// # Arguments
+    ///
+    /// * `lemma` - The name of the layer
+    pub fn with_lemma(mut self, lemma : &str) -> ConlluWriteConfig {
+        self.lemma = lemma.to_string();
+        self
+    }
+
+    /// Set the layer written in the FEATS column
+    ///
+    /// # Arguments
+    ///
+    /// * `feats` - The name of the layer
+    pub fn with_feats(mut self, feats : &str) -> ConlluWriteConfig {
+        self.feats = feats.to_string();
+        self
+    }
+
+    /// Set the layer linking each token to its head, which is written in
+    /// the HEAD column
+    ///
+    /// # Arguments
+    ///
+    /// * `head` - The name of the layer
+    pub fn with_head(mut self, head : &str) -> ConlluWriteConfig {
+        self.head = head.to_string();
+        self
+    }
+
+    /// Set the layer written in the DEPREL column
+    ///
+    /// # Arguments
+    ///
+    /// * `deprel` - The name of the layer
+    pub fn with_deprel(mut self, deprel : &str) -> ConlluWriteConfig {
+        self.deprel = deprel.to_string();
+        self
+    }
+
+    /// Set the layer written in the DEPS column
+    ///
+    /// # Arguments
+    ///
+    /// * `deps` - The name of the layer
+    pub fn with_deps(mut self, deps : &str) -> ConlluWriteConfig {
+        self.deps = deps.to_string();
+        self
+    }
+
+    /// Set the layer written in the MISC column
+    ///
+    /// # Arguments
+    ///
+    /// * `misc` - The name of the layer
+    pub fn with_misc(mut self, misc : &str) -> ConlluWriteConfig {
+        self.misc = misc.to_string();
+        self
+    }
+
+    /// Set the meta layer with the comments of each document
+    ///
+    /// # Arguments
+    ///
+    /// * `comments` - The name of the layer
+    pub fn with_comments(mut self, comments : &str) -> ConlluWriteConfig {
+        self.comments = comments.to_string();
+        self
+    }
+
+    /// Set the div layer that splits the text into sentences
+    ///
+    /// # Arguments
+    ///
+    /// * `sentences` - The name of the layer
+    pub fn with_sentences(mut self, sentences : &str) -> ConlluWriteConfig {
+        self.sentences = sentences.to_string();
+        self
+    }
+}
+
+/// Write a corpus in the CoNLL-U format. The form of each token is taken
+/// from the text and, if there is no MISC layer, `SpaceAfter=No` is written
+/// if the next token starts where the token ends. Consecutive tokens with
+/// the same span are written as a multiword token, with the form of each
+/// word taken from the form layer. The comments of a document are written
+/// before its first sentence, each sentence has a `# text` comment and
+/// missing values are written as `_`
+///
+/// # Arguments
+///
+/// * `writer` - The writer to write to
+/// * `corpus` - The corpus to write
+/// * `config` - The layers to write
+pub fn write_conllu<W : Write, C : Corpus>(mut writer : W, corpus : &C,
+    config : &ConlluWriteConfig) -> Result<(), SerializeError> {
+    let meta = corpus.get_meta();
+    let graph = LayerGraph::new(meta);
+    let char_layer = graph.root_of(&config.tokens)?;
+    for id in corpus.get_order() {
+        let doc = corpus.get_doc_by_id(id)?;
+        let text = doc.get(char_layer)
+            .and_then(|l| l.characters())
+            .ok_or_else(|| TeangaError::LayerNotFoundError(char_layer.to_string()))?;
+        let tokens = doc.indexes(&config.tokens, char_layer, meta)?;
+        let column = |layer : &str| {
+            let values = doc.data(layer, meta).unwrap_or_default();
+            if !values.is_empty() && values.len() != tokens.len() {
+                return Err(TeangaError::ModelError(
+                    format!("Layer {} does not have a value for each token in document {}", layer, id)));
+            }
+            Ok(values)
+        };
+        let form = column(&config.form)?;
+        let upos = column(&config.upos)?;
+        let xpos = column(&config.xpos)?;
+        let lemma = column(&config.lemma)?;
+        let feats = column(&config.feats)?;
+        let deprel = column(&config.deprel)?;
+        let deps = column(&config.deps)?;
+        let misc = column(&config.misc)?;
+        let heads : HashMap<usize, usize> = match doc.get(&config.head) {
+            Some(Layer::L1(v)) => v.iter().enumerate().map(|(i, h)| (i, *h as usize)).collect(),
+            Some(Layer::L2(v)) => v.iter().map(|(i, h)| (*i as usize, *h as usize)).collect(),
+            Some(Layer::L2S(v)) => v.iter().map(|(i, h, _)| (*i as usize, *h as usize)).collect(),
+            Some(_) => return Err(TeangaError::ModelError(
+                format!("Layer {} does not link tokens to their heads", config.head)).into()),
+            None => HashMap::new()
+        };
+        let sentence_starts = match doc.get(&config.sentences) {
+            Some(_) => doc.indexes(&config.sentences, char_layer, meta)?
+                .into_iter().map(|(s, _)| s).collect(),
+            None => Vec::new()
+        };
+        if let Some(Layer::MetaLayer(comments)) = doc.get(&config.comments) {
+            for (key, value) in comments.iter().flat_map(|c| c.iter().sorted_by(|a, b| a.0.cmp(b.0))) {
+                match value {
+                    Value::Bool(true) => writeln!(writer, "# {}", key)?,
+                    Value::String(v) => writeln!(writer, "# {} = {}", key, v.replace('\n', " "))?,
+                    v => writeln!(writer, "# {} = {}", key, serde_json::to_string(v)?)?
+                }
+            }
+        }
+        let space_after = |i : usize, last : usize| match tokens.get(i + 1) {
+            Some((s, _)) if i < last && *s == tokens[i].1 => "SpaceAfter=No",
+            _ => "_"
+        };
+        let chunks = tokens.iter().enumerate().chunk_by(|(_, (s, _))|
+            sentence_starts.partition_point(|start| start <= s));
+        for (_, sentence) in chunks.into_iter() {
+            let sentence : Vec<usize> = sentence.map(|(i, _)| i).collect();
+            let first = sentence[0];
+            let last = sentence[sentence.len() - 1];
+            writeln!(writer, "# text = {}", text[tokens[first].0..tokens[last].1].replace('\n', " "))?;
+            for i in sentence {
+                let head = match heads.get(&i) {
+                    Some(h) if *h >= first && *h <= last => (h - first + 1).to_string(),
+                    Some(_) => return Err(TeangaError::ModelError(
+                        format!("Token {} has a head outside its sentence in document {}", i, id)).into()),
+                    None if doc.get(&config.head).is_none() => "_".to_string(),
+                    None => "0".to_string()
+                };
+                let value = |values : &Vec<TeangaData>| match values.get(i) {
+                    Some(TeangaData::String(v)) => v.replace(['\t', '\n'], " "),
+                    _ => "_".to_string()
+                };
+                // The words of a multiword token all have its span
+                let multiword = (i > first && tokens[i - 1] == tokens[i]) ||
+                    (i < last && tokens[i + 1] == tokens[i]);
+                if multiword && (i == first || tokens[i - 1] != tokens[i]) {
+                    let end = (i..=last).take_while(|j| tokens[*j] == tokens[i]).last().unwrap_or(i);
+                    writeln!(writer, "{}-{}\t{}\t_\t_\t_\t_\t_\t_\t_\t{}",
+                        i - first + 1, end - first + 1, &text[tokens[i].0..tokens[i].1],
+                        space_after(end, last))?;
+                }
+                let (word, word_misc) = if multiword {
+                    (value(&form), "_")
+                } else {
+                    (text[tokens[i].0..tokens[i].1].to_string(), space_after(i, last))
+                };
+                let word_misc = if misc.is_empty() { word_misc.to_string() } else { value(&misc) };
+                writeln!(writer, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    i - first + 1, word, value(&lemma), value(&upos), value(&xpos),
+                    value(&feats), head, value(&deprel), value(&deps), word_misc)?;
+            }
+            writeln!(writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Read an index written by `write_jsonl_indexed`
+///
+/// # Arguments
+///
+/// * `reader` - The reader to read the index from
+///
+/// # Returns
+///
+/// The document IDs and their byte offsets in the JSONL file, in the order
+/// of the corpus
+pub fn read_jsonl_index<R : BufRead>(reader : R) -> Result<Vec<(String, u64)>, TeangaJsonError> {
+    let mut index = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let (id, offset) = line.rsplit_once('\t').and_then(|(id, offset)|
+            offset.parse::<u64>().ok().map(|offset| (id.to_string(), offset)))
+            .ok_or_else(|| TeangaError::ModelError(format!("Invalid index line: {}", line)))?;
+        index.push((id, offset));
+    }
+    Ok(index)
+}
+
+/// Read a single document from a JSONL file at a given byte offset
+///
+/// # Arguments
+///
+/// * `reader` - The reader for the JSONL file
+/// * `offset` - The byte offset of the document, as given by the index
+/// * `corpus` - The corpus giving the metadata of the document
+pub fn read_jsonl_at<R : BufRead + Seek, C : Corpus>(mut reader : R, offset : u64,
+    corpus : &C) -> Result<Document, TeangaJsonError> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let doc : HashMap<String, Layer> = serde_json::from_str(&line)?;
+    Ok(Document::new(doc, corpus.get_meta())?)
+}
+
 /// A serialization error
 #[derive(Error,Debug)]
 pub enum SerializeError {
@@ -264,7 +1611,202 @@ pub enum SerializeError {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::SimpleCorpus;
+    use crate::{SimpleCorpus, LayerType, DataType};
+
+    #[test]
+    fn test_read_conllu() {
+        let data = "# newdoc
+# sent_id = 1
+# text = Vino del mar.
+1\tVino\tvenir\tVERB\t_\t_\t0\troot\t_\t_
+2-3\tdel\t_\t_\t_\t_\t_\t_\t_\t_
+2\tde\tde\tADP\t_\t_\t4\tcase\t_\t_
+3\tel\tel\tDET\t_\t_\t4\tdet\t_\t_
+4\tmar\tmar\tNOUN\t_\t_\t1\tobl\t_\tSpaceAfter=No
+5\t.\t.\tPUNCT\t_\t_\t1\tpunct\t_\t_
+
+1\tHola\thola\tINTJ\tUH\t_\t0\troot\t_\t_
+1.1\tfue\tser\tAUX\t_\t_\t_\t_\t0:root\t_
+";
+        let mut corpus = SimpleCorpus::new();
+        read_conllu(data.as_bytes(), &mut corpus,
+            &ConlluConfig::new().with_multiword_tokens(true)).unwrap();
+        assert_eq!(corpus.get_docs().len(), 2);
+        let doc = corpus.get_doc_by_id(&corpus.get_docs()[0]).unwrap();
+        assert_eq!(doc["text"], Layer::Characters("Vino del mar.".to_string()));
+        assert_eq!(doc["tokens"], Layer::L2(vec![(0, 4), (5, 8), (5, 8), (9, 12), (12, 13)]));
+        assert_eq!(doc.text("lemma", corpus.get_meta()).unwrap(), vec!["Vino", "del", "del", "mar", "."]);
+        assert_eq!(doc["upos"], Layer::LS(vec!["VERB", "ADP", "DET", "NOUN", "PUNCT"]
+            .into_iter().map(|s| s.to_string()).collect()));
+        assert_eq!(doc["head"], Layer::L2(vec![(1, 3), (2, 3), (3, 0), (4, 0)]));
+        assert_eq!(doc.validate_links(corpus.get_meta()), Ok(()));
+        assert_eq!(doc["_multiword_tokens"], Layer::MetaLayer(vec![HashMap::from([
+            ("id".to_string(), Value::String("2-3".to_string())),
+            ("form".to_string(), Value::String("del".to_string()))])]));
+        assert_eq!(doc["misc"], Layer::LS(vec!["_", "_", "_", "SpaceAfter=No", "_"]
+            .into_iter().map(|s| s.to_string()).collect()));
+        assert_eq!(doc["_comments"], Layer::MetaLayer(vec![
+            HashMap::from([("newdoc".to_string(), Value::Bool(true))]),
+            HashMap::from([("sent_id".to_string(), Value::String("1".to_string()))])]));
+        let doc = corpus.get_doc_by_id(&corpus.get_docs()[1]).unwrap();
+        assert_eq!(doc["xpos"], Layer::LS(vec!["UH".to_string()]));
+        assert_eq!(doc["feats"], Layer::LS(vec!["_".to_string()]));
+        assert_eq!(doc.get("_comments"), None);
+        assert_eq!(doc.get("_empty_nodes"), None);
+    }
+
+    #[test]
+    fn test_read_conllu_columns() {
+        let data = "1\tTá\tbí\tVERB\t_\t_\t0\troot\t_\t_
+2\tsé\tsé\tPRON\t_\t_\t1\tnsubj\t_
+";
+        let mut corpus = SimpleCorpus::new();
+        let err = read_conllu(data.as_bytes(), &mut corpus, &ConlluConfig::new()).unwrap_err();
+        assert!(matches!(err, SerializeError::Teanga(TeangaError::ModelError(ref m)) if m.starts_with("Line 2:")));
+    }
+
+    #[test]
+    fn test_write_conllu() {
+        // The first two sentences of the UD English-EWT development set
+        let data = "# newdoc id = weblog-blogspot.com_nominations_20041117172713_ENG_20041117_172713
+# sent_id = weblog-blogspot.com_nominations_20041117172713_ENG_20041117_172713-0001
+# newpar id = weblog-blogspot.com_nominations_20041117172713_ENG_20041117_172713-p0001
+# text = From the AP comes this story :
+1\tFrom\tfrom\tADP\tIN\t_\t3\tcase\t3:case\t_
+2\tthe\tthe\tDET\tDT\tDefinite=Def|PronType=Art\t3\tdet\t3:det\t_
+3\tAP\tAP\tPROPN\tNNP\tNumber=Sing\t4\tobl\t4:obl:from\t_
+4\tcomes\tcome\tVERB\tVBZ\tMood=Ind|Number=Sing|Person=3|Tense=Pres|VerbForm=Fin\t0\troot\t0:root\t_
+5\tthis\tthis\tDET\tDT\tNumber=Sing|PronType=Dem\t6\tdet\t6:det\t_
+6\tstory\tstory\tNOUN\tNN\tNumber=Sing\t4\tnsubj\t4:nsubj\t_
+7\t:\t:\tPUNCT\t:\t_\t4\tpunct\t4:punct\t_
+
+# sent_id = weblog-blogspot.com_nominations_20041117172713_ENG_20041117_172713-0002
+# newpar id = weblog-blogspot.com_nominations_20041117172713_ENG_20041117_172713-p0002
+# text = President Bush on Tuesday nominated two individuals to replace retiring jurists on federal courts in the Washington area.
+1\tPresident\tPresident\tPROPN\tNNP\tNumber=Sing\t5\tnsubj\t5:nsubj\t_
+2\tBush\tBush\tPROPN\tNNP\tNumber=Sing\t1\tflat\t1:flat\t_
+3\ton\ton\tADP\tIN\t_\t4\tcase\t4:case\t_
+4\tTuesday\tTuesday\tPROPN\tNNP\tNumber=Sing\t5\tobl\t5:obl:on\t_
+5\tnominated\tnominate\tVERB\tVBD\tMood=Ind|Number=Sing|Person=3|Tense=Past|VerbForm=Fin\t0\troot\t0:root\t_
+6\ttwo\ttwo\tNUM\tCD\tNumForm=Word|NumType=Card\t7\tnummod\t7:nummod\t_
+7\tindividuals\tindividual\tNOUN\tNNS\tNumber=Plur\t5\tobj\t5:obj\t_
+8\tto\tto\tPART\tTO\t_\t9\tmark\t9:mark\t_
+9\treplace\treplace\tVERB\tVB\tVerbForm=Inf\t5\tadvcl\t5:advcl:to\t_
+10\tretiring\tretire\tVERB\tVBG\tVerbForm=Ger\t11\tamod\t11:amod\t_
+11\tjurists\tjurist\tNOUN\tNNS\tNumber=Plur\t9\tobj\t9:obj\t_
+12\ton\ton\tADP\tIN\t_\t14\tcase\t14:case\t_
+13\tfederal\tfederal\tADJ\tJJ\tDegree=Pos\t14\tamod\t14:amod\t_
+14\tcourts\tcourt\tNOUN\tNNS\tNumber=Plur\t11\tnmod\t11:nmod:on\t_
+15\tin\tin\tADP\tIN\t_\t18\tcase\t18:case\t_
+16\tthe\tthe\tDET\tDT\tDefinite=Def|PronType=Art\t18\tdet\t18:det\t_
+17\tWashington\tWashington\tPROPN\tNNP\tNumber=Sing\t18\tcompound\t18:compound\t_
+18\tarea\tarea\tNOUN\tNN\tNumber=Sing\t14\tnmod\t14:nmod:in\tSpaceAfter=No
+19\t.\t.\tPUNCT\t.\t_\t5\tpunct\t5:punct\t_
+
+";
+        let mut corpus = SimpleCorpus::new();
+        read_conllu(data.as_bytes(), &mut corpus, &ConlluConfig::new()).unwrap();
+        let mut out = Vec::new();
+        write_conllu(&mut out, &corpus, &ConlluWriteConfig::new()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), data);
+    }
+
+    #[test]
+    fn test_write_conllu_multiword() {
+        let data = "# text = Vino del mar.
+1\tVino\tvenir\tVERB\t_\t_\t0\troot\t_\t_
+2-3\tdel\t_\t_\t_\t_\t_\t_\t_\t_
+2\tde\tde\tADP\t_\t_\t4\tcase\t_\t_
+3\tel\tel\tDET\t_\t_\t4\tdet\t_\t_
+4\tmar\tmar\tNOUN\t_\t_\t1\tobl\t_\tSpaceAfter=No
+5\t.\t.\tPUNCT\t_\t_\t1\tpunct\t_\t_
+
+";
+        let mut corpus = SimpleCorpus::new();
+        read_conllu(data.as_bytes(), &mut corpus, &ConlluConfig::new()).unwrap();
+        let mut out = Vec::new();
+        write_conllu(&mut out, &corpus, &ConlluWriteConfig::new()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), data);
+        let mut out = Vec::new();
+        write_conllu(&mut out, &corpus, &ConlluWriteConfig::new().with_misc("none")).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), data);
+    }
+
+    #[test]
+    fn test_write_conllu_sentences() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("sentences").base("text").layer_type(LayerType::div).add().unwrap();
+        corpus.build_layer("pos").base("words").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        corpus.build_doc().layer("text", "Dia duit. Conas atá tú?").unwrap()
+            .layer("words", vec![(0, 3), (4, 8), (8, 9), (10, 15), (16, 20), (21, 24), (24, 25)]).unwrap()
+            .layer("sentences", vec![0, 10]).unwrap()
+            .layer("pos", vec!["NOUN", "NOUN", "PUNCT", "ADV", "VERB", "PRON", "PUNCT"]).unwrap()
+            .add().unwrap();
+        let mut out = Vec::new();
+        write_conllu(&mut out, &corpus, &ConlluWriteConfig::new()
+            .with_tokens("words").with_upos("pos")).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines : Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "# text = Dia duit.");
+        assert_eq!(lines[2], "2\tduit\t_\tNOUN\t_\t_\t_\t_\t_\tSpaceAfter=No");
+        assert_eq!(lines[3], "3\t.\t_\tPUNCT\t_\t_\t_\t_\t_\t_");
+        assert_eq!(lines[5], "# text = Conas atá tú?");
+        assert_eq!(lines[6], "1\tConas\t_\tADV\t_\t_\t_\t_\t_\t_");
+    }
+
+    #[test]
+    fn test_open_maybe_compressed() {
+        let data = "_meta:\n    text:\n        type: characters\nKjco:\n    text: This is a document.\n";
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join("corpus.yaml");
+        std::fs::write(&plain, data).unwrap();
+        #[cfg_attr(not(any(feature = "gzip", feature = "zstd")), allow(unused_mut))]
+        let mut paths = vec![plain];
+        #[cfg(feature = "gzip")]
+        {
+            let gzip = dir.path().join("corpus.yaml.gz");
+            let mut encoder = flate2::write::GzEncoder::new(File::create(&gzip).unwrap(),
+                flate2::Compression::default());
+            encoder.write_all(data.as_bytes()).unwrap();
+            encoder.finish().unwrap();
+            paths.push(gzip);
+        }
+        #[cfg(feature = "zstd")]
+        {
+            let zstd = dir.path().join("corpus.dat");
+            std::fs::write(&zstd, zstd::encode_all(data.as_bytes(), 0).unwrap()).unwrap();
+            paths.push(zstd);
+        }
+        for path in paths {
+            let mut corpus = SimpleCorpus::new();
+            read_yaml(open_maybe_compressed(&path).unwrap(), &mut corpus).unwrap();
+            assert_eq!(corpus.get_docs(), vec!["Kjco"]);
+        }
+    }
+
+    #[test]
+    fn test_write_json_streaming() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_doc().layer("text", "Tá an aimsir go maith.").unwrap()
+            .layer("words", vec![(0, 3), (4, 6), (7, 13), (14, 16), (17, 22), (22, 23)]).unwrap()
+            .add().unwrap();
+        corpus.build_doc().layer("text", "\"Quoted\" text").unwrap()
+            .add().unwrap();
+        let mut expected = Vec::new();
+        write_json(&mut expected, &corpus).unwrap();
+        let mut out = Vec::new();
+        write_json_streaming(&mut out, corpus.get_meta(),
+            corpus.get_order().iter().map(|id| corpus.get_doc_by_id(id).map(|doc| (id.clone(), doc)))).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), String::from_utf8(expected).unwrap());
+        let mut out = Vec::new();
+        write_json_streaming(&mut out, &HashMap::new(), std::iter::empty()).unwrap();
+        assert_eq!(out, b"{\"_meta\":{}}");
+    }
 
     #[test]
     fn test_deserialize_yaml() {
@@ -354,6 +1896,188 @@ ecWc:
             "_meta:\n    text:\n        type: characters\n    tokens:\n        type: span\n        base: text\necWc:\n    text: This is an example\n    tokens: [[0,4],[5,7],[8,10],[11,18]]\n");
     }
  
+    #[test]
+    fn test_pretty_yaml_config() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("tokens").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_doc().layer("text", "This is an example").unwrap()
+            .layer("tokens", vec![(0, 4), (5, 7), (8, 10), (11, 18)]).unwrap()
+            .add().unwrap();
+        let mut out = Vec::new();
+        pretty_yaml_serialize_with_config(&corpus, &mut out,
+            &YamlWriteConfig::new().with_indent(2).with_trailing_newline(false)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+            "_meta:\n  text:\n    type: characters\n  tokens:\n    type: span\n    base: text\necWc:\n  text: This is an example\n  tokens: [[0,4],[5,7],[8,10],[11,18]]");
+    }
+
+    #[test]
+    fn test_jsonl_grouped() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("dep").base("words").layer_type(LayerType::seq)
+            .data(DataType::Link).add().unwrap();
+        let data = r#"{"doc": "a", "text": "I ran", "words": [[0, 1], [2, 5]], "dep": [1, 1]}
+{"doc": "a", "text": "You sat", "words": [[0, 3], [4, 7]], "dep": [1, 1]}
+{"doc": "b", "text": "Hi", "words": [[0, 2]], "dep": [0]}
+"#;
+        read_jsonl_grouped(data.as_bytes(), &mut corpus, "doc").unwrap();
+        assert_eq!(corpus.get_docs().len(), 2);
+        let doc = corpus.get_doc_by_id(&corpus.get_docs()[0]).unwrap();
+        assert_eq!(doc.text("words", corpus.get_meta()).unwrap(), vec!["I", "ran", "You", "sat"]);
+        assert_eq!(doc.get("dep"), Some(&Layer::L1(vec![1, 1, 3, 3])));
+        let doc = corpus.get_doc_by_id(&corpus.get_docs()[1]).unwrap();
+        assert_eq!(doc.text("text", corpus.get_meta()).unwrap(), vec!["Hi"]);
+        let data = r#"{"doc": "c", "text": "I ran", "words": [[0, 1], [2, 5]]}
+{"doc": "c", "text": "You sat", "words": [[0, 3], [4, 7]], "dep": [1, 1]}
+"#;
+        assert!(read_jsonl_grouped(data.as_bytes(), &mut corpus, "doc").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "tar")]
+    fn test_read_jsonl_tar() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut add = |name : &str, data : &str| {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, data.as_bytes()).unwrap();
+        };
+        add("shards/part1.jsonl", "{\"text\": \"First document\"}\n");
+        add("meta.yaml", "_meta:\n  text:\n    type: characters\n");
+        add("shards/part2.jsonl", "{\"text\": \"Second document\"}\n{\"text\": \"Third document\"}\n");
+        add("README", "Not a corpus file");
+        let data = builder.into_inner().unwrap();
+        let mut corpus = SimpleCorpus::new();
+        read_jsonl_tar(data.as_slice(), &mut corpus).unwrap();
+        assert_eq!(corpus.get_docs().len(), 3);
+        let doc = corpus.get_doc_by_id(&corpus.get_docs()[0]).unwrap();
+        assert_eq!(doc.text("text", corpus.get_meta()).unwrap(), vec!["First document"]);
+    }
+
+    #[test]
+    fn test_write_spacy_json() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("pos").base("words").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        corpus.build_layer("ner").base("text").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        corpus.build_doc()
+            .layer("text", "Éire abú").unwrap()
+            .layer("words", vec![(0, 5), (6, 10)]).unwrap()
+            .layer("pos", vec!["PROPN", "ADV"]).unwrap()
+            .layer("ner", vec![(0, 5, "LOC")]).unwrap()
+            .add().unwrap();
+        let mut out = Vec::new();
+        write_spacy_json(&mut out, &corpus, &SpacyMapping::new("text")
+            .tokens("words").tags("pos").entities("ner")).unwrap();
+        let json : serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json, serde_json::json!([{
+            "text": "Éire abú",
+            "tokens": [
+                { "id": 0, "start": 0, "end": 4, "tag": "PROPN" },
+                { "id": 1, "start": 5, "end": 8, "tag": "ADV" }
+            ],
+            "entities": [[0, 4, "LOC"]]
+        }]));
+    }
+
+    #[test]
+    fn test_write_vrt() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("sentences").base("text").layer_type(LayerType::div).add().unwrap();
+        corpus.build_layer("pos").base("words").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        corpus.build_layer("ner").base("words").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        let id = corpus.build_doc()
+            .layer("text", "Tá Seán anseo. Slán!").unwrap()
+            .layer("words", vec![(0, 3), (4, 9), (10, 15), (15, 16), (17, 22), (22, 23)]).unwrap()
+            .layer("sentences", vec![0, 17]).unwrap()
+            .layer("pos", vec!["VERB", "PROPN", "ADV", "PUNCT", "INTJ", "PUNCT"]).unwrap()
+            .layer("ner", vec![(1, 2, "PER")]).unwrap()
+            .add().unwrap();
+        let mut out = Vec::new();
+        write_vrt(&mut out, &corpus, &VrtConfig::new("words")
+            .attribute("pos").attribute("lemma").structure("sentences").structure("ner")).unwrap_err();
+        out.clear();
+        write_vrt(&mut out, &corpus, &VrtConfig::new("words")
+            .attribute("pos").structure("sentences").structure("ner")).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("<text id=\"{}\">
+<sentences>
+Tá\tVERB
+<ner value=\"PER\">
+Seán\tPROPN
+</ner>
+anseo\tADV
+.\tPUNCT
+</sentences>
+<sentences>
+Slán\tINTJ
+!\tPUNCT
+</sentences>
+</text>
+", id));
+    }
+
+    #[test]
+    fn test_write_layer_tsv() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("pos").base("words").layer_type(LayerType::seq)
+            .data(DataType::String).add().unwrap();
+        corpus.build_layer("ner").base("words").layer_type(LayerType::span)
+            .data(DataType::String).add().unwrap();
+        let id1 = corpus.build_doc()
+            .layer("text", "Tá Seán Ó Sé\tanseo").unwrap()
+            .layer("words", vec![(0, 3), (4, 9), (10, 12), (13, 22), (17, 22)]).unwrap()
+            .layer("pos", vec!["VERB", "PROPN", "PROPN"]).unwrap()
+            .layer("ner", vec![(1, 3, "PER")]).unwrap()
+            .add().unwrap();
+        let id2 = corpus.build_doc()
+            .layer("text", "Slán").unwrap()
+            .layer("words", vec![(0, 5)]).unwrap()
+            .add().unwrap();
+        let mut out = Vec::new();
+        write_layer_tsv(&mut out, &corpus, "words", &["pos", "ner"]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("# {}
+Tá\tVERB\t
+Seán\tPROPN\tPER
+Ó\tPROPN\tPER
+\"Sé\tanseo\"\t\t
+anseo\t\t
+
+# {}
+Slán\t\t
+", id1, id2));
+        assert!(write_layer_tsv(Vec::new(), &corpus, "words", &["lemma"]).is_err());
+    }
+
+    #[test]
+    fn test_jsonl_indexed() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        let id1 = corpus.build_doc().layer("text", "The first document").unwrap().add().unwrap();
+        let id2 = corpus.build_doc().layer("text", "The second document").unwrap().add().unwrap();
+        let mut out = Vec::new();
+        let mut index = Vec::new();
+        write_jsonl_indexed(&mut out, &mut index, &corpus).unwrap();
+        let index = read_jsonl_index(index.as_slice()).unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index[0], (id1.clone(), 0));
+        assert_eq!(index[1].0, id2);
+        let doc = read_jsonl_at(std::io::Cursor::new(&out), index[1].1, &corpus).unwrap();
+        assert_eq!(doc, corpus.get_doc_by_id(&id2).unwrap());
+    }
+
     #[test]
     fn test_1() {
         let mut corpus = SimpleCorpus::new();
@@ -368,8 +2092,78 @@ ecWc:
     type: characters
   document:
     type: div
-    base: characters".to_string();
+    base: text".to_string();
  
         read_yaml_meta(data.as_bytes(), &mut SimpleCorpus::new()).unwrap();
     }
+
+    #[test]
+    fn test_yaml_anchors() {
+        let data = "_meta:
+  text: &chars
+    type: characters
+  title: *chars
+  words: &span
+    type: span
+    base: text
+  chunks: *span
+  tokens:
+    <<: *span
+    data: string
+Kjco:
+  text: This is a document.
+  words: &w [[0, 4], [5, 7]]
+  chunks: *w
+  tokens: [[0, 4, DET], [5, 7, VERB]]
+";
+        let mut corpus = SimpleCorpus::new();
+        read_yaml(data.as_bytes(), &mut corpus).unwrap();
+        let meta = corpus.get_meta();
+        assert_eq!(meta["title"].layer_type, LayerType::characters);
+        assert_eq!(meta["tokens"].layer_type, LayerType::span);
+        assert_eq!(meta["tokens"].base, Some("text".to_string()));
+        assert_eq!(meta["tokens"].data, Some(DataType::String));
+        let doc = corpus.get_doc_by_id("Kjco").unwrap();
+        assert_eq!(doc.get("words"), doc.get("chunks"));
+    }
+
+    #[test]
+    fn test_read_yaml_append() {
+        let mut first = SimpleCorpus::new();
+        first.build_layer("text").add().unwrap();
+        first.build_doc().layer("text", "Aon").unwrap().add().unwrap();
+        first.build_doc().layer("text", "Dó").unwrap().add().unwrap();
+        let mut second = SimpleCorpus::new();
+        second.build_layer("text").add().unwrap();
+        second.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        second.build_doc().layer("text", "Dó").unwrap().add().unwrap();
+        second.build_doc().layer("text", "Trí").unwrap().add().unwrap();
+        let mut first_yaml = Vec::new();
+        write_yaml(&mut first_yaml, &first).unwrap();
+        let mut second_yaml = Vec::new();
+        write_yaml(&mut second_yaml, &second).unwrap();
+
+        let mut corpus = SimpleCorpus::new();
+        read_yaml(first_yaml.as_slice(), &mut corpus).unwrap();
+        read_yaml_with_mode(second_yaml.as_slice(), &mut corpus, LoadMode::Append).unwrap();
+        assert!(corpus.get_meta().contains_key("words"));
+        let texts : Vec<String> = corpus.iter_docs().map(|doc|
+            doc.unwrap().text("text", corpus.get_meta()).unwrap().join("")).collect();
+        assert_eq!(texts, vec!["Aon", "Dó", "Trí"]);
+
+        read_yaml_with_mode(first_yaml.as_slice(), &mut corpus, LoadMode::Replace).unwrap();
+        assert_eq!(corpus.get_order(), first.get_order());
+        assert!(!corpus.get_meta().contains_key("words"));
+
+        let conflict = "_meta:\n  text:\n    type: div\n    base: words\n  words:\n    type: characters\n";
+        let err = read_yaml_with_mode(conflict.as_bytes(), &mut corpus, LoadMode::Append).unwrap_err();
+        assert!(err.to_string().contains("different definition"));
+
+        let invalid = "_meta:\n  text:\n    type: characters\nAbcd:\n  words: [[0, 4]]\n";
+        assert!(read_yaml_with_mode(invalid.as_bytes(), &mut corpus, LoadMode::Replace).is_err());
+        assert_eq!(corpus.get_order(), first.get_order());
+
+        read_yaml(second_yaml.as_slice(), &mut corpus).unwrap();
+        assert_eq!(corpus.get_docs().len(), 3);
+    }
 }
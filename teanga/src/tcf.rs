@@ -1,19 +1,32 @@
 //! Teanga Compressed Format
+//!
+//! TCF is the compressed binary format of Teanga and is the format to use for
+//! compact files. Files are read in order with `read_tcf` or `read_tcf_iter`,
+//! or by document ID with `TCFReader`, and `TCFCorpus` reads a file as a
+//! read-only corpus without loading its documents into memory. Readers accept
+//! files of this and earlier versions (see `TCF_VERSION`), so there is
+//! nothing to migrate when the version changes.
 use thiserror::Error;
 
+mod corpus;
 mod data;
 mod index;
 mod read;
 mod layer;
+mod stats;
 mod string;
 mod tcf_index;
 mod type_index;
 mod write;
 
-pub use write::{write_tcf, write_tcf_with_config, write_tcf_header, write_tcf_config, write_tcf_header_compression, write_tcf_doc, doc_content_to_bytes, TCFWriteError};
-pub use read::{read_tcf, read_tcf_header, read_tcf_doc, bytes_to_doc, TCFReadError};
+pub use write::{write_tcf, write_tcf_with_config, write_tcf_header, write_tcf_header_with_config, write_tcf_config, write_tcf_header_compression, write_tcf_doc, write_tcf_doc_index, doc_content_to_bytes, CountingWriter, TCFWriteError};
+pub use read::{read_tcf, read_tcf_header, read_tcf_doc, read_tcf_iter, TCFDocIter, TCFReader, bytes_to_doc, TCFReadError};
+pub use corpus::TCFCorpus;
 pub use index::{Index, IndexResult};
-pub use string::{StringCompression, SupportedStringCompression, StringCompressionError, NoCompression, SmazCompression, ShocoCompression};
+pub use stats::{tcf_stats, TCFStats};
+pub use string::{StringCompression, SupportedStringCompression, StringCompressionError, NoCompression, SmazCompression, ShocoCompression, train_shoco_model};
+#[cfg(feature = "zstd")]
+pub use string::ZstdCompression;
 
 /// A TCF Result type
 pub type TCFResult<T> = Result<T, TCFError>;
@@ -42,19 +55,41 @@ pub enum TCFError {
     /// An enum value was invalid
     #[error("Invalid enum value: {0}")]
     InvalidEnumValue(String),
+    /// A string index did not refer to a string in the index
+    #[error("Invalid string index: {0}")]
+    InvalidStringIndex(u32),
+    /// An enum index was out of range for the values of the layer
+    #[error("Invalid enum index: {0}")]
+    InvalidEnumIndex(u32),
+    /// The layer has link data, which is not stored as TCF data
+    #[error("Link data type not supported")]
+    LinkDataNotSupported,
+    /// The layer has no data type
+    #[error("No data type specified")]
+    NoDataType,
+    /// The data read does not match the data type of the layer
+    #[error("LayerDesc data type does not match TCFData type")]
+    DataTypeMismatch,
+    /// A string index is too large to be written
+    #[error("Index too large: {0}")]
+    IndexTooLarge(u32),
 }
 
 /// Configuration for TCF 
 #[derive(Debug, Clone, PartialEq)]
 pub struct TCFConfig {
     /// The compression to use for strings
-    pub string_compression : StringCompressionMethod
+    pub string_compression : StringCompressionMethod,
+    /// Whether to write an index of document offsets at the end of the file,
+    /// allowing documents to be read by ID with `TCFReader`
+    pub doc_index : bool
 }
 
 impl Default for TCFConfig {
     fn default() -> Self {
         TCFConfig {
-            string_compression : StringCompressionMethod::Smaz
+            string_compression : StringCompressionMethod::Smaz,
+            doc_index : true
         }
     }
 }
@@ -69,7 +104,8 @@ impl TCFConfig {
     /// A new TCF configuration
     pub fn new() -> TCFConfig {
         TCFConfig {
-            string_compression : StringCompressionMethod::Smaz
+            string_compression : StringCompressionMethod::Smaz,
+            doc_index : true
         }
     }
 
@@ -77,10 +113,19 @@ impl TCFConfig {
         self.string_compression = sc;
         self
     }
+
+    /// Set whether to write an index of document offsets at the end of the
+    /// file
+    pub fn with_doc_index(mut self, doc_index : bool) -> TCFConfig {
+        self.doc_index = doc_index;
+        self
+    }
 }
 
-/// The compression method for strings
+/// The compression method for strings. More methods are available with the
+/// `zstd` feature, so matches must have a wildcard arm
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum StringCompressionMethod {
     /// No compression
     None,
@@ -89,8 +134,20 @@ pub enum StringCompressionMethod {
     /// Use Shoco with default model
     ShocoDefault,
     /// Build a new Shoco model
-    GenerateShocoModel(usize)
+    GenerateShocoModel(usize),
+    /// Try each method on a sample of this many bytes of the corpus and use
+    /// the one giving the smallest output
+    Auto(usize),
+    /// Use Zstandard without a dictionary
+    #[cfg(feature = "zstd")]
+    Zstd,
+    /// Use Zstandard with a dictionary trained on this many bytes of the
+    /// corpus. The dictionary is stored in the header
+    #[cfg(feature = "zstd")]
+    GenerateZstdDictionary(usize)
 }
 
-/// The TCF version for binary compatibility
-pub static TCF_VERSION : u16 = 1;
+/// The TCF version for binary compatibility. Version 2 added the optional
+/// document index at the end of the file, so files written without one are
+/// marked as version 1
+pub static TCF_VERSION : u16 = 2;
@@ -0,0 +1,147 @@
+//! A read-only corpus backed by a TCF file
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use crate::{Corpus, DataType, Document, DocumentContent, IntoLayer, Layer, LayerDesc, LayerType, TeangaError, TeangaResult, Value};
+use crate::tcf::read::{read_tcf_iter, ReadDocError, TCFReader, TCFReadError};
+
+/// A corpus that reads its documents from a TCF file as they are needed,
+/// so that only the metadata and the document index are kept in memory.
+/// Each call to `iter_docs` streams the documents from the file, and
+/// `get_doc_by_id` seeks to the document using the document index.
+///
+/// The corpus cannot be changed and all writes return
+/// `TeangaError::TCFMutError`. The file must have a document index (see
+/// `TCFConfig::with_doc_index`)
+pub struct TCFCorpus {
+    path : PathBuf,
+    reader : Mutex<TCFReader>,
+    meta : HashMap<String, LayerDesc>,
+    order : Vec<String>
+}
+
+impl TCFCorpus {
+    /// Open a TCF file as a corpus
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the TCF file
+    pub fn open<P : AsRef<Path>>(path : P) -> Result<TCFCorpus, TCFReadError> {
+        let reader = TCFReader::open(path.as_ref())?;
+        Ok(TCFCorpus {
+            path: path.as_ref().to_path_buf(),
+            meta: reader.meta().clone(),
+            order: reader.ids().to_vec(),
+            reader: Mutex::new(reader)
+        })
+    }
+}
+
+impl Corpus for TCFCorpus {
+    type LayerStorage = Layer;
+    type Content = Document;
+
+    fn add_layer_meta(&mut self, _name: String, _layer_type: LayerType,
+        _base: Option<String>, _data: Option<DataType>, _link_types: Option<Vec<String>>,
+        _target: Option<String>, _default: Option<Layer>,
+        _meta: HashMap<String, Value>) -> TeangaResult<()> {
+        Err(TeangaError::TCFMutError)
+    }
+
+    fn add_doc<D : IntoLayer, DC : DocumentContent<D>>(&mut self, _content : DC) -> TeangaResult<String> {
+        Err(TeangaError::TCFMutError)
+    }
+
+    fn update_doc<D : IntoLayer, DC : DocumentContent<D>>(&mut self, _id : &str, _content : DC) -> TeangaResult<String> {
+        Err(TeangaError::TCFMutError)
+    }
+
+    fn remove_doc(&mut self, _id : &str) -> TeangaResult<()> {
+        Err(TeangaError::TCFMutError)
+    }
+
+    fn get_doc_by_id(&self, id : &str) -> TeangaResult<Document> {
+        let mut reader = self.reader.lock()
+            .map_err(|_| TeangaError::ModelError("TCF reader lock poisoned".to_string()))?;
+        reader.get(id).map_err(into_teanga_error)
+    }
+
+    fn get_docs(&self) -> Vec<String> {
+        self.order.clone()
+    }
+
+    fn get_meta(&self) -> &HashMap<String, LayerDesc> {
+        &self.meta
+    }
+
+    fn get_order(&self) -> &Vec<String> {
+        &self.order
+    }
+
+    fn iter_docs<'a>(&'a self) -> Box<dyn Iterator<Item=TeangaResult<Document>> + 'a> {
+        Box::new(self.iter_doc_ids().map(|r| r.map(|(_, doc)| doc)))
+    }
+
+    fn iter_doc_ids<'a>(&'a self) -> Box<dyn Iterator<Item=TeangaResult<(String, Document)>> + 'a> {
+        let iter = File::open(&self.path)
+            .map_err(TCFReadError::from)
+            .and_then(|file| read_tcf_iter(BufReader::new(file)));
+        match iter {
+            Ok(iter) => Box::new(iter.map(|r| r.map_err(into_teanga_error))),
+            Err(e) => Box::new(std::iter::once(Err(into_teanga_error(e))))
+        }
+    }
+}
+
+fn into_teanga_error(e : TCFReadError) -> TeangaError {
+    match e {
+        TCFReadError::TeangaError(e) => e,
+        TCFReadError::TCFError(ReadDocError::TeangaError(e)) => e,
+        TCFReadError::TCFError(ReadDocError::TCFError(e)) => TeangaError::TCFReadError(e),
+        e => TeangaError::ModelError(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimpleCorpus;
+    use crate::match_condition::AnyText;
+    use crate::tcf::write_tcf;
+
+    #[test]
+    fn test_tcf_corpus() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").layer_type(LayerType::span).base("text").add().unwrap();
+        for text in ["bhí sé fuar", "tá sé te", "bhí sé fuar"] {
+            let words : Vec<(u32, u32)> = text.split(' ').scan(0, |start, w| {
+                let span = (*start, *start + w.len() as u32);
+                *start += w.len() as u32 + 1;
+                Some(span)
+            }).collect();
+            corpus.build_doc()
+                .layer("text", text).unwrap()
+                .layer("words", words).unwrap()
+                .add().unwrap();
+        }
+        let file = tempfile::NamedTempFile::new().unwrap();
+        write_tcf(&mut file.reopen().unwrap(), &corpus).unwrap();
+        let mut tcf_corpus = TCFCorpus::open(file.path()).unwrap();
+        assert_eq!(tcf_corpus.get_order(), corpus.get_order());
+        assert_eq!(tcf_corpus.get_meta(), corpus.get_meta());
+        assert!(tcf_corpus.content_eq(&corpus));
+        let docs : Vec<(String, Document)> = tcf_corpus.iter_doc_ids().map(|r| r.unwrap()).collect();
+        assert_eq!(docs, corpus.iter_doc_ids().map(|r| r.unwrap()).collect::<Vec<_>>());
+        let id = &corpus.get_order()[1];
+        assert_eq!(tcf_corpus.get_doc_by_id(id).unwrap(), corpus.get_doc_by_id(id).unwrap());
+        assert!(matches!(tcf_corpus.get_doc_by_id("nope"), Err(TeangaError::DocumentNotFoundError)));
+        assert_eq!(tcf_corpus.text_freq("words", AnyText).unwrap()["sé"], 3);
+        assert!(matches!(tcf_corpus.build_doc().layer("text", "níl").unwrap().add(),
+            Err(TeangaError::TCFMutError)));
+        assert!(matches!(tcf_corpus.remove_doc(id), Err(TeangaError::TCFMutError)));
+        assert_eq!(tcf_corpus.len(), 3);
+    }
+}
@@ -34,46 +34,42 @@ impl TCFData {
                 }
                 Ok(TCFData::Enum(v))
             }
-            Some(DataType::Link) => {
-                panic!("Link data type not supported");
-            }
-            None => {
-                panic!("No data type specified");
-            }
+            Some(DataType::Link) => Err(TCFError::LinkDataNotSupported),
+            None => Err(TCFError::NoDataType)
         }
     }
 
-    pub fn to_vec(&self, index : &Index, ld : &LayerDesc) -> Vec<String> {
+    pub fn to_vec(&self, index : &Index, ld : &LayerDesc) -> TCFResult<Vec<String>> {
         match self {
             TCFData::String(v) => {
                 v.iter().map(|i| match i {
                     IndexResult::String(s) => {
                         index.idx(s);
-                        s.clone()
+                        Ok(s.clone())
                     }
-                    IndexResult::Index(i) => index.str(*i).unwrap()
+                    IndexResult::Index(i) => index.str(*i)
+                        .ok_or(TCFError::InvalidStringIndex(*i))
                 }).collect()
             }
             TCFData::Enum(v) => {
                 match ld.data {
                     Some(DataType::Enum(ref enum_vals)) => {
-                        v.iter().map(|i| enum_vals[*i as usize].clone()).collect()
-                    }
-                    _ => {
-                        panic!("LayerDesc data type does not match TCFData type");
+                        v.iter().map(|i| enum_vals.get(*i as usize).cloned()
+                            .ok_or(TCFError::InvalidEnumIndex(*i))).collect()
                     }
+                    _ => Err(TCFError::DataTypeMismatch)
                 }
             }
         }
     }
 
-    pub fn into_bytes<C : StringCompression>(self, compress : &C) -> Vec<u8> {
+    pub fn into_bytes<C : StringCompression>(self, compress : &C) -> TCFResult<Vec<u8>> {
         match self {
             TCFData::String(v) => {
                 index_results_to_bytes(&v, compress)
             }
             TCFData::Enum(v) => {
-                TCFIndex::from_vec(&v).into_bytes()
+                Ok(TCFIndex::from_vec(&v).into_bytes())
             }
         }
     }
@@ -88,12 +84,8 @@ impl TCFData {
                 let (v, len) = TCFIndex::from_bytes(data)?;
                 Ok((TCFData::Enum(v.to_vec()), len))
             }
-            Some(DataType::Link) => {
-                panic!("Link data type not supported");
-            }
-            None => {
-                panic!("No data type specified");
-            }
+            Some(DataType::Link) => Err(TCFError::LinkDataNotSupported),
+            None => Err(TCFError::NoDataType)
         }
     }
 
@@ -107,19 +99,15 @@ impl TCFData {
                 let v = TCFIndex::from_reader(input)?;
                 Ok(TCFData::Enum(v.to_vec()))
             }
-            Some(DataType::Link) => {
-                panic!("Link data type not supported");
-            }
-            None => {
-                panic!("No data type specified");
-            }
+            Some(DataType::Link) => Err(TCFError::LinkDataNotSupported),
+            None => Err(TCFError::NoDataType)
         }
     }
 
 }
 
 
-fn index_results_to_bytes<C : StringCompression>(ir : &Vec<IndexResult>, compress : &C) -> Vec<u8> {
+fn index_results_to_bytes<C : StringCompression>(ir : &Vec<IndexResult>, compress : &C) -> TCFResult<Vec<u8>> {
     let mut d = Vec::new();
     let mut type_index = TypeIndex::new();
     for i in ir {
@@ -127,13 +115,13 @@ fn index_results_to_bytes<C : StringCompression>(ir : &Vec<IndexResult>, compres
             IndexResult::Index(idx) => {
                 type_index.append(false);
                 if *idx >= 2147482648 {
-                    panic!("Index too large");
+                    return Err(TCFError::IndexTooLarge(*idx));
                 }
                 d.extend(u32_to_varbytes(*idx));
             }
             IndexResult::String(s) => {
                 type_index.append(true);
-                let b = compress.compress(s);
+                let b = compress.compress(s)?;
                 d.extend(u32_to_varbytes(b.len() as u32));
                 d.extend(b);
             }
@@ -143,7 +131,7 @@ fn index_results_to_bytes<C : StringCompression>(ir : &Vec<IndexResult>, compres
     d2.extend(u32_to_varbytes(ir.len() as u32));
     d2.extend(type_index.to_bytes());
     d2.extend(d);
-    d2
+    Ok(d2)
 }
 
 fn bytes_to_index_results<S : StringCompression>(data : &[u8], s : &S) -> TCFResult<(Vec<IndexResult>, usize)> {
@@ -265,7 +253,7 @@ mod tests {
                 ..LayerDesc::default()
             }, &mut index).unwrap();
         let c = crate::tcf::string::SmazCompression;
-        let bytes = data.clone().into_bytes(&c);
+        let bytes = data.clone().into_bytes(&c).unwrap();
         let (data2, _) = TCFData::from_bytes(&bytes, &LayerDesc {
             data: Some(DataType::String),
             ..LayerDesc::default()
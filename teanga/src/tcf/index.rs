@@ -153,6 +153,18 @@ impl Index {
         })
     }
 
+    /// Remove the strings added after the first `len` strings, so that the
+    /// strings added by writes that are rolled back are not kept
+    pub(crate) fn truncate(&self, len : usize) {
+        let mut vec = self.vec.write().unwrap();
+        if vec.len() > len {
+            let mut map = self.map.write().unwrap();
+            for s in vec.drain(len..) {
+                map.remove(&s);
+            }
+        }
+    }
+
     /// Freeze the index. Future calls will not update the index
     pub fn freeze(&self) -> Index {
         Index {
@@ -1,6 +1,6 @@
 /// Teanga Compressed Format
 use crate::{Layer, Value, LayerDesc};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use ciborium::{into_writer, from_reader};
 use std::io::BufRead;
 
@@ -13,6 +13,8 @@ use crate::tcf::string::StringCompression;
 
 
 pub static TCF_EMPTY_LAYER : u8 = 0b1111_1111;
+/// Marks the start of the document index after the last document
+pub static TCF_DOC_INDEX : u8 = 0b1111_1110;
 
 pub enum TCFLayer {
     Characters(Vec<u8>),
@@ -29,7 +31,7 @@ pub enum TCFLayer {
 impl TCFLayer {
     pub fn from_layer<S : StringCompression>(l : &Layer, idx : &mut Index, ld : &LayerDesc, s : &S) -> TCFResult<TCFLayer> {
         match l {
-            Layer::Characters(c) => Ok(TCFLayer::Characters(s.compress(c))),
+            Layer::Characters(c) => Ok(TCFLayer::Characters(s.compress(c)?)),
             Layer::L1(l) => {
                 if all_ascending(l) {
                     Ok(TCFLayer::L1(TCFIndex::from_vec(&to_delta(l.clone())), true))
@@ -164,10 +166,10 @@ impl TCFLayer {
         }
     }
 
-    pub fn to_layer<S : StringCompression>(self, index : &Index, ld : &LayerDesc, s : &S) -> Layer {
-        match self {
+    pub fn into_layer<S : StringCompression>(self, index : &Index, ld : &LayerDesc, s : &S) -> TCFResult<Layer> {
+        Ok(match self {
             TCFLayer::Characters(c) => {
-                let s = s.decompress(&c).unwrap();
+                let s = s.decompress(&c)?;
                 Layer::Characters(s)
             },
             TCFLayer::L1(l, delta) => {
@@ -193,18 +195,18 @@ impl TCFLayer {
                 Layer::L3(v1.into_iter().zip(v2.into_iter()).zip(v3.into_iter()).map(|((x,y),z)| (x, y, z)).collect())
             },
             TCFLayer::LS(l) => {
-                Layer::LS(l.to_vec(index, ld))
+                Layer::LS(l.to_vec(index, ld)?)
             },
             TCFLayer::L1S(l1, l2, delta) => {
                 let v1 = l1.to_vec();
-                let v2 = l2.to_vec(index, ld);
+                let v2 = l2.to_vec(index, ld)?;
                 let v1 = if delta { from_delta(v1) } else { v1 };
                 Layer::L1S(v1.into_iter().zip(v2.into_iter()).map(|(x,y)| (x, y)).collect())
             },
             TCFLayer::L2S(l1, l2, l3, delta, diff) => {
                 let v1 = l1.to_vec();
                 let v2 = l2.to_vec();
-                let v3 = l3.to_vec(index, ld);
+                let v3 = l3.to_vec(index, ld)?;
                 let v1 = if delta { from_delta(v1) } else { v1 };
                 let v2 = if diff { from_diff(&v1, v2) } else { v2 };
                 Layer::L2S(v1.into_iter().zip(v2.into_iter()).zip(v3.into_iter()).map(|((x,y),z)| (x, y, z)).collect())
@@ -213,17 +215,17 @@ impl TCFLayer {
                 let v1 = l1.to_vec();
                 let v2 = l2.to_vec();
                 let v3 = l3.to_vec();
-                let v4 = l4.to_vec(index, ld);
+                let v4 = l4.to_vec(index, ld)?;
                 let v1 = if delta { from_delta(v1) } else { v1 };
                 let v2 = if diff { from_diff(&v1, v2) } else { v2 };
                 Layer::L3S(v1.into_iter().zip(v2.into_iter()).zip(v3.into_iter()).zip(v4.into_iter()).map(|(((x,y),z),w)| (x, y, z, w)).collect())
             },
             TCFLayer::MetaLayer(l) => Layer::MetaLayer(l)
-        }
+        })
     }
 
-    pub fn into_bytes<C : StringCompression>(self, c : &C) -> Vec<u8> {
-        match self {
+    pub fn into_bytes<C : StringCompression>(self, c : &C) -> TCFResult<Vec<u8>> {
+        Ok(match self {
             TCFLayer::Characters(c) => {
                 let mut d = Vec::new();
                 d.push(0);
@@ -275,7 +277,7 @@ impl TCFLayer {
             TCFLayer::LS(l) => {
                 let mut d = Vec::new();
                 d.push(11);
-                d.extend(l.into_bytes(c));
+                d.extend(l.into_bytes(c)?);
                 d
             }
             TCFLayer::L1S(l1, l2, delta) => {
@@ -286,7 +288,7 @@ impl TCFLayer {
                     d.push(13);
                 }
                 d.extend(l1.into_bytes());
-                d.extend(l2.into_bytes(c));
+                d.extend(l2.into_bytes(c)?);
                 d
             }
             TCFLayer::L2S(l1, l2, l3, delta, diff) => {
@@ -302,7 +304,7 @@ impl TCFLayer {
                 }
                 d.extend(l1.into_bytes());
                 d.extend(l2.into_bytes());
-                d.extend(l3.into_bytes(c));
+                d.extend(l3.into_bytes(c)?);
                 d
             }
             TCFLayer::L3S(l1, l2, l3, l4, delta, diff) => {
@@ -319,19 +321,23 @@ impl TCFLayer {
                 d.extend(l1.into_bytes());
                 d.extend(l2.into_bytes());
                 d.extend(l3.into_bytes());
-                d.extend(l4.into_bytes(c));
+                d.extend(l4.into_bytes(c)?);
                 d
             }
             TCFLayer::MetaLayer(l) => {
                 let mut d = Vec::new();
                 d.push(22);
                 let mut d2 = Vec::new();
+                // Sort the keys so that the output is deterministic
+                let l : Vec<BTreeMap<&String, &Value>> = l.iter()
+                    .map(|m| m.iter().collect())
+                    .collect();
                 into_writer(&l, &mut d2).unwrap();
                 d.extend((d2.len() as u32).to_be_bytes().iter());
                 d.extend(d2);
                 d
             }
-        }
+        })
     }
 
     pub fn from_bytes<S : StringCompression>(bytes : &[u8], offset : usize, 
@@ -623,6 +629,8 @@ impl TCFLayer {
             x => {
                 if x == TCF_EMPTY_LAYER {
                     Ok(ReadLayerResult::Empty)
+                } else if x == TCF_DOC_INDEX {
+                    Ok(ReadLayerResult::Eof)
                 } else {
                     Err(TCFError::InvalidByte)
                 }
@@ -1,26 +1,30 @@
 /// Teanga Compressed Format
-use crate::{Layer, LayerDesc, Document};
-use std::collections::HashMap;
+use crate::{Layer, LayerDesc, Document, doc_hash, unique_id};
+use std::collections::{HashMap, HashSet};
 use ciborium::from_reader;
 use thiserror::Error;
 use crate::{TeangaResult, TeangaError, WriteableCorpus};
-use std::io::{Read, BufRead, BufReader};
+use std::fs::File;
+use std::io::{Read, BufRead, BufReader, Seek, SeekFrom};
+use std::path::Path;
 
 use crate::tcf::TCF_VERSION;
 use crate::tcf::string::StringCompression;
 use crate::tcf::string::SupportedStringCompression;
 use crate::tcf::string::ShocoCompression;
 use crate::tcf::string::read_shoco_model;
+#[cfg(feature = "zstd")]
+use crate::tcf::string::{ZstdCompression, read_zstd_dictionary};
 use crate::tcf::{TCFResult, TCFError};
 use crate::tcf::index::Index;
-use crate::tcf::layer::{TCFLayer, TCF_EMPTY_LAYER};
+use crate::tcf::layer::{TCFLayer, TCF_EMPTY_LAYER, TCF_DOC_INDEX};
 
 
 
 fn bytes_to_layer<S : StringCompression>(bytes : &[u8], idx : &mut Index, 
     layer_desc : &LayerDesc, s : &S) -> TCFResult<(Layer, usize)> {
     let (tcf, len) = TCFLayer::from_bytes(bytes, 0, layer_desc, s)?;
-    Ok((tcf.to_layer(idx, layer_desc, s), len))
+    Ok((tcf.into_layer(idx, layer_desc, s)?, len))
 }
 
 pub enum ReadLayerResult<Layer> {
@@ -29,10 +33,10 @@ pub enum ReadLayerResult<Layer> {
     Eof
 }
 
-fn read_layer<R : BufRead, S : StringCompression>(bytes : &mut R, 
+pub(super) fn read_layer<R : BufRead, S : StringCompression>(bytes : &mut R, 
     idx : &Index, layer_desc : &LayerDesc, s : &S) -> TCFResult<ReadLayerResult<Layer>> {
     match TCFLayer::from_reader(bytes, layer_desc, s)? {
-        ReadLayerResult::Layer(tcf) => Ok(ReadLayerResult::Layer(tcf.to_layer(idx, layer_desc, s))),
+        ReadLayerResult::Layer(tcf) => Ok(ReadLayerResult::Layer(tcf.into_layer(idx, layer_desc, s)?)),
         ReadLayerResult::Empty => Ok(ReadLayerResult::Empty),
         ReadLayerResult::Eof => Ok(ReadLayerResult::Eof)
     }
@@ -138,7 +142,11 @@ pub enum TCFReadError {
     #[error("Not a TCF file")]
     NotTCFFile,
     #[error("Invalid version ({0} > {1})")]
-    InvalidVersion(u16, u16)
+    InvalidVersion(u16, u16),
+    #[error("The TCF file has no document index")]
+    NoDocumentIndex,
+    #[error("Invalid document index")]
+    InvalidDocumentIndex
 }
 
 
@@ -162,6 +170,211 @@ pub fn read_tcf<R: Read, C: WriteableCorpus>(
 
 }
 
+/// Read the documents of a TCF file one at a time, without loading the whole
+/// file into a corpus. The header is read immediately and each call to
+/// `next` decodes a single document
+///
+/// # Arguments
+///
+/// * `input` - The input stream
+///
+/// # Returns
+///
+/// An iterator over the IDs and documents of the file. The IDs are those
+/// that the documents would be given when read into a corpus
+pub fn read_tcf_iter<R : BufRead>(mut input : R) -> Result<TCFDocIter<R>, TCFReadError> {
+    let (meta, string_compression) = read_tcf_header(&mut input)?;
+    Ok(TCFDocIter {
+        input,
+        meta,
+        string_compression,
+        index: Index::new(),
+        ids: HashSet::new(),
+        done: false
+    })
+}
+
+/// An iterator over the documents of a TCF file, created by `read_tcf_iter`
+pub struct TCFDocIter<R : BufRead> {
+    input : R,
+    meta : HashMap<String, LayerDesc>,
+    string_compression : SupportedStringCompression,
+    index : Index,
+    ids : HashSet<String>,
+    done : bool
+}
+
+impl<R : BufRead> TCFDocIter<R> {
+    /// The metadata of the file
+    pub fn meta(&self) -> &HashMap<String, LayerDesc> {
+        &self.meta
+    }
+}
+
+impl<R : BufRead> Iterator for TCFDocIter<R> {
+    type Item = Result<(String, Document), TCFReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match read_tcf_doc(&mut self.input, &self.meta, &self.index, &self.string_compression) {
+            Ok(Some(doc)) => {
+                let id = unique_id(&doc_hash(&doc), |id| self.ids.contains(id));
+                self.ids.insert(id.clone());
+                Some(Ok((id, doc)))
+            },
+            Ok(None) => {
+                self.done = true;
+                None
+            },
+            Err(e) => {
+                self.done = true;
+                Some(Err(e.into()))
+            }
+        }
+    }
+}
+
+/// Random access to the documents of a TCF file by their IDs, using the
+/// document index at the end of the file (see `write_tcf_doc_index`)
+pub struct TCFReader {
+    input : BufReader<File>,
+    meta : HashMap<String, LayerDesc>,
+    string_compression : SupportedStringCompression,
+    index : Index,
+    ids : Vec<String>,
+    offsets : HashMap<String, u64>
+}
+
+impl TCFReader {
+    /// Open a TCF file and read its document index. Files without a document
+    /// index, including all files before version 2, can still be read
+    /// sequentially with `read_tcf` or `read_tcf_iter`
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the TCF file
+    pub fn open<P : AsRef<Path>>(path : P) -> Result<TCFReader, TCFReadError> {
+        let mut input = BufReader::new(File::open(path)?);
+        let mut format_id_bytes = [0u8; 8];
+        input.read_exact(&mut format_id_bytes)?;
+        input.rewind()?;
+        let (meta, string_compression) = read_tcf_header(&mut input)?;
+        if u16::from_be_bytes([format_id_bytes[6], format_id_bytes[7]]) < 2 {
+            return Err(TCFReadError::NoDocumentIndex);
+        }
+        let header_end = input.stream_position()?;
+        let len = input.seek(SeekFrom::End(0))?;
+        if len < header_end + 9 {
+            return Err(TCFReadError::NoDocumentIndex);
+        }
+        input.seek(SeekFrom::End(-8))?;
+        let position = read_u64(&mut input)?;
+        if position < header_end || position > len - 9 {
+            return Err(TCFReadError::NoDocumentIndex);
+        }
+        input.seek(SeekFrom::Start(position))?;
+        let mut marker = [0u8; 1];
+        input.read_exact(&mut marker)?;
+        if marker[0] != TCF_DOC_INDEX {
+            return Err(TCFReadError::NoDocumentIndex);
+        }
+        // The counts are checked against the rest of the index so that a
+        // corrupt file cannot cause a huge allocation. Each entry has at
+        // least a u32 length and a u64 offset
+        let n = read_u32(&mut input)?;
+        if n as u64 * 12 > index_remaining(&mut input, len)? {
+            return Err(TCFReadError::InvalidDocumentIndex);
+        }
+        let mut ids = Vec::with_capacity(n as usize);
+        let mut offsets = HashMap::with_capacity(n as usize);
+        for _ in 0..n {
+            let id_len = read_u32(&mut input)?;
+            if id_len as u64 > index_remaining(&mut input, len)? {
+                return Err(TCFReadError::InvalidDocumentIndex);
+            }
+            let mut id = vec![0u8; id_len as usize];
+            input.read_exact(&mut id)?;
+            let id = String::from_utf8(id)
+                .map_err(|_| TCFReadError::InvalidDocumentIndex)?;
+            let offset = read_u64(&mut input)?;
+            if offset < header_end || offset >= position {
+                return Err(TCFReadError::InvalidDocumentIndex);
+            }
+            offsets.insert(id.clone(), offset);
+            ids.push(id);
+        }
+        let strings_len = read_u64(&mut input)?;
+        let index = Index::from_bytes((&mut input).take(strings_len))
+            .map_err(|_| TCFReadError::InvalidDocumentIndex)?;
+        Ok(TCFReader {
+            input,
+            meta,
+            string_compression,
+            index,
+            ids,
+            offsets
+        })
+    }
+
+    /// The metadata of the file
+    pub fn meta(&self) -> &HashMap<String, LayerDesc> {
+        &self.meta
+    }
+
+    /// The IDs of the documents in the order they occur in the file
+    pub fn ids(&self) -> &[String] {
+        &self.ids
+    }
+
+    /// The number of documents in the file
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Whether the file has no documents
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Read a document by its ID
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the document
+    ///
+    /// # Returns
+    ///
+    /// The document, or `TeangaError::DocumentNotFoundError` if no document
+    /// has this ID
+    pub fn get(&mut self, id : &str) -> Result<Document, TCFReadError> {
+        let offset = *self.offsets.get(id)
+            .ok_or(TCFReadError::TeangaError(TeangaError::DocumentNotFoundError))?;
+        self.input.seek(SeekFrom::Start(offset))?;
+        read_tcf_doc(&mut self.input, &self.meta, &self.index, &self.string_compression)?
+            .ok_or(TCFReadError::InvalidDocumentIndex)
+    }
+}
+
+// The number of bytes between the current position and the position of the
+// document index stored in the last eight bytes of the file
+fn index_remaining<S : Seek>(input : &mut S, len : u64) -> std::io::Result<u64> {
+    Ok((len - 8).saturating_sub(input.stream_position()?))
+}
+
+fn read_u32<R : Read>(input : &mut R) -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_u64<R : Read>(input : &mut R) -> std::io::Result<u64> {
+    let mut buf = [0u8; 8];
+    input.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
 pub fn read_tcf_header<R: Read>(
     input : &mut R) -> Result<(HashMap<String, LayerDesc>, SupportedStringCompression), TCFReadError> {
     let mut format_id_bytes = vec![0u8; 8];
@@ -169,8 +382,9 @@ pub fn read_tcf_header<R: Read>(
     if format_id_bytes[0..6] != *"TEANGA".as_bytes() {
         return Err(TCFReadError::NotTCFFile);
     }
-    if format_id_bytes[6..8] != TCF_VERSION.to_be_bytes() {
-        return Err(TCFReadError::InvalidVersion(u16::from_be_bytes([format_id_bytes[6], format_id_bytes[7]]), TCF_VERSION));
+    let version = u16::from_be_bytes([format_id_bytes[6], format_id_bytes[7]]);
+    if version > TCF_VERSION {
+        return Err(TCFReadError::InvalidVersion(version, TCF_VERSION));
     }
     let mut meta_bytes = vec![0u8; 4];
     input.read_exact(meta_bytes.as_mut_slice())?;
@@ -187,7 +401,17 @@ pub fn read_tcf_header<R: Read>(
         3 => {
             let model = read_shoco_model(input)?;
             crate::tcf::string::SupportedStringCompression::Shoco(model)
-        }
+        },
+        #[cfg(feature = "zstd")]
+        4 => crate::tcf::string::SupportedStringCompression::Zstd(ZstdCompression::new()),
+        #[cfg(feature = "zstd")]
+        5 => {
+            let dictionary = read_zstd_dictionary(input)?;
+            crate::tcf::string::SupportedStringCompression::Zstd(ZstdCompression::with_dictionary(dictionary))
+        },
+        #[cfg(not(feature = "zstd"))]
+        4 | 5 => return Err(TCFReadError::TeangaError(TeangaError::ModelError(
+            "The TCF file uses Zstd string compression, which requires the zstd feature".to_string()))),
         _ => return Err(TCFReadError::TCFError(ReadDocError::TCFError(TCFError::InvalidByte)))
     };
     Ok((meta, string_compression))
@@ -197,7 +421,8 @@ pub fn read_tcf_header<R: Read>(
 mod tests {
     use super::*;
     use crate::{SimpleCorpus, build_layer, LayerType, DataType, Corpus, IntoLayer};
-    use crate::tcf::write::write_tcf;
+    use crate::tcf::write::{write_tcf, write_tcf_with_config};
+    use crate::tcf::TCFConfig;
 
     #[test]
     fn test_read_doc() {
@@ -205,7 +430,7 @@ mod tests {
         build_layer(&mut corpus, "text").add().unwrap();
         build_layer(&mut corpus, "document")
             .layer_type(LayerType::div)
-            .base("characters")
+            .base("text")
             .default(Layer::L1(vec![0]))
             .add().unwrap();
         build_layer(&mut corpus, "url")
@@ -238,18 +463,127 @@ mod tests {
             "Test string".to_string())]).unwrap();
         let mut data : Vec<u8> = Vec::new();
         write_tcf(&mut data, &corpus).unwrap();
-        assert_eq!(data, vec![84, 69, 65, 78, 71, 65, 0, 1, 0, 0, 0, 23, 161, 100, 116, 101, 120, 116, 161, 100, 116, 121, 112, 101, 106, 99, 104, 97, 114, 97, 99, 116, 101, 114, 115, 1, 0, 0, 7, 254, 84, 54, 35, 77, 114, 84]);
+        assert_eq!(data, vec![84, 69, 65, 78, 71, 65, 0, 2, 0, 0, 0, 23, 161, 100, 116, 101, 120, 116, 161, 100, 116, 121, 112, 101, 106, 99, 104, 97, 114, 97, 99, 116, 101, 114, 115, 1, 0, 0, 7, 254, 84, 54, 35, 77, 114, 84, 254, 0, 0, 0, 1, 0, 0, 0, 4, 87, 103, 119, 67, 0, 0, 0, 0, 0, 0, 0, 36, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 46]);
         let mut corpus2 = SimpleCorpus::new();
         read_tcf(&mut data.as_slice(), &mut corpus2).unwrap();
     }
 
+    #[test]
+    fn test_read_tcf_iter() {
+        let mut corpus = SimpleCorpus::new();
+        build_layer(&mut corpus, "text").add().unwrap();
+        build_layer(&mut corpus, "words")
+            .layer_type(LayerType::span)
+            .base("text")
+            .add().unwrap();
+        for text in ["Dia duit", "Conas atá tú?", "Dia duit"] {
+            corpus.build_doc()
+                .layer("text", text).unwrap()
+                .layer("words", vec![(0, 3), (4, 8)]).unwrap()
+                .add().unwrap();
+        }
+        let mut data : Vec<u8> = Vec::new();
+        write_tcf(&mut data, &corpus).unwrap();
+        let iter = read_tcf_iter(data.as_slice()).unwrap();
+        assert_eq!(iter.meta(), corpus.get_meta());
+        let docs : Vec<(String, Document)> = iter.collect::<Result<_, _>>().unwrap();
+        assert_eq!(docs.iter().map(|(id, _)| id.clone()).collect::<Vec<_>>(), corpus.order);
+        for (id, doc) in docs.iter() {
+            assert_eq!(doc, &corpus.get_doc_by_id(id).unwrap());
+        }
+        let mut data : Vec<u8> = Vec::new();
+        write_tcf_with_config(&mut data, &corpus, &TCFConfig::new().with_doc_index(false)).unwrap();
+        let mut truncated = read_tcf_iter(&data[..data.len() - 1]).unwrap();
+        assert!(truncated.next().unwrap().is_ok());
+        assert!(truncated.next().unwrap().is_ok());
+        assert!(truncated.next().unwrap().is_err());
+        assert!(truncated.next().is_none());
+    }
+
+    #[test]
+    fn test_tcf_reader() {
+        let mut corpus = SimpleCorpus::new();
+        build_layer(&mut corpus, "text").add().unwrap();
+        build_layer(&mut corpus, "pos")
+            .layer_type(LayerType::seq)
+            .base("text")
+            .data(DataType::String)
+            .add().unwrap();
+        for text in ["abc", "def", "ghi", "abc"] {
+            corpus.build_doc()
+                .layer("text", text).unwrap()
+                .layer("pos", vec!["NOUN", "VERB", "NOUN"]).unwrap()
+                .add().unwrap();
+        }
+        let file = tempfile::NamedTempFile::new().unwrap();
+        write_tcf(&mut file.reopen().unwrap(), &corpus).unwrap();
+        let mut reader = TCFReader::open(file.path()).unwrap();
+        assert_eq!(reader.ids(), corpus.order.as_slice());
+        assert_eq!(reader.meta(), corpus.get_meta());
+        for id in corpus.order.iter().rev() {
+            assert_eq!(reader.get(id).unwrap(), corpus.get_doc_by_id(id).unwrap());
+        }
+        assert!(matches!(reader.get("nope"),
+            Err(TCFReadError::TeangaError(TeangaError::DocumentNotFoundError))));
+        // Sequential reading stops at the document index
+        let mut corpus2 = SimpleCorpus::new();
+        read_tcf(std::fs::File::open(file.path()).unwrap(), &mut corpus2).unwrap();
+        assert_eq!(corpus, corpus2);
+        // Files without a document index can only be read sequentially
+        let mut data : Vec<u8> = Vec::new();
+        write_tcf_with_config(&mut data, &corpus, &TCFConfig::new().with_doc_index(false)).unwrap();
+        assert_eq!(data[6..8], 1u16.to_be_bytes());
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), &data).unwrap();
+        assert!(matches!(TCFReader::open(file.path()), Err(TCFReadError::NoDocumentIndex)));
+        let mut corpus2 = SimpleCorpus::new();
+        read_tcf(data.as_slice(), &mut corpus2).unwrap();
+        assert_eq!(corpus, corpus2);
+    }
+
+    #[test]
+    fn test_tcf_reader_corrupt_index() {
+        let mut corpus = SimpleCorpus::new();
+        build_layer(&mut corpus, "text").add().unwrap();
+        corpus.add_doc(vec![("text".to_string(), "abc".to_string())]).unwrap();
+        let mut data : Vec<u8> = Vec::new();
+        write_tcf(&mut data, &corpus).unwrap();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        // Counts in a corrupt index are rejected before anything is allocated
+        let position = u64::from_be_bytes(data[data.len() - 8..].try_into().unwrap()) as usize;
+        for count_at in [position + 1, position + 5] {
+            let mut corrupt = data.clone();
+            corrupt[count_at..count_at + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+            std::fs::write(file.path(), &corrupt).unwrap();
+            assert!(matches!(TCFReader::open(file.path()), Err(TCFReadError::InvalidDocumentIndex)));
+        }
+    }
+
+    #[test]
+    fn test_read_header_version() {
+        let mut corpus = SimpleCorpus::new();
+        build_layer(&mut corpus, "text").add().unwrap();
+        corpus.add_doc(vec![("text".to_string(), "Test string".to_string())]).unwrap();
+        let mut data : Vec<u8> = Vec::new();
+        write_tcf(&mut data, &corpus).unwrap();
+        let mut newer = data.clone();
+        newer[6..8].copy_from_slice(&(TCF_VERSION + 1).to_be_bytes());
+        assert!(matches!(read_tcf(newer.as_slice(), &mut SimpleCorpus::new()),
+            Err(TCFReadError::InvalidVersion(found, supported))
+                if found == TCF_VERSION + 1 && supported == TCF_VERSION));
+        let mut not_tcf = data.clone();
+        not_tcf[0] = b'X';
+        assert!(matches!(read_tcf(not_tcf.as_slice(), &mut SimpleCorpus::new()),
+            Err(TCFReadError::NotTCFFile)));
+    }
+
     #[test]
     fn test_serialize_3() {
         let mut corpus = SimpleCorpus::new();
         build_layer(&mut corpus, "text").add().unwrap();
         build_layer(&mut corpus, "document")
             .layer_type(LayerType::div)
-            .base("characters")
+            .base("text")
             .default(Layer::L1(vec![0]))
             .add().unwrap();
         build_layer(&mut corpus, "url")
@@ -264,7 +598,7 @@ mod tests {
             .add().unwrap();
         build_layer(&mut corpus, "words")
             .layer_type(LayerType::span)
-            .base("characters")
+            .base("text")
             .add().unwrap();
         build_layer(&mut corpus, "pos")
             .layer_type(LayerType::seq)
@@ -323,5 +657,40 @@ mod tests {
         //assert_eq!(corpus, corpus2);
      }
 
+    fn deterministic_corpus() -> SimpleCorpus {
+        let mut corpus = SimpleCorpus::new();
+        for name in ["text", "title", "abstract", "body"] {
+            build_layer(&mut corpus, name).add().unwrap();
+        }
+        let mut meta = HashMap::new();
+        for key in ["a", "b", "c", "d", "e", "f"] {
+            meta.insert(key.to_string(), crate::Value::String(key.to_string()));
+        }
+        let mut info = build_layer(&mut corpus, "_info");
+        for (key, value) in meta.iter() {
+            info = info.meta(key, value.clone());
+        }
+        info.add().unwrap();
+        for i in 0..3 {
+            corpus.build_doc()
+                .layer("text", format!("Document number {}", i)).unwrap()
+                .layer("title", format!("Title {}", i)).unwrap()
+                .layer("abstract", "An abstract").unwrap()
+                .layer("body", "The body of the document").unwrap()
+                .layer("_info", meta.clone()).unwrap()
+                .add().unwrap();
+        }
+        corpus
+    }
 
+    #[test]
+    fn test_deterministic_output() {
+        let mut data1 : Vec<u8> = Vec::new();
+        write_tcf(&mut data1, &deterministic_corpus()).unwrap();
+        for _ in 0..5 {
+            let mut data2 : Vec<u8> = Vec::new();
+            write_tcf(&mut data2, &deterministic_corpus()).unwrap();
+            assert_eq!(data1, data2);
+        }
+    }
 }
@@ -0,0 +1,165 @@
+//! Statistics on the size of a TCF file
+use std::collections::HashMap;
+use std::io::{BufRead, Read};
+use itertools::Itertools;
+use crate::{LayerType, TeangaError};
+use crate::tcf::index::Index;
+use crate::tcf::read::{read_layer, read_tcf_header, ReadLayerResult, TCFReadError};
+
+/// Statistics on the size of a TCF file, as returned by `tcf_stats`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TCFStats {
+    /// The number of documents
+    pub documents : usize,
+    /// The size of the header, including the metadata and the string
+    /// compression model
+    pub header_bytes : u64,
+    /// The size of the layers of each layer type
+    pub layer_type_bytes : HashMap<LayerType, u64>,
+    /// The number of strings in the string index
+    pub index_strings : usize,
+    /// The size of the strings in the string index
+    pub index_bytes : u64,
+    /// The size of the document index at the end of the file, or zero if the
+    /// file has no document index
+    pub doc_index_bytes : u64,
+    /// The size of the whole file
+    pub total_bytes : u64,
+    /// The estimated size of the documents without compression, measured as
+    /// the size of their layers serialized as JSON
+    pub uncompressed_bytes : u64
+}
+
+impl TCFStats {
+    /// The ratio of the estimated uncompressed size of the documents to the
+    /// size of the file
+    pub fn compression_ratio(&self) -> f64 {
+        if self.total_bytes == 0 {
+            0.0
+        } else {
+            self.uncompressed_bytes as f64 / self.total_bytes as f64
+        }
+    }
+}
+
+/// Read a TCF file and collect statistics on its size. The file is read
+/// once, decoding each layer
+///
+/// # Arguments
+///
+/// * `input` - The input stream
+///
+/// # Returns
+///
+/// The statistics of the file
+pub fn tcf_stats<R : BufRead>(input : R) -> Result<TCFStats, TCFReadError> {
+    let mut input = CountingReader { input, count: 0 };
+    let (meta, string_compression) = read_tcf_header(&mut input)?;
+    let mut stats = TCFStats {
+        documents: 0,
+        header_bytes: input.count,
+        layer_type_bytes: HashMap::new(),
+        index_strings: 0,
+        index_bytes: 0,
+        doc_index_bytes: 0,
+        total_bytes: 0,
+        uncompressed_bytes: 0
+    };
+    let index = Index::new();
+    let mut docs_end;
+    'docs: loop {
+        docs_end = input.count;
+        for (key, layer_desc) in meta.iter().sorted_by(|a, b| a.0.cmp(b.0)) {
+            let start = input.count;
+            match read_layer(&mut input, &index, layer_desc, &string_compression)
+                .map_err(|e| TCFReadError::TCFError(e.into()))? {
+                ReadLayerResult::Layer(layer) => {
+                    *stats.layer_type_bytes.entry(layer_desc.layer_type.clone()).or_insert(0) += input.count - start;
+                    stats.uncompressed_bytes += (key.len() + serde_json::to_vec(&layer)
+                        .map_err(|e| TeangaError::ModelError(e.to_string()))?.len()) as u64;
+                },
+                ReadLayerResult::Empty => {},
+                ReadLayerResult::Eof => break 'docs
+            }
+        }
+        stats.documents += 1;
+    }
+    std::io::copy(&mut input, &mut std::io::sink())?;
+    stats.total_bytes = input.count;
+    stats.doc_index_bytes = stats.total_bytes - docs_end;
+    stats.index_strings = index.vec().len();
+    stats.index_bytes = index.to_bytes().len() as u64;
+    Ok(stats)
+}
+
+// Counts the bytes read so that the size of each layer is known
+struct CountingReader<R> {
+    input : R,
+    count : u64
+}
+
+impl<R : Read> Read for CountingReader<R> {
+    fn read(&mut self, buf : &mut [u8]) -> std::io::Result<usize> {
+        let n = self.input.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R : BufRead> BufRead for CountingReader<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.input.fill_buf()
+    }
+
+    fn consume(&mut self, amt : usize) {
+        self.count += amt as u64;
+        self.input.consume(amt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SimpleCorpus, Corpus, DataType};
+    use crate::tcf::{write_tcf_with_config, TCFConfig, StringCompressionMethod};
+
+    #[test]
+    fn test_tcf_stats() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").layer_type(LayerType::span).base("text").add().unwrap();
+        corpus.build_layer("pos").layer_type(LayerType::seq).base("words")
+            .data(DataType::String).add().unwrap();
+        for text in ["bhí sé fuar", "tá sé te", "beidh sé fliuch"] {
+            let words : Vec<(u32, u32)> = text.split(' ').scan(0, |start, w| {
+                let span = (*start, *start + w.len() as u32);
+                *start += w.len() as u32 + 1;
+                Some(span)
+            }).collect();
+            corpus.build_doc()
+                .layer("text", text).unwrap()
+                .layer("words", words).unwrap()
+                .layer("pos", vec!["VERB", "PRON", "ADJ"]).unwrap()
+                .add().unwrap();
+        }
+        let config = TCFConfig::new().with_string_compression(StringCompressionMethod::None);
+        let mut data : Vec<u8> = Vec::new();
+        write_tcf_with_config(&mut data, &corpus, &config).unwrap();
+        let stats = tcf_stats(data.as_slice()).unwrap();
+        assert_eq!(stats.documents, 3);
+        assert_eq!(stats.total_bytes, data.len() as u64);
+        assert_eq!(stats.layer_type_bytes.keys().cloned().sorted_by_key(|t| format!("{:?}", t)).collect::<Vec<_>>(),
+            vec![LayerType::characters, LayerType::seq, LayerType::span]);
+        assert_eq!(stats.header_bytes + stats.layer_type_bytes.values().sum::<u64>() + stats.doc_index_bytes,
+            stats.total_bytes);
+        assert_eq!(stats.index_strings, 3);
+        assert_eq!(stats.index_bytes, "VERB\0PRON\0ADJ\0".len() as u64);
+        assert!(stats.doc_index_bytes > 0);
+        assert!(stats.compression_ratio() > 0.0);
+        let mut data : Vec<u8> = Vec::new();
+        write_tcf_with_config(&mut data, &corpus, &config.with_doc_index(false)).unwrap();
+        let stats = tcf_stats(data.as_slice()).unwrap();
+        assert_eq!(stats.doc_index_bytes, 0);
+        assert_eq!(stats.total_bytes, data.len() as u64);
+    }
+}
@@ -4,6 +4,9 @@ use shoco;
 use thiserror::Error;
 use std::io::Write;
 use std::io::Read;
+use itertools::Itertools;
+#[cfg(feature = "zstd")]
+use std::sync::Arc;
 
 use crate::TeangaResult;
 use crate::document::Document;
@@ -13,7 +16,7 @@ use crate::tcf::write::TCFWriteError;
 /// Trait for compressing and decompressing strings
 pub trait StringCompression {
     /// Compress a string
-    fn compress(&self, input: &str) -> Vec<u8>;
+    fn compress(&self, input: &str) -> StringCompressionResult<Vec<u8>>;
     /// Decompress a string
     fn decompress(&self, input: &[u8]) -> StringCompressionResult<String>;
 }
@@ -25,6 +28,9 @@ pub enum StringCompressionError {
     SmazError(#[from] smaz::DecompressError),
     #[error("UTF-8 Error: {0}")]
     Utf8Error(#[from] std::string::FromUtf8Error),
+    #[cfg(feature = "zstd")]
+    #[error("Zstd Error: {0}")]
+    ZstdError(std::io::Error),
 }
 
 /// Result type for string compression
@@ -34,8 +40,8 @@ pub type StringCompressionResult<T> = Result<T, StringCompressionError>;
 pub struct NoCompression;
 
 impl StringCompression for NoCompression {
-    fn compress(&self, input: &str) -> Vec<u8> {
-        input.as_bytes().to_vec()
+    fn compress(&self, input: &str) -> StringCompressionResult<Vec<u8>> {
+        Ok(input.as_bytes().to_vec())
     }
 
     fn decompress(&self, input: &[u8]) -> StringCompressionResult<String> {
@@ -48,8 +54,8 @@ impl StringCompression for NoCompression {
 pub struct SmazCompression;
 
 impl StringCompression for SmazCompression {
-    fn compress(&self, input: &str) -> Vec<u8> {
-        smaz::compress(input.as_bytes())
+    fn compress(&self, input: &str) -> StringCompressionResult<Vec<u8>> {
+        Ok(smaz::compress(input.as_bytes()))
     }
 
     fn decompress(&self, input: &[u8]) -> StringCompressionResult<String> {
@@ -65,8 +71,8 @@ impl StringCompression for SmazCompression {
 pub struct ShocoCompression(shoco::ShocoModel);
 
 impl StringCompression for ShocoCompression {
-    fn compress(&self, input: &str) -> Vec<u8> {
-        shoco::compress(input, &self.0)
+    fn compress(&self, input: &str) -> StringCompressionResult<Vec<u8>> {
+        Ok(shoco::compress(input, &self.0))
     }
 
     fn decompress(&self, input: &[u8]) -> StringCompressionResult<String> {
@@ -81,44 +87,269 @@ impl ShocoCompression {
     }
 
     pub fn from_corpus<'a>(docs : &mut Box<dyn Iterator<Item=TeangaResult<Document>> + 'a>, size : usize) -> Result<ShocoCompression, TCFWriteError> {
-        let mut data = Vec::new();
-        let mut total_data = 0;
-        for doc in docs {
-            if total_data > size {
-                break;
-            }
-            for (_, layer) in doc?.into_iter() {
-                match layer {
-                    Layer::Characters(v) => {
-                        let bytes = v.into_bytes();
-                        total_data += bytes.len();
-                        data.push(bytes);
-                    }
-                    _ => {}
-                }
-            }
-        }
+        ShocoCompression::from_sample(sample_strings(docs, size)?)
+    }
+
+    fn from_sample(data : Vec<Vec<u8>>) -> Result<ShocoCompression, TCFWriteError> {
         let gen_model = shoco::GenShocoModel::from_iter(Box::new(data.into_iter()))
             .generate()?;
         Ok(ShocoCompression(gen_model))
+    }
+}
+
+/// Use the Zstandard compression algorithm, optionally with a dictionary
+/// trained on the corpus so that short strings also compress well
+#[cfg(feature = "zstd")]
+#[derive(Clone)]
+pub struct ZstdCompression {
+    level : i32,
+    dictionary : Option<Arc<ZstdDictionary>>
+}
+
+// A dictionary with the encoder and decoder dictionaries prepared from it,
+// so that they are not prepared again for every string
+#[cfg(feature = "zstd")]
+struct ZstdDictionary {
+    bytes : Vec<u8>,
+    encoder : zstd::dict::EncoderDictionary<'static>,
+    decoder : zstd::dict::DecoderDictionary<'static>
+}
+
+#[cfg(feature = "zstd")]
+impl StringCompression for ZstdCompression {
+    fn compress(&self, input: &str) -> StringCompressionResult<Vec<u8>> {
+        match self.dictionary {
+            Some(ref dictionary) => zstd::bulk::Compressor::with_prepared_dictionary(&dictionary.encoder)
+                .and_then(|mut c| c.compress(input.as_bytes())),
+            None => zstd::bulk::compress(input.as_bytes(), self.level)
+        }.map_err(StringCompressionError::ZstdError)
+    }
+
+    fn decompress(&self, input: &[u8]) -> StringCompressionResult<String> {
+        let mut decoder = match self.dictionary {
+            Some(ref dictionary) => zstd::stream::read::Decoder::with_prepared_dictionary(input, &dictionary.decoder),
+            None => zstd::stream::read::Decoder::with_buffer(input)
+        }.map_err(StringCompressionError::ZstdError)?;
+        let mut bytes = Vec::new();
+        decoder.read_to_end(&mut bytes).map_err(StringCompressionError::ZstdError)?;
+        Ok(String::from_utf8(bytes)?)
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl ZstdCompression {
+    /// Zstandard compression at the default level without a dictionary
+    pub fn new() -> ZstdCompression {
+        ZstdCompression {
+            level: zstd::DEFAULT_COMPRESSION_LEVEL,
+            dictionary: None
+        }
+    }
 
+    /// Zstandard compression at the default level with a dictionary
+    pub fn with_dictionary(dictionary : Vec<u8>) -> ZstdCompression {
+        let level = zstd::DEFAULT_COMPRESSION_LEVEL;
+        ZstdCompression {
+            level,
+            dictionary: Some(Arc::new(ZstdDictionary {
+                encoder: zstd::dict::EncoderDictionary::copy(&dictionary, level),
+                decoder: zstd::dict::DecoderDictionary::copy(&dictionary),
+                bytes: dictionary
+            }))
+        }
+    }
+
+    /// Train a dictionary on a sample of the text of a corpus. If the sample
+    /// is too small to train a dictionary, no dictionary is used
+    ///
+    /// # Arguments
+    ///
+    /// * `docs` - The documents of the corpus
+    /// * `size` - The number of bytes of text to sample
+    pub fn from_corpus<'a>(docs : &mut Box<dyn Iterator<Item=TeangaResult<Document>> + 'a>, size : usize) -> Result<ZstdCompression, TCFWriteError> {
+        let sample = sample_strings(docs, size)?;
+        let total : usize = sample.iter().map(|s| s.len()).sum();
+        let dictionary_size = (total / 10).clamp(256, ZSTD_MAX_DICTIONARY_SIZE);
+        match zstd::dict::from_samples(&sample, dictionary_size) {
+            Ok(dictionary) => Ok(ZstdCompression::with_dictionary(dictionary)),
+            Err(_) => Ok(ZstdCompression::new())
+        }
+    }
+
+    /// The dictionary, if any
+    pub fn dictionary(&self) -> Option<&[u8]> {
+        self.dictionary.as_ref().map(|d| d.bytes.as_slice())
     }
 }
 
-/// Enum for supported string compression methods
+#[cfg(feature = "zstd")]
+impl std::fmt::Debug for ZstdCompression {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ZstdCompression")
+            .field("level", &self.level)
+            .field("dictionary", &self.dictionary())
+            .finish()
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl PartialEq for ZstdCompression {
+    fn eq(&self, other : &Self) -> bool {
+        self.level == other.level && self.dictionary() == other.dictionary()
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl Default for ZstdCompression {
+    fn default() -> Self {
+        ZstdCompression::new()
+    }
+}
+
+/// Write a Zstandard dictionary to a stream
+#[cfg(feature = "zstd")]
+pub fn write_zstd_dictionary<W: Write>(out : &mut W, dictionary : &[u8]) -> std::io::Result<()> {
+    out.write_all(&(dictionary.len() as u32).to_be_bytes())?;
+    out.write_all(dictionary)
+}
+
+/// Read a Zstandard dictionary from a stream
+#[cfg(feature = "zstd")]
+pub fn read_zstd_dictionary<R: Read>(input : &mut R) -> std::io::Result<Vec<u8>> {
+    let mut len_buf = [0; 4];
+    input.read_exact(&mut len_buf)?;
+    let len = u32::from_be_bytes(len_buf) as usize;
+    // The length is not trusted, so the dictionary only grows as bytes are
+    // read and a corrupt length cannot cause a huge allocation
+    let mut dictionary = Vec::new();
+    input.take(len as u64).read_to_end(&mut dictionary)?;
+    if dictionary.len() < len {
+        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof,
+            "Zstandard dictionary is shorter than its length"));
+    }
+    Ok(dictionary)
+}
+
+/// The largest dictionary trained by `ZstdCompression::from_corpus`, which is
+/// the default size used by the `zstd` command line tool
+#[cfg(feature = "zstd")]
+pub const ZSTD_MAX_DICTIONARY_SIZE : usize = 112_640;
+
+/// Train a Shoco model on a sample of strings, e.g., for a language other
+/// than English. Files written with the model store it in their header, so
+/// readers do not need the sample to decompress them
+///
+/// # Arguments
+///
+/// * `samples` - The strings to train on
+/// * `bytes_budget` - The number of bytes of the samples to use. Strings are
+///   taken in order and the last one is truncated to fit the budget
+///
+/// # Returns
+///
+/// The trained model
+pub fn train_shoco_model<'a, I : IntoIterator<Item=&'a str>>(samples : I, bytes_budget : usize) -> Result<ShocoCompression, TCFWriteError> {
+    let mut data = Vec::new();
+    let mut remaining = bytes_budget;
+    for sample in samples {
+        if remaining == 0 {
+            break;
+        }
+        let mut end = sample.len().min(remaining);
+        while !sample.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end > 0 {
+            data.push(sample.as_bytes()[..end].to_vec());
+            remaining -= end;
+        }
+    }
+    ShocoCompression::from_sample(data)
+}
+
+/// Collect the text of the characters layers of documents until the size
+/// of the sample exceeds `size` bytes
+fn sample_strings<'a>(docs : &mut Box<dyn Iterator<Item=TeangaResult<Document>> + 'a>, size : usize) -> Result<Vec<Vec<u8>>, TCFWriteError> {
+    let mut data = Vec::new();
+    let mut total_data = 0;
+    for doc in docs {
+        if total_data > size {
+            break;
+        }
+        let doc = doc?;
+        for key in doc.keys().into_iter().sorted() {
+            if let Some(Layer::Characters(v)) = doc.get(&key) {
+                total_data += v.len();
+                data.push(v.as_bytes().to_vec());
+            }
+        }
+    }
+    Ok(data)
+}
+
+/// Select the string compression method that gives the smallest output for
+/// a sample of the corpus. The size of a generated Shoco model is included
+/// when comparing it with the other methods.
+///
+/// # Arguments
+///
+/// * `docs` - The documents of the corpus
+/// * `size` - The number of bytes of text to sample
+pub fn select_string_compression<'a>(docs : &mut Box<dyn Iterator<Item=TeangaResult<Document>> + 'a>, size : usize) -> Result<SupportedStringCompression, TCFWriteError> {
+    let sample = sample_strings(docs, size)?;
+    let texts : Vec<String> = sample.iter()
+        .map(|b| String::from_utf8_lossy(b).into_owned())
+        .collect();
+    let compressed_size = |c : &SupportedStringCompression| -> StringCompressionResult<usize> {
+        texts.iter().map(|t| c.compress(t).map(|b| b.len())).sum()
+    };
+    let mut best = SupportedStringCompression::None;
+    let mut best_size = compressed_size(&best)?;
+    let generated = if sample.is_empty() {
+        Vec::new()
+    } else {
+        vec![ShocoCompression::from_sample(sample)?]
+    };
+    let candidates = vec![SupportedStringCompression::Smaz,
+        SupportedStringCompression::Shoco(ShocoCompression::default())].into_iter()
+        .chain(generated.into_iter().map(SupportedStringCompression::Shoco));
+    for candidate in candidates {
+        let mut size = compressed_size(&candidate)?;
+        if let SupportedStringCompression::Shoco(ref model) = candidate {
+            if *model != ShocoCompression::default() {
+                let mut model_bytes = Vec::new();
+                write_shoco_model(&mut model_bytes, model)?;
+                size += model_bytes.len();
+            }
+        }
+        if size < best_size {
+            best = candidate;
+            best_size = size;
+        }
+    }
+    Ok(best)
+}
+
+/// Enum for supported string compression methods. More methods are
+/// available with the `zstd` feature, so matches must have a wildcard arm
 #[derive(Debug,Clone)]
+#[non_exhaustive]
 pub enum SupportedStringCompression {
     None,
     Smaz,
     Shoco(ShocoCompression),
+    #[cfg(feature = "zstd")]
+    Zstd(ZstdCompression),
 }
 
 impl StringCompression for SupportedStringCompression {
-    fn compress(&self, input: &str) -> Vec<u8> {
+    fn compress(&self, input: &str) -> StringCompressionResult<Vec<u8>> {
         match self {
             SupportedStringCompression::None => NoCompression.compress(input),
             SupportedStringCompression::Smaz => SmazCompression.compress(input),
             SupportedStringCompression::Shoco(c) => c.compress(input),
+            #[cfg(feature = "zstd")]
+            SupportedStringCompression::Zstd(c) => c.compress(input),
         }
     }
 
@@ -127,6 +358,8 @@ impl StringCompression for SupportedStringCompression {
             SupportedStringCompression::None => NoCompression.decompress(input),
             SupportedStringCompression::Smaz => SmazCompression.decompress(input),
             SupportedStringCompression::Shoco(c) => c.decompress(input),
+            #[cfg(feature = "zstd")]
+            SupportedStringCompression::Zstd(c) => c.decompress(input),
         }
     }
 }
@@ -320,6 +553,20 @@ mod tests {
         assert_eq!(model, model2);
     }
 
+    #[test]
+    fn test_train_shoco_model() {
+        let samples = ["Tá an aimsir go breá inniu", "Beidh sé ag cur báistí amárach",
+            "Bhí sé fuar inné"];
+        let model = train_shoco_model(samples, 40).unwrap();
+        let mut bytes = Vec::new();
+        write_shoco_model(&mut bytes, &model).unwrap();
+        let model2 = read_shoco_model(&mut bytes.as_slice()).unwrap();
+        assert_eq!(model, model2);
+        let compressed = model.compress(samples[1]).unwrap();
+        assert_eq!(model2.decompress(&compressed).unwrap(), samples[1]);
+        assert!(train_shoco_model(samples, 0).is_ok());
+    }
+
     #[test]
     fn test_no_compression() {
         test_compression(StringCompressionMethod::None);
@@ -340,12 +587,53 @@ mod tests {
         test_compression(StringCompressionMethod::GenerateShocoModel(100));
     }
 
+    #[test]
+    fn test_auto_compression() {
+        test_compression(StringCompressionMethod::Auto(1000));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_compression() {
+        test_compression(StringCompressionMethod::Zstd);
+        test_compression(StringCompressionMethod::GenerateZstdDictionary(1000));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_dictionary() {
+        let mut corpus = SimpleCorpus::new();
+        build_layer(&mut corpus, "text").add().unwrap();
+        let places = ["Baile Átha Cliath", "Corcaigh", "Gaillimh", "Luimneach", "Port Láirge", "Doire", "Sligeach", "Cill Chainnigh"];
+        for i in 0..400 {
+            corpus.add_doc(vec![("text".to_string(),
+                format!("Tá an aimsir go breá i {} inniu, agus beidh sí fuar amárach ({})", places[i % places.len()], i))]).unwrap();
+        }
+        let compression = ZstdCompression::from_corpus(&mut corpus.iter_docs(), 100_000).unwrap();
+        assert!(compression.dictionary().is_some());
+        let text = "Tá an aimsir go breá i Sligeach inniu";
+        let compressed = compression.compress(text).unwrap();
+        assert!(compressed.len() < ZstdCompression::new().compress(text).unwrap().len());
+        assert_eq!(compression.decompress(&compressed).unwrap(), text);
+        let mut data : Vec<u8> = Vec::new();
+        write_tcf_with_config(&mut data, &corpus,
+            &TCFConfig::new().with_string_compression(StringCompressionMethod::GenerateZstdDictionary(100_000))).unwrap();
+        let mut corpus2 = SimpleCorpus::new();
+        read_tcf(&mut data.as_slice(), &mut corpus2).unwrap();
+        assert_eq!(corpus, corpus2);
+        let mut dictionary = Vec::new();
+        write_zstd_dictionary(&mut dictionary, compression.dictionary().unwrap()).unwrap();
+        assert_eq!(read_zstd_dictionary(&mut dictionary.as_slice()).unwrap(), compression.dictionary().unwrap());
+        dictionary[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(read_zstd_dictionary(&mut dictionary.as_slice()).is_err());
+    }
+
     fn test_compression(method : StringCompressionMethod) {
         let mut corpus = SimpleCorpus::new();
         build_layer(&mut corpus, "text").add().unwrap();
         build_layer(&mut corpus, "document")
             .layer_type(LayerType::div)
-            .base("characters")
+            .base("text")
             .default(Layer::L1(vec![0]))
             .add().unwrap();
         build_layer(&mut corpus, "url")
@@ -1,5 +1,5 @@
 use crate::{Layer, LayerDesc, Document};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use ciborium::into_writer;
 use std::io::Write;
 use thiserror::Error;
@@ -12,15 +12,19 @@ use crate::tcf::TCFResult;
 use crate::tcf::index::Index;
 use crate::tcf::layer::TCFLayer;
 use crate::tcf::layer::TCF_EMPTY_LAYER;
+use crate::tcf::layer::TCF_DOC_INDEX;
 use crate::tcf::string::StringCompression;
 use crate::tcf::string::ShocoCompression;
 use crate::tcf::string::SupportedStringCompression;
 use crate::tcf::string::write_shoco_model;
+use crate::tcf::string::select_string_compression;
+#[cfg(feature = "zstd")]
+use crate::tcf::string::{ZstdCompression, write_zstd_dictionary};
 
 
 fn layer_to_bytes<C : StringCompression>(layer : &Layer, idx : &mut Index, 
     ld : &LayerDesc, c : &C) -> TCFResult<Vec<u8>> {
-    Ok(TCFLayer::from_layer(layer, idx, ld, c)?.into_bytes(c))
+    TCFLayer::from_layer(layer, idx, ld, c)?.into_bytes(c)
 }
 
 
@@ -61,7 +65,9 @@ pub enum TCFWriteError {
     #[error("IO error: {0}")]
     IOError(#[from] std::io::Error),
     #[error("Teanga error: {0}")]
-    TeangaError(#[from] TeangaError)
+    TeangaError(#[from] TeangaError),
+    #[error("String compression error: {0}")]
+    StringCompressionError(#[from] crate::tcf::string::StringCompressionError)
 }
 
 /// Write the corpus to TCF
@@ -84,16 +90,60 @@ pub fn write_tcf<W : Write, C: Corpus>(
 /// * `config` - The configuration for the TCF
 pub fn write_tcf_with_config<W : Write, C: Corpus>(
     out : &mut W, corpus : &C, config : &TCFConfig) -> Result<(), TCFWriteError> {
-    write_tcf_header(out, corpus.get_meta())?;
-    let string_compression = write_tcf_config(out, &mut corpus.iter_docs(), config)?;
+    let mut out = CountingWriter::new(out);
+    write_tcf_header_with_config(&mut out, corpus.get_meta(), config)?;
+    let string_compression = write_tcf_config(&mut out, &mut corpus.iter_docs(), config)?;
     let mut index = Index::new();
-    for doc in corpus.iter_docs() {
-        write_tcf_doc(out, doc?,
+    let mut offsets = Vec::new();
+    for doc in corpus.iter_doc_ids() {
+        let (id, doc) = doc?;
+        offsets.push((id, out.count));
+        write_tcf_doc(&mut out, doc,
                 &mut index, corpus.get_meta(), &string_compression)?;
     }
+    if config.doc_index {
+        let position = out.count;
+        write_tcf_doc_index(&mut out, &offsets, &index, position)?;
+    }
     Ok(())
 }
 
+/// A writer that counts the bytes written to it. This gives the offsets of
+/// the documents needed by `write_tcf_doc_index` when writing documents one
+/// at a time with `write_tcf_doc`
+pub struct CountingWriter<W : Write> {
+    out : W,
+    count : u64
+}
+
+impl<W : Write> CountingWriter<W> {
+    /// Create a writer that counts the bytes written to `out`
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - The output stream
+    pub fn new(out : W) -> CountingWriter<W> {
+        CountingWriter { out, count: 0 }
+    }
+
+    /// The number of bytes written so far
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+impl<W : Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf : &[u8]) -> std::io::Result<usize> {
+        let n = self.out.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
 /// Write only the TCF header.
 ///
 /// This is used for progressive conversion on the command line
@@ -109,10 +159,30 @@ pub fn write_tcf_with_config<W : Write, C: Corpus>(
 /// to call `write_tcf_doc` for each document
 pub fn write_tcf_header<W : Write>(
     out : &mut W, meta : &HashMap<String, LayerDesc>) -> Result<(Index, Vec<String>), TCFWriteError> {
+    write_tcf_header_with_config(out, meta, &TCFConfig::default())
+}
+
+/// Write only the TCF header, with the version for a configuration. Files
+/// without a document index are marked as version 1, so that they can be
+/// read by readers that do not know version 2
+///
+/// # Arguments
+///
+/// * `out` - The output stream
+/// * `meta` - The metadata of the corpus
+/// * `config` - The configuration for the TCF
+///
+/// # Returns
+///
+/// The index and the keys of the layers in the corpus, as for
+/// `write_tcf_header`
+pub fn write_tcf_header_with_config<W : Write>(
+    out : &mut W, meta : &HashMap<String, LayerDesc>, config : &TCFConfig) -> Result<(Index, Vec<String>), TCFWriteError> {
+    let version = if config.doc_index { TCF_VERSION } else { 1 };
     out.write("TEANGA".as_bytes())?;
-    out.write(TCF_VERSION.to_be_bytes().as_ref())?;
+    out.write(version.to_be_bytes().as_ref())?;
     let mut meta_bytes : Vec<u8> = Vec::new();
-    into_writer(meta, &mut meta_bytes).unwrap();
+    into_writer(&meta.iter().collect::<BTreeMap<_, _>>(), &mut meta_bytes).unwrap();
     out.write((meta_bytes.len() as u32).to_be_bytes().as_ref())?;
     out.write(meta_bytes.as_slice())?;
     let index = Index::new();
@@ -148,6 +218,22 @@ pub fn write_tcf_config<'a, W : Write>(
             let model = ShocoCompression::from_corpus(docs, size)?;
             write_shoco_model(out, &model)?;
             SupportedStringCompression::Shoco(model)
+        },
+        StringCompressionMethod::Auto(size) => {
+            let c = select_string_compression(docs, size)?;
+            write_string_compression(out, &c)?;
+            c
+        },
+        #[cfg(feature = "zstd")]
+        StringCompressionMethod::Zstd => {
+            out.write_all(&[4u8])?;
+            SupportedStringCompression::Zstd(ZstdCompression::new())
+        },
+        #[cfg(feature = "zstd")]
+        StringCompressionMethod::GenerateZstdDictionary(size) => {
+            let c = SupportedStringCompression::Zstd(ZstdCompression::from_corpus(docs, size)?);
+            write_string_compression(out, &c)?;
+            c
         }
     };
     Ok(c)
@@ -165,22 +251,40 @@ pub fn write_tcf_header_compression<W: Write>(
     out.write("TEANGA".as_bytes())?;
     out.write(TCF_VERSION.to_be_bytes().as_ref())?;
     let mut meta_bytes : Vec<u8> = Vec::new();
-    into_writer(meta, &mut meta_bytes).unwrap();
+    into_writer(&meta.iter().collect::<BTreeMap<_, _>>(), &mut meta_bytes).unwrap();
     out.write((meta_bytes.len() as u32).to_be_bytes().as_ref())?;
     out.write(meta_bytes.as_slice())?;
+    write_string_compression(out, string_compression)
+}
+
+// Write the byte identifying the string compression and, if needed, the model
+fn write_string_compression<W: Write>(
+    out : &mut W, string_compression : &SupportedStringCompression) -> Result<(), TCFWriteError> {
     match string_compression {
         SupportedStringCompression::None => {
-            out.write(&[0u8])?;
+            out.write_all(&[0u8])?;
         },
         SupportedStringCompression::Smaz => {
-            out.write(&[1u8])?;
+            out.write_all(&[1u8])?;
         },
         SupportedStringCompression::Shoco(model) => {
             if *model == ShocoCompression::default() {
-                out.write(&[2u8])?;
+                out.write_all(&[2u8])?;
             } else {
-                out.write(&[3u8])?;
-                write_shoco_model(out, &model)?;
+                out.write_all(&[3u8])?;
+                write_shoco_model(out, model)?;
+            }
+        },
+        #[cfg(feature = "zstd")]
+        SupportedStringCompression::Zstd(c) => {
+            match c.dictionary() {
+                Some(dictionary) => {
+                    out.write_all(&[5u8])?;
+                    write_zstd_dictionary(out, dictionary)?;
+                },
+                None => {
+                    out.write_all(&[4u8])?;
+                }
             }
         }
     }
@@ -188,6 +292,39 @@ pub fn write_tcf_header_compression<W: Write>(
 }
 
 
+/// Write the document index, which allows documents to be read by ID with
+/// `TCFReader`. This should be called after the last document has been
+/// written with `write_tcf_doc`
+///
+/// The index consists of the `TCF_DOC_INDEX` byte, the number of documents,
+/// the ID and byte offset of each document, the strings of the string index
+/// and finally the byte offset of the index itself, so that it can be found
+/// from the end of the file
+///
+/// # Arguments
+///
+/// * `out` - The output stream
+/// * `offsets` - The ID of each document and its byte offset from the start
+///   of the file
+/// * `index` - The string index after writing the last document
+/// * `position` - The byte offset from the start of the file at which the
+///   document index is written
+pub fn write_tcf_doc_index<W : Write>(out : &mut W, offsets : &[(String, u64)],
+    index : &Index, position : u64) -> Result<(), TCFWriteError> {
+    out.write_all(&[TCF_DOC_INDEX])?;
+    out.write_all(&(offsets.len() as u32).to_be_bytes())?;
+    for (id, offset) in offsets {
+        out.write_all(&(id.len() as u32).to_be_bytes())?;
+        out.write_all(id.as_bytes())?;
+        out.write_all(&offset.to_be_bytes())?;
+    }
+    let strings = index.to_bytes();
+    out.write_all(&(strings.len() as u64).to_be_bytes())?;
+    out.write_all(&strings)?;
+    out.write_all(&position.to_be_bytes())?;
+    Ok(())
+}
+
 /// Write a single document as TCF.
 ///
 /// This should be called after `write_tcf_header` to write the document
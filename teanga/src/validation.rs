@@ -0,0 +1,193 @@
+//! Validation of the consistency of a corpus
+//!
+//! This checks a corpus against its metadata, so that errors in a corpus
+//! are reported when it is loaded rather than when a layer is used.
+use std::collections::HashMap;
+use itertools::Itertools;
+use thiserror::Error;
+use crate::{Corpus, DataType, Document, Layer, LayerDesc, LayerGraph, LayerType, TeangaData};
+
+/// An inconsistency found by `Corpus::validate`
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("{}Layer {layer}: {reason}", .document.as_ref().map(|d| format!("Document {}: ", d)).unwrap_or_default())]
+pub struct ValidationError {
+    /// The document with the error, or `None` for errors in the metadata
+    pub document: Option<String>,
+    /// The layer with the error
+    pub layer: String,
+    /// A description of the error
+    pub reason: String
+}
+
+/// Validate a corpus, see `Corpus::validate`
+pub(crate) fn validate<C : Corpus + ?Sized>(corpus : &C) -> Vec<ValidationError> {
+    let meta = corpus.get_meta();
+    let mut errors = Vec::new();
+    let meta_error = |layer : &str, reason : String| ValidationError {
+        document: None,
+        layer: layer.to_string(),
+        reason
+    };
+    for name in meta.keys().sorted() {
+        let layer_desc = &meta[name];
+        for (kind, referenced) in [("base", &layer_desc.base), ("target", &layer_desc.target)] {
+            if let Some(referenced) = referenced {
+                if !referenced.is_empty() && !meta.contains_key(referenced) {
+                    errors.push(meta_error(name,
+                        format!("The {} layer {} is not declared", kind, referenced)));
+                }
+            }
+        }
+    }
+    if let Err(e) = LayerGraph::new(meta).topo_order() {
+        errors.push(meta_error("", e.to_string()));
+    }
+    for id in corpus.get_order() {
+        match corpus.get_doc_by_id(id) {
+            Ok(doc) => validate_doc(id, &doc, meta, &mut errors),
+            Err(e) => errors.push(ValidationError {
+                document: Some(id.clone()),
+                layer: String::new(),
+                reason: format!("The document could not be read: {}", e)
+            })
+        }
+    }
+    errors
+}
+
+fn validate_doc(id : &str, doc : &Document, meta : &HashMap<String, LayerDesc>,
+    errors : &mut Vec<ValidationError>) {
+    let mut error = |layer : &str, reason : String| errors.push(ValidationError {
+        document: Some(id.to_string()),
+        layer: layer.to_string(),
+        reason
+    });
+    let mut checked = Vec::new();
+    for name in doc.keys().into_iter().sorted() {
+        let Some(layer_desc) = meta.get(&name) else {
+            error(&name, "The layer is not declared".to_string());
+            continue;
+        };
+        let layer = &doc[name.as_str()];
+        let valid_shape = match (&layer_desc.layer_type, layer) {
+            (_, Layer::MetaLayer(_)) => continue,
+            (LayerType::characters, Layer::Characters(_)) => true,
+            (LayerType::seq, Layer::L1(_) | Layer::LS(_) | Layer::L1S(_)) => true,
+            (LayerType::div | LayerType::element, Layer::L1(_) | Layer::L2(_) | Layer::L1S(_) | Layer::L2S(_)) => true,
+            (LayerType::span, Layer::L2(_) | Layer::L3(_) | Layer::L2S(_) | Layer::L3S(_)) => true,
+            _ => false
+        };
+        if !valid_shape {
+            error(&name, format!("The annotations do not have the form of a {} layer", layer_desc.layer_type));
+            continue;
+        }
+        checked.push(name.clone());
+        let Some(base) = layer_desc.base.as_ref().filter(|b| !b.is_empty()) else {
+            continue;
+        };
+        let Some(base_len) = doc.get(base).map(|l| l.len()) else {
+            error(&name, format!("The base layer {} is not in the document", base));
+            continue;
+        };
+        let out_of_range = match (&layer_desc.layer_type, layer) {
+            (LayerType::seq, layer) => (layer.len() != base_len).then(||
+                format!("The layer has {} values but the base layer has {} elements", layer.len(), base_len)),
+            (LayerType::span, _) => {
+                let spans : Vec<(u32, u32)> = match layer {
+                    Layer::L2(v) => v.clone(),
+                    Layer::L3(v) => v.iter().map(|a| (a.0, a.1)).collect(),
+                    Layer::L2S(v) => v.iter().map(|a| (a.0, a.1)).collect(),
+                    Layer::L3S(v) => v.iter().map(|a| (a.0, a.1)).collect(),
+                    _ => Vec::new()
+                };
+                spans.iter().find(|(s, e)| s > e || *e as usize > base_len).map(|(s, e)|
+                    format!("The span ({}, {}) is outside the base layer of length {}", s, e, base_len))
+            },
+            (layer_type, _) => {
+                let indexes : Vec<u32> = match layer {
+                    Layer::L1(v) => v.clone(),
+                    Layer::L2(v) => v.iter().map(|a| a.0).collect(),
+                    Layer::L1S(v) => v.iter().map(|a| a.0).collect(),
+                    Layer::L2S(v) => v.iter().map(|a| a.0).collect(),
+                    _ => Vec::new()
+                };
+                // A div may start at the end of the base layer if its last
+                // segment is empty
+                let max = if *layer_type == LayerType::div { base_len + 1 } else { base_len };
+                indexes.iter().find(|i| **i as usize >= max).map(|i|
+                    format!("The index {} is outside the base layer of length {}", i, base_len))
+            }
+        };
+        if let Some(reason) = out_of_range {
+            error(&name, reason);
+        }
+        if let Some(DataType::Enum(values)) = &layer_desc.data {
+            for data in layer.data(layer_desc) {
+                if let TeangaData::String(s) = data {
+                    if !values.contains(&s) {
+                        error(&name, format!("The value {} is not one of {}", s, values.join(", ")));
+                    }
+                }
+            }
+        }
+    }
+    // Only check the links of layers with the right form, as reading the
+    // data of other layers fails
+    let doc = Document {
+        content: checked.into_iter().map(|name| (name.clone(), doc[name.as_str()].clone())).collect()
+    };
+    if let Err(link_errors) = doc.validate_links(meta) {
+        for e in link_errors {
+            error(&e.layer.clone(), e.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::SimpleCorpus;
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut corpus = SimpleCorpus::new();
+        corpus.build_layer("text").add().unwrap();
+        corpus.build_layer("words").base("text").layer_type(LayerType::span).add().unwrap();
+        corpus.build_layer("pos").base("words").layer_type(LayerType::seq)
+            .data(DataType::Enum(vec!["NOUN".to_string(), "VERB".to_string()])).add().unwrap();
+        corpus.build_layer("head").base("words").layer_type(LayerType::seq)
+            .data(DataType::Link).add().unwrap();
+        let id = corpus.build_doc().layer("text", "Rith sé").unwrap()
+            .layer("words", vec![(0, 4), (5, 8)]).unwrap()
+            .layer("pos", vec!["VERB", "PRON"]).unwrap()
+            .layer("head", vec![0, 0]).unwrap()
+            .add().unwrap();
+        assert_eq!(corpus.validate(), vec![ValidationError {
+            document: Some(id.clone()),
+            layer: "pos".to_string(),
+            reason: "The value PRON is not one of NOUN, VERB".to_string()
+        }]);
+        corpus.update_doc(&id, vec![
+            ("words".to_string(), Layer::L2(vec![(0, 4), (5, 9)])),
+            ("pos".to_string(), Layer::LS(vec!["VERB".to_string(), "NOUN".to_string()])),
+            ("head".to_string(), Layer::L1(vec![0, 2]))]).unwrap();
+        let errors = corpus.validate();
+        assert_eq!(errors.iter().map(|e| e.layer.as_str()).collect::<Vec<_>>(), vec!["words", "head"]);
+        assert_eq!(errors[0].to_string(), format!(
+            "Document {}: Layer words: The span (5, 9) is outside the base layer of length 8", id));
+    }
+
+    #[test]
+    fn test_validate_meta() {
+        let mut meta = HashMap::new();
+        meta.insert("a".to_string(), LayerDesc { base: Some("b".to_string()), ..LayerDesc::default() });
+        meta.insert("b".to_string(), LayerDesc { base: Some("a".to_string()), ..LayerDesc::default() });
+        meta.insert("c".to_string(), LayerDesc { target: Some("d".to_string()), ..LayerDesc::default() });
+        // set_meta rejects this metadata, so the corpus is created directly
+        let corpus = SimpleCorpus { meta, order: Vec::new(), content: HashMap::new() };
+        let errors = corpus.validate();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].layer, "c");
+        assert!(errors[1].reason.contains("cyclic"));
+    }
+}